@@ -1,13 +1,18 @@
 use std::error::Error;
+use std::io::{BufRead, BufReader};
 use reqwest::blocking::Client;
+use reqwest::blocking::multipart::{Form, Part};
 use reqwest::header;
 use reqwest::header::{HeaderMap, HeaderValue};
-use common::http::payloads::{CreatePackagePatchPayload, CreatePackagePayload, PackageRebuildPayload};
-use common::http::responses::{PackagePatchResponse, PackageResponse, SuccessResponse, WorkerResponse};
+use common::http::payloads::{CreatePackagePatchPayload, CreatePackagePayload, PackageRebuildPayload, RepositoryCheckPayload, UpdatePackagePayload};
+use common::models::{PackageRequirements, PackageSource};
+use common::http::responses::{BuildResponse, PackagePatchResponse, PackageResponse, PackageVersionResponse, RepositoryCheckResponse, SuccessResponse, WorkerResponse};
 use anyhow::{anyhow, Result};
 
 pub struct Api {
     client: Client,
+    /// Client without timeout for long-lived streams
+    stream_client: Client,
     host: String,
 }
 
@@ -18,11 +23,16 @@ impl Api {
         headers.insert(header::AUTHORIZATION, HeaderValue::from_str(api_key.as_str())?);
 
         let client = Client::builder()
+            .default_headers(headers.clone())
+            .build()?;
+        let stream_client = Client::builder()
             .default_headers(headers)
+            .timeout(None)
             .build()?;
 
         Ok(Api {
             client,
+            stream_client,
             host
         })
     }
@@ -58,7 +68,7 @@ impl Api {
         )
     }
 
-    pub fn create_package(&self, name: String, run_before: Option<String>) -> Result<PackageResponse>
+    pub fn create_package(&self, name: String, run_before: Option<String>, keep_versions: Option<i32>, source: PackageSource, requirements: PackageRequirements) -> Result<PackageResponse>
     {
         Ok(
             self.client
@@ -66,17 +76,54 @@ impl Api {
                 .json(&CreatePackagePayload {
                     name,
                     run_before,
+                    keep_versions,
+                    source,
+                    requirements,
+                    build_env: Default::default(),
+                    limits: Default::default(),
                 })
                 .send()?
                 .json()?
         )
     }
 
-    pub fn delete_package(&self, id: i32) -> Result<SuccessResponse>
+    pub fn upload_package(&self, name: String, run_before: Option<String>, archive: Vec<u8>) -> Result<PackageResponse>
+    {
+        let mut form = Form::new()
+            .text("name", name.clone())
+            .part("file", Part::bytes(archive).file_name(format!("{}.tar.gz", name)));
+        if let Some(run_before) = run_before {
+            form = form.text("run_before", run_before);
+        }
+
+        Ok(
+            self.client
+                .post(format!("{}/api/packages/upload", self.host))
+                .multipart(form)
+                .send()?
+                .error_for_status()?
+                .json()?
+        )
+    }
+
+    pub fn update_package(&self, id: i32, payload: &UpdatePackagePayload) -> Result<PackageResponse>
+    {
+        Ok(
+            self.client
+                .patch(format!("{}/api/packages/{}", self.host, id))
+                .json(payload)
+                .send()?
+                .error_for_status()?
+                .json()?
+        )
+    }
+
+    pub fn delete_package(&self, id: i32, keep_files: bool) -> Result<SuccessResponse>
     {
         Ok(
             self.client
                 .delete(format!("{}/api/packages/{}", self.host, id))
+                .query(&[("keep_files", keep_files)])
                 .send()?
                 .json()?
         )
@@ -104,6 +151,19 @@ impl Api {
         Ok(response)
     }
 
+    /// Checks the repository, fixing it can take a while on large repositories hence the client without timeout.
+    pub fn check_repository(&self, fix: bool) -> Result<RepositoryCheckResponse>
+    {
+        Ok(
+            self.stream_client
+                .post(format!("{}/api/repository/check", self.host))
+                .json(&RepositoryCheckPayload { fix: Some(fix) })
+                .send()?
+                .error_for_status()?
+                .json()?
+        )
+    }
+
     pub fn get_logs(&self, id: i32) -> Result<String>
     {
         let response: String = self.client
@@ -114,6 +174,39 @@ impl Api {
         Ok(response)
     }
 
+    /// Follows the logs of the running build of a package, calling the closure with each received chunk
+    /// until the build finishes.
+    pub fn follow_logs(&self, id: i32, mut on_content: impl FnMut(&str)) -> Result<()>
+    {
+        let response = self.stream_client
+            .get(format!("{}/api/packages/{}/logs/stream", self.host, id))
+            .send()?
+            .error_for_status()?;
+
+        let mut reader = BufReader::new(response);
+        let mut line = String::new();
+        let mut event = None;
+        let mut data: Vec<String> = Vec::new();
+        while reader.read_line(&mut line)? > 0 {
+            let field = line.strip_suffix('\n').unwrap_or(&line);
+            if field.is_empty() {
+                if event.as_deref() == Some("end") {
+                    break;
+                }
+                on_content(&data.join("\n"));
+                event = None;
+                data.clear();
+            } else if let Some(value) = field.strip_prefix("event: ") {
+                event = Some(value.to_string());
+            } else if let Some(value) = field.strip_prefix("data: ") {
+                data.push(value.to_string());
+            }
+            line.clear();
+        }
+
+        Ok(())
+    }
+
     pub fn get_workers(&self) -> Result<Vec<WorkerResponse>> {
         let response: Vec<WorkerResponse> = self.client.get(format!("{}/api/workers", self.host))
             .send()?
@@ -162,6 +255,60 @@ impl Api {
         )
     }
 
+    pub fn get_builds(&self, package_id: i32, limit: Option<i64>) -> Result<Vec<BuildResponse>>
+    {
+        let mut request = self.client
+            .get(format!("{}/api/packages/{}/builds", self.host, package_id));
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+
+        Ok(request.send()?.json()?)
+    }
+
+    pub fn get_build_logs(&self, package_id: i32, id: i32) -> Result<String>
+    {
+        Ok(
+            self.client
+                .get(format!("{}/api/packages/{}/builds/{}/logs", self.host, package_id, id))
+                .send()?
+                .error_for_status()?
+                .text()?
+        )
+    }
+
+    pub fn get_package_versions(&self, package_id: i32) -> Result<Vec<PackageVersionResponse>>
+    {
+        Ok(
+            self.client
+                .get(format!("{}/api/packages/{}/versions", self.host, package_id))
+                .send()?
+                .json()?
+        )
+    }
+
+    pub fn rollback_package(&self, package_id: i32, version_id: i32) -> Result<PackageResponse>
+    {
+        Ok(
+            self.client
+                .post(format!("{}/api/packages/{}/versions/{}/rollback", self.host, package_id, version_id))
+                .send()?
+                .error_for_status()?
+                .json()?
+        )
+    }
+
+    pub fn cancel_package(&self, package_id: i32) -> Result<PackageResponse>
+    {
+        Ok(
+            self.client
+                .post(format!("{}/api/packages/{}/cancel", self.host, package_id))
+                .send()?
+                .error_for_status()?
+                .json()?
+        )
+    }
+
     pub fn webhook_trigger_package(&self) -> Result<SuccessResponse, Box<dyn Error>>
     {
         let response: SuccessResponse = self.client.post(format!("{}/api/webhooks/trigger", self.host))
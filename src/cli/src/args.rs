@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -25,7 +26,7 @@ pub enum Commands {
         #[command(subcommand)]
         command: WorkerCommands,
     },
-    /// Packages related commands. list, get, add, remove, rebuild.
+    /// Packages related commands. list, get, add, upload, update, remove, rebuild, versions, rollback, cancel.
     Packages {
         #[command(subcommand)]
         command: PackageCommands
@@ -35,9 +36,22 @@ pub enum Commands {
         #[command(subcommand)]
         command: PatchCommands
     },
+    /// Build history related commands. list, logs.
+    Builds {
+        #[command(subcommand)]
+        command: BuildCommands
+    },
     /// <package> Fetch the logs for the given package.
     Logs {
         package: String,
+        /// Follow the logs of the running build until it finishes
+        #[clap(long, short, action)]
+        follow: bool,
+    },
+    /// Repository related commands. check.
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommands
     },
     /// Webhooks related commands. trigger.
     Webhooks {
@@ -77,12 +91,50 @@ pub enum PackageCommands {
     /// Add a new package
     Add {
         name: Option<String>,
-        run_before: Option<String>
+        run_before: Option<String>,
+        /// Amount of versions to keep in the repository, defaults to the server setting
+        #[clap(long)]
+        keep_versions: Option<i32>,
+        /// Build from the given git repository instead of AUR
+        #[clap(long, conflicts_with = "local")]
+        git: Option<String>,
+        /// Branch, tag or commit to checkout from the git repository
+        #[clap(long, requires = "git")]
+        git_ref: Option<String>,
+        /// Directory of the PKGBUILD inside the git repository
+        #[clap(long, requires = "git")]
+        git_subdirectory: Option<String>,
+        /// Build from the given directory on the worker instead of AUR
+        #[clap(long)]
+        local: Option<String>,
+        #[command(flatten)]
+        requirements: RequirementOptions,
+    },
+
+    /// Upload a directory containing a PKGBUILD and build the package from it
+    Upload {
+        /// Directory containing the PKGBUILD
+        path: PathBuf,
+        /// Name of the package. Defaults to the name of the directory
+        #[clap(long)]
+        name: Option<String>,
+        #[clap(long)]
+        run_before: Option<String>,
+    },
+
+    /// Update the settings of a package
+    Update {
+        name: String,
+        #[command(flatten)]
+        options: PackageUpdateOptions,
     },
 
-    /// Remove a package
+    /// Remove a package along with its files from the repository
     Remove {
-        name: String
+        name: String,
+        /// Keep the package files and uploaded source on disk, only removing the package from the repository database
+        #[clap(long, action)]
+        keep_files: bool,
     },
 
     /// package1 package2 [...] Rebuild specified packages, if no specified packages rebuild all.
@@ -91,6 +143,130 @@ pub enum PackageCommands {
         #[clap(long, short, action)]
         force: bool
     },
+
+    /// List the versions of a package retained in the repository
+    Versions {
+        name: String,
+    },
+
+    /// Point the repository back to a retained version of a package
+    Rollback {
+        name: String,
+        version: String,
+    },
+
+    /// Cancel the running build of a package
+    Cancel {
+        name: String,
+    },
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct PackageUpdateOptions {
+    #[clap(long)]
+    pub run_before: Option<String>,
+    /// Amount of versions to keep in the repository
+    #[clap(long)]
+    pub keep_versions: Option<i32>,
+    /// Packages with a higher priority are built first
+    #[clap(long)]
+    pub priority: Option<i32>,
+    /// Rebuild the package every given amount of seconds, 0 to only build on demand
+    #[clap(long, conflicts_with = "clear_rebuild_interval")]
+    pub rebuild_interval: Option<i64>,
+    /// Use the server rebuild interval again
+    #[clap(long, action)]
+    pub clear_rebuild_interval: bool,
+    /// Cron expression with seconds, e.g. "0 0 3 * * *" to rebuild every night at 3
+    #[clap(long, conflicts_with = "clear_schedule")]
+    pub schedule: Option<String>,
+    /// Remove the rebuild schedule
+    #[clap(long, action)]
+    pub clear_schedule: bool,
+    /// Cancel builds of the package running for longer than the given amount of seconds, 0 to never cancel them
+    #[clap(long, conflicts_with = "clear_build_timeout")]
+    pub build_timeout: Option<i64>,
+    /// Use the server build timeout again
+    #[clap(long, action)]
+    pub clear_build_timeout: bool,
+    /// Cache C/C++ and Rust compilations across builds of the package
+    #[clap(long)]
+    pub compiler_cache: Option<bool>,
+    /// Keep network access during build() and package() on workers isolating builds from the network
+    #[clap(long)]
+    pub allow_network: Option<bool>,
+    /// Pause or resume builds of the package
+    #[clap(long)]
+    pub enabled: Option<bool>,
+    #[command(flatten)]
+    pub requirements: RequirementOptions,
+    #[command(flatten)]
+    pub build_env: Box<BuildEnvOptions>,
+    #[command(flatten)]
+    pub limits: Box<LimitOptions>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct LimitOptions {
+    /// Amount of CPUs the builds may use, e.g. 2.5
+    #[clap(long)]
+    pub limit_cpus: Option<f64>,
+    /// Memory in MiB the builds may use
+    #[clap(long)]
+    pub limit_memory: Option<u64>,
+    /// Amount of processes the builds may run at once
+    #[clap(long)]
+    pub limit_pids: Option<u64>,
+    /// Disk space in GiB the builds may use
+    #[clap(long)]
+    pub limit_disk: Option<u64>,
+    /// Use the limits of the workers again before applying the other options
+    #[clap(long, action)]
+    pub clear_limits: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct BuildEnvOptions {
+    /// MAKEFLAGS of the builds, e.g. "-j8"
+    #[clap(long)]
+    pub makeflags: Option<String>,
+    /// CFLAGS of the builds
+    #[clap(long)]
+    pub cflags: Option<String>,
+    /// Extension of the built packages, e.g. ".pkg.tar.xz"
+    #[clap(long)]
+    pub pkgext: Option<String>,
+    /// Comma separated entries appended to the makepkg OPTIONS, e.g. "!debug,!lto"
+    #[clap(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub makepkg_options: Option<Vec<String>>,
+    /// Extra environment variable of the builds as KEY=VALUE, can be repeated
+    #[clap(long, value_parser = parse_env_var)]
+    pub env: Vec<(String, String)>,
+    /// Remove the build environment of the package before applying the other options
+    #[clap(long, action)]
+    pub clear_build_env: bool,
+}
+
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    value.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or(format!("Expected KEY=VALUE, got '{}'", value))
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct RequirementOptions {
+    /// Architecture to build the package for, defaults to the first architecture of the server
+    #[clap(long)]
+    pub arch: Option<String>,
+    /// Minimum amount of CPUs of the worker building the package
+    #[clap(long)]
+    pub min_cpus: Option<usize>,
+    /// Minimum disk space in GiB of the worker building the package
+    #[clap(long)]
+    pub min_disk: Option<u64>,
+    /// Comma separated tags the worker building the package must have
+    #[clap(long, value_delimiter = ',')]
+    pub tags: Option<Vec<String>>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -114,6 +290,34 @@ pub enum PatchCommands {
     }
 }
 
+#[derive(Subcommand, Debug)]
+pub enum BuildCommands {
+    /// List the build history of a package, most recent first
+    List {
+        package_name: String,
+        /// Only show the given amount of builds
+        #[clap(long, short)]
+        limit: Option<i64>,
+    },
+
+    /// Fetch the logs of a specific build of a package
+    Logs {
+        package_name: String,
+        id: i32
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommands {
+    /// Check the repository files, signatures and databases against the packages
+    Check {
+        /// Repair the repository: delete orphan files and stale signatures, sign packages, rebuild the databases
+        /// and queue the packages with missing files for rebuild
+        #[clap(long, action)]
+        fix: bool,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum WebhookCommands {
     /// Manually trigger a webhook
@@ -1,14 +1,17 @@
 use crate::api::Api;
+use crate::args::{BuildEnvOptions, LimitOptions, PackageUpdateOptions, RequirementOptions};
 use crate::profile::{Profile, ProfileConfig};
-use crate::utils::{get_color_from_package_status, get_color_from_worker_status};
+use crate::utils::{create_source_archive, get_color_from_build_status, get_color_from_package_status, get_color_from_worker_status};
 use chrono::Local;
 use cli_table::{Cell, CellStruct, Style, Table};
 use colored::Colorize;
-use common::http::payloads::CreatePackagePatchPayload;
-use common::models::PackageStatus;
+use common::http::payloads::{CreatePackagePatchPayload, UpdatePackagePayload};
+use common::models::{BuildEnvironment, PackageRequirements, PackageSource, PackageStatus, ResourceLimits};
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::{Confirm, Input};
 use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::path::Path;
 
 macro_rules! try_get_package_from_name {
     ($api:expr, $package:expr) => {
@@ -40,6 +43,9 @@ pub fn workers_list(api: &Api) {
                 .unwrap_or(&"None".to_string())
                 .as_str()
                 .cell(),
+            worker.labels.arch.as_str().cell(),
+            worker.labels.cpus.cell(),
+            worker.labels.tags.join(", ").cell(),
         ]);
     }
     println!(
@@ -49,6 +55,9 @@ pub fn workers_list(api: &Api) {
                 "ID".cell().bold(true),
                 "Status".cell().bold(true),
                 "Current Job".cell().bold(true),
+                "Arch".cell().bold(true),
+                "CPUs".cell().bold(true),
+                "Tags".cell().bold(true),
             ])
             .display()
             .unwrap()
@@ -139,6 +148,7 @@ pub fn packages_get(api: &Api, name: &String) {
 
     println!("ID: {}", package.id);
     println!("Name: {}", package.name);
+    println!("Source: {}", package.source);
     println!("Run Before Command: {:?}", package.run_before);
     println!("Status: {}", package.status.to_string());
     println!(
@@ -153,10 +163,81 @@ pub fn packages_get(api: &Api, name: &String) {
     );
     println!("Files: {:?}", package.files);
     println!("Last Built Version {:?}", package.last_built_version);
+    println!("Upstream Version {:?}", package.upstream_version);
     println!("Last Error {:?}", package.last_error);
+    println!(
+        "Retries {} (next {})",
+        package.retry_count,
+        package
+            .next_retry
+            .map(|dt| dt
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string())
+            .unwrap_or("None".to_string())
+    );
+    println!("Keep Versions {:?}", package.keep_versions);
+    println!("Priority {}", package.priority);
+    println!("Rebuild Interval {:?}", package.rebuild_interval);
+    println!("Schedule {:?}", package.schedule);
+    println!("Build Timeout {:?}", package.build_timeout);
+    println!("Compiler Cache {}", package.compiler_cache);
+    println!("Allow Network {}", package.allow_network);
+    println!("Implicit {}", package.implicit);
+    println!("Required By {:?}", package.required_by);
+    println!("Enabled {}", package.enabled);
+    println!("Dependencies {:?}", package.dependencies);
+    println!("Requirements {:?}", package.requirements);
+    println!("Build Environment {:?}", package.build_env);
+    println!("Limits {:?}", package.limits);
+}
+
+/// Overrides the given requirements with the ones passed on the command line.
+fn apply_requirement_options(options: &RequirementOptions, requirements: PackageRequirements) -> PackageRequirements {
+    PackageRequirements {
+        arch: options.arch.clone().or(requirements.arch),
+        min_cpus: options.min_cpus.or(requirements.min_cpus),
+        min_disk: options.min_disk.or(requirements.min_disk),
+        tags: options.tags.clone().unwrap_or(requirements.tags),
+    }
+}
+
+/// Overrides the given build environment with the one passed on the command line.
+fn apply_build_env_options(options: &BuildEnvOptions, build_env: BuildEnvironment) -> BuildEnvironment {
+    let build_env = if options.clear_build_env {
+        BuildEnvironment::default()
+    } else {
+        build_env
+    };
+
+    let mut env = build_env.env;
+    env.extend(options.env.iter().cloned());
+    BuildEnvironment {
+        makeflags: options.makeflags.clone().or(build_env.makeflags),
+        cflags: options.cflags.clone().or(build_env.cflags),
+        pkgext: options.pkgext.clone().or(build_env.pkgext),
+        options: options.makepkg_options.clone().unwrap_or(build_env.options),
+        env,
+    }
+}
+
+/// Overrides the given resource limits with the ones passed on the command line.
+fn apply_limit_options(options: &LimitOptions, limits: ResourceLimits) -> ResourceLimits {
+    let limits = if options.clear_limits {
+        ResourceLimits::default()
+    } else {
+        limits
+    };
+
+    ResourceLimits {
+        cpus: options.limit_cpus.or(limits.cpus),
+        memory: options.limit_memory.or(limits.memory),
+        pids: options.limit_pids.or(limits.pids),
+        disk: options.limit_disk.or(limits.disk),
+    }
 }
 
-pub fn packages_create(api: &Api, name: &Option<String>, run_before: &Option<String>) {
+pub fn packages_create(api: &Api, name: &Option<String>, run_before: &Option<String>, keep_versions: Option<i32>, source: PackageSource, requirements: &RequirementOptions) {
     let (name, run_before) = match name.as_ref() {
         None => {
             let name: String = Input::with_theme(&ColorfulTheme::default())
@@ -178,13 +259,81 @@ pub fn packages_create(api: &Api, name: &Option<String>, run_before: &Option<Str
         Some(name) => (name.to_string(), run_before.clone()),
     };
 
-    match api.create_package(name, run_before) {
+    let requirements = apply_requirement_options(requirements, PackageRequirements::default());
+    match api.create_package(name, run_before, keep_versions, source, requirements) {
         Ok(package) => println!("Package {} created successfully", package.name),
         Err(e) => eprintln!("Failed to create package: {}", e),
     }
 }
 
-pub fn packages_delete(api: &Api, name: &String) {
+pub fn packages_upload(api: &Api, path: &Path, name: &Option<String>, run_before: &Option<String>) {
+    let name = match name.clone().or_else(|| {
+        path.canonicalize().ok()?.file_name().map(|n| n.to_string_lossy().to_string())
+    }) {
+        Some(name) => name,
+        None => {
+            eprintln!("Unable to determine package name from {:?}, use --name", path);
+            return;
+        }
+    };
+
+    if !path.join("PKGBUILD").exists() {
+        eprintln!("No PKGBUILD found in {:?}", path);
+        return;
+    }
+
+    let archive = match create_source_archive(path) {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("Failed to create source archive: {}", e);
+            return;
+        }
+    };
+
+    match api.upload_package(name, run_before.clone(), archive) {
+        Ok(package) => println!("Package {} uploaded successfully", package.name),
+        Err(e) => eprintln!("Failed to upload package: {}", e),
+    }
+}
+
+pub fn packages_update(api: &Api, name: &String, options: &PackageUpdateOptions) {
+    let package = try_get_package_from_name!(api, name);
+
+    let payload = UpdatePackagePayload {
+        run_before: options.run_before.clone().map(Some),
+        keep_versions: options.keep_versions.map(Some),
+        source: None,
+        priority: options.priority,
+        rebuild_interval: if options.clear_rebuild_interval {
+            Some(None)
+        } else {
+            options.rebuild_interval.map(Some)
+        },
+        schedule: if options.clear_schedule {
+            Some(None)
+        } else {
+            options.schedule.clone().map(Some)
+        },
+        build_timeout: if options.clear_build_timeout {
+            Some(None)
+        } else {
+            options.build_timeout.map(Some)
+        },
+        compiler_cache: options.compiler_cache,
+        allow_network: options.allow_network,
+        enabled: options.enabled,
+        requirements: Some(apply_requirement_options(&options.requirements, package.requirements)),
+        build_env: Some(apply_build_env_options(&options.build_env, package.build_env)),
+        limits: Some(apply_limit_options(&options.limits, package.limits)),
+    };
+
+    match api.update_package(package.id, &payload) {
+        Ok(package) => println!("Package {} updated successfully", package.name),
+        Err(e) => eprintln!("Failed to update package {}: {}", package.name, e),
+    }
+}
+
+pub fn packages_delete(api: &Api, name: &String, keep_files: bool) {
     let package = try_get_package_from_name!(api, name);
     if Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt(format!("Do you want to delete {} ?", package.name))
@@ -192,7 +341,7 @@ pub fn packages_delete(api: &Api, name: &String) {
         .interact()
         .unwrap()
     {
-        match api.delete_package(package.id) {
+        match api.delete_package(package.id, keep_files) {
             Ok(_) => println!("Package {} deleted", package.name),
             Err(e) => eprintln!("Failed to delete package {}: {}", package.name, e),
         }
@@ -221,6 +370,88 @@ pub fn packages_rebuild(api: &Api, packages: Vec<String>, force: bool) {
     }
 }
 
+pub fn packages_versions(api: &Api, name: &String) {
+    let package = try_get_package_from_name!(api, name);
+
+    match api.get_package_versions(package.id) {
+        Ok(versions) => {
+            if versions.is_empty() {
+                println!("No versions found.");
+                return;
+            }
+            let rows: Vec<Vec<CellStruct>> = versions
+                .into_iter()
+                .map(|version| {
+                    let current = if package.last_built_version.as_ref() == Some(&version.version) {
+                        "*".bold().green().to_string()
+                    } else {
+                        "".to_string()
+                    };
+                    vec![
+                        version.id.cell(),
+                        version.version.cell(),
+                        current.cell(),
+                        version
+                            .created_at
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                            .cell(),
+                        version.files.join(", ").cell(),
+                    ]
+                })
+                .collect();
+            println!(
+                "{}",
+                rows
+                    .table()
+                    .title(vec![
+                        "Id".cell().bold(true),
+                        "Version".cell().bold(true),
+                        "Current".cell().bold(true),
+                        "Built".cell().bold(true),
+                        "Files".cell().bold(true),
+                    ])
+                    .display()
+                    .unwrap()
+            );
+        }
+        Err(e) => {
+            eprintln!("Error while getting versions: {}", e);
+        }
+    }
+}
+
+pub fn packages_rollback(api: &Api, name: &String, version: &String) {
+    let package = try_get_package_from_name!(api, name);
+
+    let versions = match api.get_package_versions(package.id) {
+        Ok(versions) => versions,
+        Err(e) => {
+            eprintln!("Error while getting versions: {}", e);
+            return;
+        }
+    };
+    let Some(version) = versions.into_iter().find(|v| &v.version == version) else {
+        eprintln!("Version {} of {} is not retained in the repository", version, package.name);
+        return;
+    };
+
+    match api.rollback_package(package.id, version.id) {
+        Ok(package) => println!("Rolled back {} to version {}", package.name, version.version),
+        Err(e) => eprintln!("Failed to rollback package {}: {}", package.name, e),
+    }
+}
+
+pub fn packages_cancel(api: &Api, name: &String) {
+    let package = try_get_package_from_name!(api, name);
+
+    match api.cancel_package(package.id) {
+        Ok(package) => println!("Cancelled build of {}", package.name),
+        Err(e) => eprintln!("Failed to cancel build of {}: {}", package.name, e),
+    }
+}
+
 pub fn patches_list(api: &Api, package_name: &String) {
     let package = try_get_package_from_name!(api, package_name);
 
@@ -283,8 +514,104 @@ pub fn patches_delete(api: &Api, package_name: &String, id: i32) {
     }
 }
 
-pub fn logs_get(api: &Api, package: String) {
+pub fn builds_list(api: &Api, package_name: &String, limit: Option<i64>) {
+    let package = try_get_package_from_name!(api, package_name);
+
+    match api.get_builds(package.id, limit) {
+        Ok(builds) => {
+            if builds.is_empty() {
+                println!("No builds found.");
+                return;
+            }
+            let rows: Vec<Vec<CellStruct>> = builds
+                .into_iter()
+                .map(|build| {
+                    vec![
+                        build.id.cell(),
+                        build
+                            .status
+                            .to_string()
+                            .cell()
+                            .foreground_color(Some(get_color_from_build_status(&build.status).into())),
+                        build.version.unwrap_or("None".to_string()).cell(),
+                        build
+                            .started_at
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                            .cell(),
+                        build
+                            .ended_at
+                            .map(|ended_at| format!("{}s", (ended_at - build.started_at).num_seconds()))
+                            .unwrap_or("-".to_string())
+                            .cell(),
+                        build.worker_id.map(|id| id.to_string()).unwrap_or("None".to_string()).cell(),
+                        build
+                            .compiler_cache
+                            .map(|stats| format!("{}/{}", stats.hits, stats.hits + stats.misses))
+                            .unwrap_or("-".to_string())
+                            .cell(),
+                        build.error_class.map(|c| c.to_string()).unwrap_or("".to_string()).cell(),
+                        build.error.unwrap_or("".to_string()).cell(),
+                    ]
+                })
+                .collect();
+            println!(
+                "{}",
+                rows
+                    .table()
+                    .title(vec![
+                        "Id".cell().bold(true),
+                        "Status".cell().bold(true),
+                        "Version".cell().bold(true),
+                        "Started".cell().bold(true),
+                        "Duration".cell().bold(true),
+                        "Worker".cell().bold(true),
+                        "Cache hits".cell().bold(true),
+                        "Class".cell().bold(true),
+                        "Error".cell().bold(true),
+                    ])
+                    .display()
+                    .unwrap()
+            );
+        }
+        Err(e) => {
+            eprintln!("Error while getting builds: {}", e);
+        }
+    }
+}
+
+pub fn builds_logs(api: &Api, package_name: &String, id: i32) {
+    let package = try_get_package_from_name!(api, package_name);
+
+    match api.get_build_logs(package.id, id) {
+        Ok(contents) => {
+            println!("Logs for {} build {}", package.name, id);
+            println!("{}", contents);
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch build logs with error {:?}", err);
+        }
+    }
+}
+
+pub fn logs_get(api: &Api, package: String, follow: bool) {
     let package = try_get_package_from_name!(api, &package);
+    if follow {
+        if package.status == PackageStatus::BUILDING {
+            println!("Following logs for {}", package.name);
+            let res = api.follow_logs(package.id, |content| {
+                print!("{}", content);
+                let _ = stdout().flush();
+            });
+            match res {
+                Ok(_) => println!("\nBuild of {} finished", package.name),
+                Err(err) => println!("Failed to follow logs with error {:?}", err),
+            }
+            return;
+        }
+        println!("{} is not building, showing the logs of its last build", package.name);
+    }
     let res = api.get_logs(package.id);
     match res {
         Ok(contents) => {
@@ -297,6 +624,47 @@ pub fn logs_get(api: &Api, package: String) {
     }
 }
 
+pub fn repo_check(api: &Api, fix: bool) {
+    let report = match api.check_repository(fix) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Failed to check repository: {}", e);
+            return;
+        }
+    };
+
+    let sections = [
+        ("Missing files", &report.missing_files),
+        ("Orphan files", &report.orphan_files),
+        ("Missing signatures", &report.missing_signatures),
+        ("Stale signatures", &report.stale_signatures),
+        ("Missing database entries", &report.missing_db_entries),
+        ("Stale database entries", &report.stale_db_entries),
+    ];
+    let mut issues = 0;
+    for (title, files) in sections {
+        if files.is_empty() {
+            continue;
+        }
+        issues += files.len();
+        println!("{} ({})", title.bold(), files.len());
+        for file in files {
+            println!("  {}", file);
+        }
+    }
+
+    if issues == 0 {
+        println!("{}", "Repository is consistent".green());
+    } else if report.fixed {
+        println!("{}", format!("Fixed {} issue(s)", issues).green());
+        if !report.rebuilt_packages.is_empty() {
+            println!("Queued for rebuild to restore missing files: {}", report.rebuilt_packages.join(", "));
+        }
+    } else {
+        println!("{}", format!("Found {} issue(s), run with --fix to repair them", issues).yellow());
+    }
+}
+
 pub fn webhook_trigger_package_update(api: &Api) {
     let res = api.webhook_trigger_package();
     match res {
@@ -8,9 +8,10 @@ use std::process::exit;
 use clap::Parser;
 use colored::Colorize;
 use crate::api::Api;
-use crate::args::{Args, Commands, PackageCommands, PatchCommands, ProfileCommands, WebhookCommands, WorkerCommands};
-use crate::commands::{logs_get, packages_create, packages_delete, packages_get, packages_list, packages_rebuild, patches_create, patches_delete, patches_list, profile_create, profile_delete, profile_list, profile_set_default, webhook_trigger_package_update, workers_delete, workers_list};
+use crate::args::{Args, BuildCommands, Commands, PackageCommands, PatchCommands, ProfileCommands, RepoCommands, WebhookCommands, WorkerCommands};
+use crate::commands::{builds_list, builds_logs, logs_get, packages_cancel, packages_create, packages_delete, packages_get, packages_list, packages_rebuild, packages_rollback, packages_update, packages_upload, packages_versions, patches_create, patches_delete, patches_list, profile_create, profile_delete, profile_list, profile_set_default, repo_check, webhook_trigger_package_update, workers_delete, workers_list};
 use crate::profile::ProfileConfig;
+use common::models::PackageSource;
 
 fn get_api(args: &Args, profile_config: &ProfileConfig) -> Api {
     let api = if args.base_url.is_some() && args.api_key.is_some() {
@@ -59,9 +60,25 @@ fn main() {
             match command {
                 PackageCommands::List { compact } => packages_list(&api, compact),
                 PackageCommands::Get { name} => packages_get(&api, name),
-                PackageCommands::Add { name, run_before} => packages_create(&api, name, run_before),
-                PackageCommands::Remove { name } => packages_delete(&api, name),
+                PackageCommands::Add { name, run_before, keep_versions, git, git_ref, git_subdirectory, local, requirements } => {
+                    let source = match (git, local) {
+                        (Some(url), _) => PackageSource::Git {
+                            url: url.clone(),
+                            reference: git_ref.clone(),
+                            subdirectory: git_subdirectory.clone(),
+                        },
+                        (None, Some(path)) => PackageSource::Local { path: path.clone() },
+                        (None, None) => PackageSource::Aur,
+                    };
+                    packages_create(&api, name, run_before, *keep_versions, source, requirements)
+                },
+                PackageCommands::Upload { path, name, run_before } => packages_upload(&api, path, name, run_before),
+                PackageCommands::Update { name, options } => packages_update(&api, name, options),
+                PackageCommands::Remove { name, keep_files } => packages_delete(&api, name, *keep_files),
                 PackageCommands::Rebuild { packages, force } => packages_rebuild(&api, packages.clone(), *force),
+                PackageCommands::Versions { name } => packages_versions(&api, name),
+                PackageCommands::Rollback { name, version } => packages_rollback(&api, name, version),
+                PackageCommands::Cancel { name } => packages_cancel(&api, name),
             }
         }
         Commands::Patches { command } => {
@@ -75,10 +92,25 @@ fn main() {
                     patches_delete(&api, package_name, *id)
             }
         }
-        Commands::Logs { package} => {
+        Commands::Builds { command } => {
             let api = get_api(&args, &profile_config);
-            logs_get(&api, package.clone())
+
+            match command {
+                BuildCommands::List { package_name, limit } => builds_list(&api, package_name, *limit),
+                BuildCommands::Logs { package_name, id } => builds_logs(&api, package_name, *id),
+            }
+        }
+        Commands::Logs { package, follow } => {
+            let api = get_api(&args, &profile_config);
+            logs_get(&api, package.clone(), *follow)
         },
+        Commands::Repo { command } => {
+            let api = get_api(&args, &profile_config);
+
+            match command {
+                RepoCommands::Check { fix } => repo_check(&api, *fix),
+            }
+        }
         Commands::Webhooks {command} => {
             let api = get_api(&args, &profile_config);
 
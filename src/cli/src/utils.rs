@@ -1,4 +1,9 @@
-use common::models::{PackageStatus, WorkerStatus};
+use anyhow::Result;
+use common::models::{BuildStatus, PackageStatus, WorkerStatus};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::read_dir;
+use std::path::Path;
 
 pub enum UnifiedColor {
     Red,
@@ -61,4 +66,42 @@ pub fn get_color_from_package_status(status: &PackageStatus) -> UnifiedColor
         PackageStatus::BUILT => UnifiedColor::Green,
         PackageStatus::FAILED => UnifiedColor::Red,
     }
-}
\ No newline at end of file
+}
+
+pub fn get_color_from_build_status(status: &BuildStatus) -> UnifiedColor
+{
+    match status {
+        BuildStatus::UNKNOWN => UnifiedColor::White,
+        BuildStatus::RUNNING => UnifiedColor::Yellow,
+        BuildStatus::BUILT => UnifiedColor::Green,
+        BuildStatus::SKIPPED => UnifiedColor::Cyan,
+        BuildStatus::FAILED => UnifiedColor::Red,
+        BuildStatus::CANCELLED => UnifiedColor::Magenta,
+    }
+}
+
+/// Creates a gzipped tarball of a package directory with the PKGBUILD at its root.
+/// Build directories and built packages left over by makepkg are not included.
+pub fn create_source_archive(dir: &Path) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+
+        if entry.file_type()?.is_dir() {
+            if [".git", "src", "pkg"].contains(&name_str.as_ref()) {
+                continue;
+            }
+            builder.append_dir_all(&name, entry.path())?;
+        } else {
+            if name_str.contains(".pkg.tar") {
+                continue;
+            }
+            builder.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::models::{BuildEnvironment, PackageRequirements, PackageSource, ResourceLimits};
 
 #[derive(Serialize, Deserialize)]
 pub struct PackageRebuildPayload {
@@ -6,15 +7,48 @@ pub struct PackageRebuildPayload {
     pub force: Option<bool>
 }
 
+#[derive(Serialize, Deserialize, Default)]
+pub struct RepositoryCheckPayload {
+    pub fix: Option<bool>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CreatePackagePayload {
     pub name: String,
     pub run_before: Option<String>,
+    pub keep_versions: Option<i32>,
+    #[serde(default)]
+    pub source: PackageSource,
+    #[serde(default)]
+    pub requirements: PackageRequirements,
+    #[serde(default)]
+    pub build_env: BuildEnvironment,
+    #[serde(default)]
+    pub limits: ResourceLimits,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Fields left out of the payload keep their current value.
+/// The nullable ones are cleared by setting them to `null`, hence their `Some(None)`.
+#[derive(Serialize, Deserialize, Default)]
 pub struct UpdatePackagePayload {
-    pub run_before: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "::serde_with::rust::double_option")]
+    pub run_before: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "::serde_with::rust::double_option")]
+    pub keep_versions: Option<Option<i32>>,
+    pub source: Option<PackageSource>,
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "::serde_with::rust::double_option")]
+    pub rebuild_interval: Option<Option<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "::serde_with::rust::double_option")]
+    pub schedule: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "::serde_with::rust::double_option")]
+    pub build_timeout: Option<Option<i64>>,
+    pub compiler_cache: Option<bool>,
+    pub allow_network: Option<bool>,
+    pub enabled: Option<bool>,
+    pub requirements: Option<PackageRequirements>,
+    pub build_env: Option<BuildEnvironment>,
+    pub limits: Option<ResourceLimits>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1,4 +1,4 @@
-use crate::models::{PackageStatus, WorkerStatus};
+use crate::models::{BuildEnvironment, BuildErrorClass, BuildStatus, CompilerCacheStats, PackageRequirements, PackageSource, PackageStatus, ResourceLimits, WorkerLabels, WorkerStatus};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -26,6 +26,31 @@ pub struct PackageResponse {
     pub files: Vec<String>,
     pub last_built_version: Option<String>,
     pub last_error: Option<String>,
+    pub keep_versions: Option<i32>,
+    pub source: PackageSource,
+    pub upstream_version: Option<String>,
+    pub upstream_last_modified: Option<DateTime<Utc>>,
+    pub priority: i32,
+    pub rebuild_interval: Option<i64>,
+    pub schedule: Option<String>,
+    pub build_timeout: Option<i64>,
+    #[serde(default)]
+    pub compiler_cache: bool,
+    #[serde(default)]
+    pub allow_network: bool,
+    #[serde(default)]
+    pub implicit: bool,
+    #[serde(default)]
+    pub required_by: Vec<String>,
+    pub enabled: bool,
+    pub dependencies: Vec<String>,
+    pub requirements: PackageRequirements,
+    #[serde(default)]
+    pub build_env: BuildEnvironment,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    pub retry_count: i32,
+    pub next_retry: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -36,10 +61,55 @@ pub struct PackagePatchResponse {
     pub sha_512: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageVersionResponse {
+    pub id: i32,
+    pub package_id: i32,
+    pub version: String,
+    pub files: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildResponse {
+    pub id: i32,
+    pub package_id: i32,
+    pub worker_id: Option<i32>,
+    pub status: BuildStatus,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub version: Option<String>,
+    pub error: Option<String>,
+    pub error_class: Option<BuildErrorClass>,
+    #[serde(default)]
+    pub compiler_cache: Option<CompilerCacheStats>,
+    pub log_file: Option<String>,
+}
+
+/// Issues found in the repository, files are given as `arch/file`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RepositoryCheckResponse {
+    /// Files of packages missing from the repository.
+    pub missing_files: Vec<String>,
+    /// Package files neither belonging to a package nor to one of its retained versions.
+    pub orphan_files: Vec<String>,
+    pub missing_signatures: Vec<String>,
+    /// Signatures older than their package, of missing packages or present while the repository is not signed.
+    pub stale_signatures: Vec<String>,
+    pub missing_db_entries: Vec<String>,
+    /// Entries of the database not matching a current package file.
+    pub stale_db_entries: Vec<String>,
+    /// Whether the issues were repaired.
+    pub fixed: bool,
+    /// Packages queued for rebuild to restore their missing files.
+    pub rebuilt_packages: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerResponse {
     pub id: usize,
     pub status: WorkerStatus,
     pub current_job: Option<String>,
     pub version: String,
+    pub labels: WorkerLabels,
 }
\ No newline at end of file
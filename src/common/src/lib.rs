@@ -1,3 +1,4 @@
 pub mod messages;
 pub mod http;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod version;
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use crate::models::PackageJob;
-use crate::models::WorkerStatus;
+use crate::models::{WorkerLabels, WorkerStatus};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "payload")]
@@ -8,10 +8,19 @@ pub enum WebsocketMessage {
     WorkerHello {
         version: String,
         status: WorkerStatus,
+        #[serde(default)]
+        labels: WorkerLabels,
     },
     JobSubmit {
         package: PackageJob,
     },
+    JobCancel {
+        package_id: i32,
+    },
+    BuildLogs {
+        package_id: i32,
+        content: String,
+    },
     WorkerStatusRequest {},
     WorkerStatusUpdate {
         status: WorkerStatus,
@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::fmt;
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -7,18 +9,183 @@ pub struct PackagePatchDefinition {
     pub sha512: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// Where the PKGBUILD of a package is fetched from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(tag = "type")]
+pub enum PackageSource {
+    #[default]
+    Aur,
+    Git {
+        url: String,
+        /// Branch, tag or commit to checkout. Defaults to the remote HEAD.
+        reference: Option<String>,
+        /// Directory containing the PKGBUILD relative to the repository root.
+        subdirectory: Option<String>,
+    },
+    /// Directory on the worker containing the PKGBUILD.
+    Local {
+        path: String,
+    },
+    /// Source tarball uploaded to the server, fetched by the worker through the worker API.
+    Upload {
+        file: String,
+    },
+}
+
+impl fmt::Display for PackageSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageSource::Aur => write!(f, "AUR"),
+            PackageSource::Git { url, reference, subdirectory } => {
+                write!(f, "git {}", url)?;
+                if let Some(reference) = reference {
+                    write!(f, "#{}", reference)?;
+                }
+                if let Some(subdirectory) = subdirectory {
+                    write!(f, " ({})", subdirectory)?;
+                }
+                Ok(())
+            }
+            PackageSource::Local { path } => write!(f, "local {}", path),
+            PackageSource::Upload { file } => write!(f, "upload {}", file),
+        }
+    }
+}
+
+/// Capabilities advertised by a worker, matched against the requirements of packages when dispatching.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct WorkerLabels {
+    pub arch: String,
+    pub cpus: usize,
+    /// Disk space available for builds in GiB.
+    pub max_disk: Option<u64>,
+    pub tags: Vec<String>,
+}
+
+impl Default for WorkerLabels {
+    fn default() -> Self {
+        WorkerLabels {
+            arch: "x86_64".to_string(),
+            cpus: 1,
+            max_disk: None,
+            tags: vec![],
+        }
+    }
+}
+
+impl WorkerLabels {
+    /// Whether a worker with these labels can build a package with the given requirements.
+    /// `default_arch` is used when the package does not require a specific architecture.
+    pub fn satisfies(&self, requirements: &PackageRequirements, default_arch: &str) -> bool {
+        self.arch == requirements.arch.as_deref().unwrap_or(default_arch)
+            && requirements.min_cpus.is_none_or(|cpus| self.cpus >= cpus)
+            && requirements.min_disk.is_none_or(|disk| self.max_disk.is_some_and(|max| max >= disk))
+            && requirements.tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// Labels a worker needs to build a package.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct PackageRequirements {
+    /// Architecture to build the package for, defaults to the first architecture of the server.
+    pub arch: Option<String>,
+    pub min_cpus: Option<usize>,
+    /// Disk space needed for the build in GiB.
+    pub min_disk: Option<u64>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct PackageDefinition {
     pub package_id: i32,
     pub name: String,
     pub run_before: Option<String>,
     pub patches: Vec<PackagePatchDefinition>,
+    #[serde(default)]
+    pub source: PackageSource,
+    /// Whether C/C++ and Rust compilations are cached across builds of the package with ccache and sccache.
+    #[serde(default)]
+    pub compiler_cache: bool,
+    #[serde(default)]
+    pub build_env: BuildEnvironment,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Whether build() and package() keep network access on workers isolating builds from the network.
+    #[serde(default)]
+    pub allow_network: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// Resources a build may use on the worker, unlimited when not set.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Amount of CPUs the build may use, e.g. 1.5.
+    pub cpus: Option<f64>,
+    /// Memory in MiB.
+    pub memory: Option<u64>,
+    /// Maximum amount of processes and threads.
+    pub pids: Option<u64>,
+    /// Disk space in GiB the build sandbox and the sources cache may grow by.
+    pub disk: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Applies the given overrides on top of these limits.
+    pub fn merge(&self, overrides: &ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            cpus: overrides.cpus.or(self.cpus),
+            memory: overrides.memory.or(self.memory),
+            pids: overrides.pids.or(self.pids),
+            disk: overrides.disk.or(self.disk),
+        }
+    }
+}
+
+/// makepkg settings and environment variables of a build, overriding the ones of the distribution.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct BuildEnvironment {
+    pub makeflags: Option<String>,
+    pub cflags: Option<String>,
+    /// Extension of the built packages, e.g. `.pkg.tar.xz`.
+    pub pkgext: Option<String>,
+    /// Entries appended to the makepkg OPTIONS, e.g. `!debug` or `!lto`.
+    pub options: Vec<String>,
+    /// Extra environment variables of the build.
+    pub env: BTreeMap<String, String>,
+}
+
+impl BuildEnvironment {
+    /// Applies the given overrides on top of this environment.
+    pub fn merge(&self, overrides: &BuildEnvironment) -> BuildEnvironment {
+        let mut env = self.env.clone();
+        env.extend(overrides.env.clone());
+        BuildEnvironment {
+            makeflags: overrides.makeflags.clone().or(self.makeflags.clone()),
+            cflags: overrides.cflags.clone().or(self.cflags.clone()),
+            pkgext: overrides.pkgext.clone().or(self.pkgext.clone()),
+            options: self.options.iter().chain(overrides.options.iter()).cloned().collect(),
+            env,
+        }
+    }
+}
+
+/// Compilations served from or missing in the compiler cache during a build.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompilerCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct PackageJob {
     pub definition: PackageDefinition,
     pub last_built_version: Option<String>,
+    /// Name of the repository served by the server, workers install the dependencies already built there instead of building them.
+    #[serde(default)]
+    pub repo_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Hash, Eq)]
@@ -55,6 +222,98 @@ impl fmt::Display for PackageStatus {
     }
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Hash, Eq)]
+#[repr(u8)]
+pub enum BuildStatus {
+    UNKNOWN = 0,
+    RUNNING = 1,
+    BUILT = 2,
+    SKIPPED = 3,
+    FAILED = 4,
+    CANCELLED = 5,
+}
+
+impl BuildStatus {
+    pub fn from_u8(value: u8) -> BuildStatus {
+        match value {
+            1 => BuildStatus::RUNNING,
+            2 => BuildStatus::BUILT,
+            3 => BuildStatus::SKIPPED,
+            4 => BuildStatus::FAILED,
+            5 => BuildStatus::CANCELLED,
+            _ => BuildStatus::UNKNOWN,
+        }
+    }
+}
+
+impl From<BuildStatus> for i16 {
+    fn from(value: BuildStatus) -> Self {
+        value as u8 as i16
+    }
+}
+
+impl fmt::Display for BuildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Category of a build failure, used to decide whether the build should be retried.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Hash, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildErrorClass {
+    /// Failure to reach a mirror or the server, e.g. while updating the chroot or downloading sources.
+    Network,
+    /// Failure to clone or fetch the PKGBUILD of the package or one of its dependencies.
+    Source,
+    /// Failure to query the AUR RPC.
+    AurRpc,
+    /// makepkg failed to build the package.
+    Build,
+    /// The build ran longer than its timeout and was cancelled by the server.
+    Timeout,
+    /// The build exceeded its memory, process or disk limit.
+    ResourceLimit,
+    Unknown,
+}
+
+impl BuildErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuildErrorClass::Network => "network",
+            BuildErrorClass::Source => "source",
+            BuildErrorClass::AurRpc => "aur_rpc",
+            BuildErrorClass::Build => "build",
+            BuildErrorClass::Timeout => "timeout",
+            BuildErrorClass::ResourceLimit => "resource_limit",
+            BuildErrorClass::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for BuildErrorClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "network" => Ok(BuildErrorClass::Network),
+            "source" => Ok(BuildErrorClass::Source),
+            "aur_rpc" => Ok(BuildErrorClass::AurRpc),
+            "build" => Ok(BuildErrorClass::Build),
+            "timeout" => Ok(BuildErrorClass::Timeout),
+            "resource_limit" => Ok(BuildErrorClass::ResourceLimit),
+            "unknown" => Ok(BuildErrorClass::Unknown),
+            _ => Err(format!("Unknown build error class '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for BuildErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum WorkerStatus {
     UNKNOWN,
@@ -71,4 +330,4 @@ impl fmt::Display for WorkerStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+
+/// Splits a version in its epoch, version and release, as done by pacman.
+fn parse_evr(value: &str) -> (&str, &str, Option<&str>)
+{
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (epoch, rest) = match value[digits..].strip_prefix(':') {
+        Some(rest) => (if digits == 0 { "0" } else { &value[..digits] }, rest),
+        None => ("0", value),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compares two package versions with the same rules as pacman's `vercmp`.
+pub fn vercmp(a: &str, b: &str) -> Ordering
+{
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (epoch_a, version_a, release_a) = parse_evr(a);
+    let (epoch_b, version_b, release_b) = parse_evr(b);
+
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(version_a, version_b))
+        .then_with(|| match (release_a, release_b) {
+            (Some(release_a), Some(release_b)) => rpmvercmp(release_a, release_b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Compares version segments separated by non alphanumeric characters, numbers being newer than letters.
+fn rpmvercmp(a: &str, b: &str) -> Ordering
+{
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let one = a.as_bytes();
+    let two = b.as_bytes();
+    let (mut i, mut j) = (0, 0);
+
+    while i < one.len() && j < two.len() {
+        let (start_i, start_j) = (i, j);
+        while i < one.len() && !one[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < two.len() && !two[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i >= one.len() || j >= two.len() {
+            break;
+        }
+        if i - start_i != j - start_j {
+            return (i - start_i).cmp(&(j - start_j));
+        }
+
+        let is_num = one[i].is_ascii_digit();
+        let is_segment = |c: &u8| if is_num { c.is_ascii_digit() } else { c.is_ascii_alphabetic() };
+        let end_i = i + one[i..].iter().take_while(|c| is_segment(c)).count();
+        let end_j = j + two[j..].iter().take_while(|c| is_segment(c)).count();
+
+        // Segments of different types, numbers are newer
+        if end_j == j {
+            return if is_num { Ordering::Greater } else { Ordering::Less };
+        }
+
+        let (mut segment_a, mut segment_b) = (&one[i..end_i], &two[j..end_j]);
+        if is_num {
+            segment_a = &segment_a[segment_a.iter().take_while(|c| **c == b'0').count()..];
+            segment_b = &segment_b[segment_b.iter().take_while(|c| **c == b'0').count()..];
+            if segment_a.len() != segment_b.len() {
+                return segment_a.len().cmp(&segment_b.len());
+            }
+        }
+        let ordering = segment_a.cmp(segment_b);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+
+        i = end_i;
+        j = end_j;
+    }
+
+    if i >= one.len() && j >= two.len() {
+        return Ordering::Equal;
+    }
+
+    // A remaining alpha segment is older than nothing, e.g. 1.0alpha < 1.0 but 1.0.1 > 1.0
+    if (i >= one.len() && !two[j].is_ascii_alphabetic()) || (i < one.len() && one[i].is_ascii_alphabetic()) {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
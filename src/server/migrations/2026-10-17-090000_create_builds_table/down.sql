@@ -0,0 +1 @@
+DROP TABLE builds;
\ No newline at end of file
@@ -0,0 +1,15 @@
+create table builds
+(
+    id         INTEGER primary key autoincrement NOT NULL,
+    package_id INTEGER NOT NULL
+        constraint builds_packages_id_fk
+            references packages (id)
+            on delete cascade,
+    worker_id  INTEGER  DEFAULT NULL,
+    status     SMALLINT DEFAULT 1 NOT NULL,
+    started_at INT8              NOT NULL,
+    ended_at   INT8     DEFAULT NULL,
+    version    TEXT     DEFAULT NULL,
+    error      TEXT     DEFAULT NULL,
+    log_file   TEXT     DEFAULT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE package_versions;
+ALTER TABLE packages DROP COLUMN keep_versions;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE packages ADD COLUMN keep_versions INTEGER DEFAULT NULL;
+
+create table package_versions
+(
+    id         INTEGER primary key autoincrement NOT NULL,
+    package_id INTEGER NOT NULL
+        constraint package_versions_packages_id_fk
+            references packages (id)
+            on delete cascade,
+    version    TEXT              NOT NULL,
+    files      TEXT DEFAULT '[]' NOT NULL,
+    created_at INT8              NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE packages DROP COLUMN source_subdirectory;
+ALTER TABLE packages DROP COLUMN source_ref;
+ALTER TABLE packages DROP COLUMN source_url;
+ALTER TABLE packages DROP COLUMN source_type;
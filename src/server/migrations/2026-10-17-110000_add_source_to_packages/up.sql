@@ -0,0 +1,4 @@
+ALTER TABLE packages ADD COLUMN source_type SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD COLUMN source_url TEXT DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN source_ref TEXT DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN source_subdirectory TEXT DEFAULT NULL;
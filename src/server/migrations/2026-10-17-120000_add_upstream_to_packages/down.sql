@@ -0,0 +1,2 @@
+ALTER TABLE packages DROP COLUMN upstream_last_modified;
+ALTER TABLE packages DROP COLUMN upstream_version;
@@ -0,0 +1,2 @@
+ALTER TABLE packages ADD COLUMN upstream_version TEXT DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN upstream_last_modified INT8 DEFAULT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE packages DROP COLUMN enabled;
+ALTER TABLE packages DROP COLUMN schedule;
+ALTER TABLE packages DROP COLUMN rebuild_interval;
+ALTER TABLE packages DROP COLUMN priority;
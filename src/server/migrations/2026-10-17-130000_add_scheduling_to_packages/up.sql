@@ -0,0 +1,4 @@
+ALTER TABLE packages ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD COLUMN rebuild_interval INT8 DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN schedule TEXT DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT 1;
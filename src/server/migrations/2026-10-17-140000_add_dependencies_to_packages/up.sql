@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN dependencies TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+ALTER TABLE packages DROP COLUMN required_tags;
+ALTER TABLE packages DROP COLUMN min_disk;
+ALTER TABLE packages DROP COLUMN min_cpus;
+ALTER TABLE packages DROP COLUMN required_arch;
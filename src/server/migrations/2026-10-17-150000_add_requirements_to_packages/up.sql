@@ -0,0 +1,4 @@
+ALTER TABLE packages ADD COLUMN required_arch TEXT DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN min_cpus INTEGER DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN min_disk INT8 DEFAULT NULL;
+ALTER TABLE packages ADD COLUMN required_tags TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,3 @@
+ALTER TABLE builds DROP COLUMN error_class;
+ALTER TABLE packages DROP COLUMN retry_at;
+ALTER TABLE packages DROP COLUMN retry_count;
@@ -0,0 +1,3 @@
+ALTER TABLE packages ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD COLUMN retry_at INT8 DEFAULT NULL;
+ALTER TABLE builds ADD COLUMN error_class TEXT DEFAULT NULL;
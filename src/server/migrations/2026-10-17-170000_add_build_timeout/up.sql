@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN build_timeout INT8 DEFAULT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE builds DROP COLUMN compiler_cache_misses;
+ALTER TABLE builds DROP COLUMN compiler_cache_hits;
+ALTER TABLE packages DROP COLUMN compiler_cache;
@@ -0,0 +1,3 @@
+ALTER TABLE packages ADD COLUMN compiler_cache BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE builds ADD COLUMN compiler_cache_hits INT8 DEFAULT NULL;
+ALTER TABLE builds ADD COLUMN compiler_cache_misses INT8 DEFAULT NULL;
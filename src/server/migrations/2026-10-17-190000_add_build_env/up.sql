@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN build_env TEXT NOT NULL DEFAULT '{}';
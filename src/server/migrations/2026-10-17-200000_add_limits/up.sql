@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN limits TEXT NOT NULL DEFAULT '{}';
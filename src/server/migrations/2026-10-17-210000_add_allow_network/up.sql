@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN allow_network BOOLEAN NOT NULL DEFAULT 0;
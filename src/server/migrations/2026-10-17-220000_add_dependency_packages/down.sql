@@ -0,0 +1,2 @@
+ALTER TABLE packages DROP COLUMN required_by;
+ALTER TABLE packages DROP COLUMN implicit;
@@ -0,0 +1,2 @@
+ALTER TABLE packages ADD COLUMN implicit BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE packages ADD COLUMN required_by TEXT NOT NULL DEFAULT '[]';
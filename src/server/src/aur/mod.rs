@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+use reqwest::Client;
+use serde::Deserialize;
+
+/// Maximum amount of packages queried in a single info request to stay under URL length limits.
+const INFO_BATCH_SIZE: usize = 100;
+
+/// Suffixes of packages built from a VCS, their version can only be known by building them.
+const VCS_SUFFIXES: [&str; 7] = ["-git", "-svn", "-hg", "-bzr", "-fossil", "-darcs", "-cvs"];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AurInfo {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Version")]
+    pub version: String,
+    #[serde(rename = "LastModified")]
+    pub last_modified: i64,
+}
+
+impl AurInfo {
+    pub fn get_last_modified(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.last_modified, 0).unwrap_or_default()
+    }
+
+    /// Converts the AUR `[epoch:]pkgver-pkgrel` version into the format reported by workers after a build.
+    pub fn get_build_version(&self) -> String {
+        let (epoch, version) = match self.version.split_once(':') {
+            Some((epoch, version)) => (epoch, version),
+            None => ("", self.version.as_str()),
+        };
+        let (pkgver, pkgrel) = version.rsplit_once('-').unwrap_or((version, ""));
+
+        format!("{}{}{}", pkgver, pkgrel, epoch)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct AurInfoResults {
+    pub results: Vec<AurInfo>,
+}
+
+#[derive(Clone)]
+pub struct AurClient {
+    rpc_url: String,
+    client: Client,
+}
+
+impl AurClient {
+    pub fn new(rpc_url: String) -> Self {
+        AurClient {
+            rpc_url: rpc_url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    /// Queries the info endpoint of the RPC for the given packages, batching the requests.
+    /// Packages not found on the AUR are absent from the result.
+    pub async fn info(&self, names: &[String]) -> Result<Vec<AurInfo>> {
+        let mut infos = Vec::new();
+
+        for batch in names.chunks(INFO_BATCH_SIZE) {
+            let query: Vec<(&str, &str)> = batch.iter().map(|name| ("arg[]", name.as_str())).collect();
+            let results: AurInfoResults = self.client
+                .get(format!("{}/v5/info", self.rpc_url))
+                .query(&query)
+                .send().await
+                .with_context(|| "Failed to query AUR RPC")?
+                .error_for_status()?
+                .json().await
+                .with_context(|| "Failed to parse AUR RPC response")?;
+            debug!("AUR RPC returned {} results for {} packages", results.results.len(), batch.len());
+
+            infos.extend(results.results);
+        }
+
+        Ok(infos)
+    }
+}
+
+pub fn is_vcs_package(name: &str) -> bool {
+    VCS_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::aur::{is_vcs_package, AurClient, AurInfo};
+    use actix_web::dev::ServerHandle;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Starts a mock AUR RPC answering info requests for the given (name, version, last_modified).
+    /// Returns the RPC url, the server handle and a counter of received requests.
+    pub fn start_mock_rpc(packages: Vec<(&'static str, &'static str, i64)>) -> (String, ServerHandle, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        let server = HttpServer::new(move || {
+            let packages = packages.clone();
+            let counter = counter.clone();
+            App::new().route("/rpc/v5/info", web::get().to(move |req: HttpRequest| {
+                let packages = packages.clone();
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let names: Vec<String> = req.query_string()
+                        .split('&')
+                        .filter_map(|arg| arg.split_once('='))
+                        .filter(|(key, _)| *key == "arg[]" || *key == "arg%5B%5D")
+                        .map(|(_, value)| value.to_string())
+                        .collect();
+                    let results: Vec<_> = packages.iter()
+                        .filter(|(name, _, _)| names.contains(&name.to_string()))
+                        .map(|(name, version, last_modified)| json!({
+                            "Name": name,
+                            "Version": version,
+                            "LastModified": last_modified,
+                        }))
+                        .collect();
+                    HttpResponse::Ok().json(json!({ "resultcount": results.len(), "results": results, "type": "multiinfo", "version": 5 }))
+                }
+            }))
+        })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}/rpc", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        (url, handle, requests)
+    }
+
+    #[actix_web::test]
+    async fn test_info() {
+        let (url, handle, requests) = start_mock_rpc(vec![
+            ("first", "1.0.0-1", 100),
+            ("second", "2:1.2-3", 200),
+        ]);
+
+        let mut names: Vec<String> = (0..150).map(|i| format!("missing{}", i)).collect();
+        names.push("first".to_string());
+        names.push("second".to_string());
+
+        let infos = AurClient::new(url).info(&names).await.unwrap();
+        assert_eq!(2, infos.len());
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+
+        let second = infos.iter().find(|i| i.name == "second").unwrap();
+        assert_eq!(200, second.get_last_modified().timestamp());
+        assert_eq!("1.232", second.get_build_version());
+
+        handle.stop(true).await;
+    }
+
+    #[test]
+    fn test_build_version() {
+        let info = AurInfo {
+            name: "name".to_string(),
+            version: "1.0.0-1".to_string(),
+            last_modified: 0,
+        };
+        assert_eq!("1.0.01", info.get_build_version());
+        assert!(is_vcs_package("name-git"));
+        assert!(!is_vcs_package("github-cli"));
+    }
+}
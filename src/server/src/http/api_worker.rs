@@ -1,23 +1,36 @@
+use actix_files::NamedFile;
 use actix_multipart::form::MultipartForm;
 use actix_multipart::form::tempfile::TempFile;
 use actix_multipart::form::text::Text;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
 use actix_web::web::{scope, Json};
+use actix_web::http::StatusCode;
+use anyhow::anyhow;
+use common::models::{BuildErrorClass, CompilerCacheStats};
 use log::{debug, error, info};
-use crate::http::base::{JsonResult, SuccessResponse};
+use std::path::{Component, Path};
+use crate::http::base::{HttpError, JsonResult, SuccessResponse};
 use crate::http::HttpState;
+use crate::orchestrator::{BuildFailure, BuildReport};
 
 pub fn register() -> Scope {
     scope("/api_workers")
         .route("/upload", web::post().to(upload))
+        .route("/sources/{file}", web::get().to(source))
+        .route("/repository/key", web::get().to(repository_key))
         .route("/ws", web::get().to(websocket))
 }
 
 #[derive(Debug, MultipartForm)]
 struct UploadForm {
     pub package_name: Text<String>,
+    pub package_base: Option<Text<String>>,
     pub version: Option<Text<String>>,
     pub error: Option<Text<String>>,
+    pub error_class: Option<Text<String>>,
+    pub dependencies: Vec<Text<String>>,
+    pub compiler_cache_hits: Option<Text<u64>>,
+    pub compiler_cache_misses: Option<Text<u64>>,
 
     pub log_files: Vec<TempFile>,
     pub files: Vec<TempFile>,
@@ -30,11 +43,24 @@ async fn upload(
 {
     debug!("Received upload from worker {:?}", form);
 
+    let failure = form.error.map(|error| BuildFailure {
+        message: error.into_inner(),
+        class: form.error_class
+            .and_then(|class| class.parse().ok())
+            .unwrap_or(BuildErrorClass::Unknown),
+    });
+
     let res = state.orchestrator.write().await
         .handle_package_build_output(
             form.package_name.to_string(),
             form.version.map(|x| x.to_string()),
-            form.error.map(|x| x.to_string()),
+            failure,
+            BuildReport {
+                package_base: form.package_base.map(|x| x.into_inner()),
+                dependencies: form.dependencies.into_iter().map(|x| x.into_inner()).collect(),
+                compiler_cache: form.compiler_cache_hits.zip(form.compiler_cache_misses)
+                    .map(|(hits, misses)| CompilerCacheStats { hits: hits.into_inner(), misses: misses.into_inner() }),
+            },
             form.log_files,
             form.files,
         ).await;
@@ -47,6 +73,25 @@ async fn upload(
     Ok(Json(SuccessResponse::from(true)))
 }
 
+async fn source(state: web::Data<HttpState>, path: web::Path<String>) -> Result<NamedFile, HttpError>
+{
+    let file = path.into_inner();
+    if Path::new(&file).components().any(|x| !matches!(x, Component::Normal(_))) || file.contains('/') {
+        return Err(HttpError::new(anyhow!("Bad request"), StatusCode::BAD_REQUEST));
+    }
+
+    let path = state.config.read().await.sources_path.join(&file);
+    NamedFile::open_async(&path).await.map_err(|_| HttpError::not_found())
+}
+
+async fn repository_key(state: web::Data<HttpState>) -> Result<HttpResponse, HttpError>
+{
+    match state.orchestrator.read().await.export_repository_key().await? {
+        Some(key) => Ok(HttpResponse::Ok().content_type("application/pgp-keys").body(key)),
+        None => Err(HttpError::not_found()),
+    }
+}
+
 async fn websocket(
     req: HttpRequest,
     stream: web::Payload,
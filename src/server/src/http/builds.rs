@@ -0,0 +1,162 @@
+use crate::http::base::{HttpError, JsonResult, ResponseResult};
+use crate::http::HttpState;
+use actix_web::http::StatusCode;
+use actix_web::web::{scope, Json};
+use actix_web::{web, HttpResponse, Scope};
+use anyhow::anyhow;
+use common::http::responses::BuildResponse;
+use common::models::BuildStatus;
+use serde::Deserialize;
+use std::path::Component;
+
+pub fn register() -> Scope
+{
+    scope("/packages/{package_id}/builds")
+        .route("", web::get().to(index))
+        .route("/{id}", web::get().to(get))
+        .route("/{id}/logs", web::get().to(action_logs))
+}
+
+#[derive(Deserialize)]
+struct IndexQuery {
+    pub status: Option<BuildStatus>,
+    pub limit: Option<i64>,
+}
+
+async fn index(
+    state: web::Data<HttpState>,
+    path: web::Path<i32>,
+    query: web::Query<IndexQuery>,
+) -> JsonResult<Vec<BuildResponse>> {
+    let query = query.into_inner();
+    let builds = state.orchestrator.write().await
+        .get_package_store()
+        .get_builds_for_package(path.into_inner(), query.status, query.limit)
+        .await?;
+
+    Ok(Json(builds.into_iter().map(Into::into).collect()))
+}
+
+async fn get(state: web::Data<HttpState>, path: web::Path<(i32, i32)>) -> JsonResult<BuildResponse> {
+    let (package_id, build_id) = path.into_inner();
+
+    if let Some(build) = state.orchestrator.write().await
+        .get_package_store()
+        .get_build(package_id, build_id).await? {
+        return Ok(Json(build.into()));
+    }
+
+    Err(HttpError::not_found())
+}
+
+async fn action_logs(state: web::Data<HttpState>, path: web::Path<(i32, i32)>) -> ResponseResult {
+    let (package_id, build_id) = path.into_inner();
+
+    let build = state.orchestrator.write().await
+        .get_package_store()
+        .get_build(package_id, build_id).await?
+        .ok_or(HttpError::not_found())?;
+    let log_file = build.log_file.ok_or(HttpError::not_found())?;
+
+    let path = state
+        .config
+        .read()
+        .await
+        .build_logs_path
+        .join("builds")
+        .join(log_file);
+    if path.components().any(|x| x == Component::ParentDir) {
+        return Err(HttpError::new(anyhow!("Bad request"), StatusCode::BAD_REQUEST));
+    }
+
+    let content = tokio::fs::read_to_string(&path).await.unwrap_or_else(|e| {
+        format!("Failed to read file: {}", e)
+    });
+    Ok(HttpResponse::Ok().body(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+    use common::http::responses::BuildResponse;
+    use common::models::BuildStatus;
+    use tokio::io::AsyncWriteExt;
+    use crate::get_test_app;
+
+    #[actix_web::test]
+    async fn test_index_builds() {
+        let (app, _) = get_test_app!();
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/builds")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: Vec<BuildResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].status, BuildStatus::FAILED);
+        assert_eq!(parsed[1].status, BuildStatus::BUILT);
+        assert_eq!(parsed[1].version, Some("lastver".to_string()));
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/builds?status=BUILT&limit=1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: Vec<BuildResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].status, BuildStatus::BUILT);
+    }
+
+    #[actix_web::test]
+    async fn test_get_builds() {
+        let (app, _) = get_test_app!();
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/builds/1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: BuildResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.id, 1);
+        assert_eq!(parsed.package_id, 2);
+        assert!(parsed.ended_at.is_some());
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/builds/1")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_action_logs_builds() {
+        let (app, state) = get_test_app!();
+
+        let base_path = state.config.read().await.build_logs_path.join("builds");
+        tokio::fs::create_dir_all(&base_path).await.unwrap();
+        {
+            let mut file = tokio::fs::File::create(base_path.join("second-1.log")).await.unwrap();
+            file.write_all("build log content".as_bytes()).await.unwrap();
+            file.flush().await.unwrap();
+        }
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/builds/1/logs")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), "build log content");
+    }
+}
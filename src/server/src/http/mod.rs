@@ -1,8 +1,11 @@
 mod api_worker;
 mod auth_middleware;
 mod base;
+mod builds;
 mod packages;
 mod patches;
+mod repository;
+mod versions;
 mod workers;
 mod webhooks;
 
@@ -36,8 +39,11 @@ fn get_app(cfg: &mut ServiceConfig, state: HttpState, config: &Config) {
                 web::scope("/api")
                     .service(workers::register())
                     .service(patches::register())
+                    .service(builds::register())
+                    .service(versions::register())
                     .service(packages::register())
                     .service(webhooks::register())
+                    .service(repository::register())
             )
             .service(api_worker::register()),
     );
@@ -66,10 +72,10 @@ mod tests {
             use crate::http::{get_app, HttpState};
             use crate::models::config::Config;
             use crate::orchestrator::Orchestrator;
-            use crate::persistence::package_store::{PackageInsert, PackagePatchInsert};
+            use crate::persistence::package_store::{BuildInsert, PackageInsert, PackagePatchInsert, PackageVersionInsert};
             use actix_web::{test, App};
             use chrono::Utc;
-            use common::models::PackageStatus;
+            use common::models::{BuildErrorClass, BuildStatus, PackageStatus};
             use log::LevelFilter;
             use std::path::PathBuf;
             use std::sync::Arc;
@@ -81,11 +87,21 @@ mod tests {
                 api_key: "api_key".to_string(),
                 port: 3000,
                 repo_name: "test".to_string(),
+                architectures: vec!["x86_64".to_string()],
                 sign_key: None,
                 rebuild_time: None,
+                build_timeout: None,
+                update_check_time: None,
+                aur_rpc_url: "http://127.0.0.1/rpc".to_string(),
+                keep_versions: 1,
+                retry_max_attempts: 3,
+                retry_backoff_time: 10,
+                retry_error_classes: vec![BuildErrorClass::Network],
                 serve_path: PathBuf::from("/tmp/aur-build-server-test/repo"),
+                migrate_legacy_layout: false,
                 build_logs_path: PathBuf::from("/tmp/aur-build-server-test/logs"),
                 database_path: ":memory:".into(),
+                sources_path: PathBuf::from("/tmp/aur-build-server-test/sources"),
                 webhook_verify_ssl: false,
                 webhook_certificate: None,
                 webhooks: vec![],
@@ -99,6 +115,7 @@ mod tests {
                 .create_package(PackageInsert {
                     name: "first".to_string(),
                     run_before: None,
+                    ..Default::default()
                 })
                 .await
                 .unwrap();
@@ -107,6 +124,7 @@ mod tests {
                 .create_package(PackageInsert {
                     name: "second".to_string(),
                     run_before: Some("run_before_second".to_string()),
+                    ..Default::default()
                 })
                 .await
                 .unwrap();
@@ -120,6 +138,27 @@ mod tests {
                 .await
                 .unwrap();
 
+            orchestrator
+                .get_package_store()
+                .create_package_version(PackageVersionInsert::new(
+                    2,
+                    "oldver".to_string(),
+                    vec!["file0.tar".to_string()],
+                    Utc::now() - chrono::TimeDelta::hours(1),
+                ))
+                .await
+                .unwrap();
+            orchestrator
+                .get_package_store()
+                .create_package_version(PackageVersionInsert::new(
+                    2,
+                    "lastver".to_string(),
+                    vec!["file1.tar".to_string()],
+                    Utc::now(),
+                ))
+                .await
+                .unwrap();
+
             orchestrator
                 .get_package_store()
                 .create_patch(PackagePatchInsert {
@@ -130,6 +169,31 @@ mod tests {
                 .await
                 .unwrap();
 
+            let mut build = orchestrator
+                .get_package_store()
+                .create_build(BuildInsert::running(2, Some(0)))
+                .await
+                .unwrap();
+            build.version = Some("lastver".to_string());
+            build.log_file = Some("second-1.log".to_string());
+            build.finish(BuildStatus::BUILT, None);
+            orchestrator
+                .get_package_store()
+                .update_build(&build)
+                .await
+                .unwrap();
+            let mut build = orchestrator
+                .get_package_store()
+                .create_build(BuildInsert::running(2, Some(0)))
+                .await
+                .unwrap();
+            build.finish(BuildStatus::FAILED, Some("error".to_string()));
+            orchestrator
+                .get_package_store()
+                .update_build(&build)
+                .await
+                .unwrap();
+
             let state = HttpState {
                 orchestrator: Arc::new(RwLock::new(orchestrator)),
                 config: mutexed_config,
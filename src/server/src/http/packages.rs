@@ -1,23 +1,37 @@
 use crate::http::base::{HttpError, JsonResult, ResponseResult, SuccessResponse};
 use crate::http::HttpState;
 use crate::persistence::package_store::PackageInsert;
+use crate::repository::SUPPORTED_PKGEXTS;
+use crate::worker::build_logs::BuildLogEvent;
+use actix_multipart::form::tempfile::TempFile;
+use actix_multipart::form::text::Text;
+use actix_multipart::form::MultipartForm;
 use actix_web::http::StatusCode;
 use actix_web::web::{scope, Json};
 use actix_web::{web, HttpResponse, Scope};
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use common::http::payloads::{PackageRebuildPayload, UpdatePackagePayload, CreatePackagePayload};
 use common::http::responses::PackageResponse;
-use std::path::Component;
+use common::models::{BuildEnvironment, PackageRequirements, PackageSource, PackageStatus, ResourceLimits};
+use cron::Schedule;
+use futures_util::{stream, StreamExt};
+use log::{error, warn};
+use std::path::{Component, Path};
+use std::str::FromStr;
 use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
 
 pub fn register() -> Scope {
     scope("/packages")
         .route("", web::get().to(index))
         .route("", web::post().to(post))
         .route("/rebuild", web::post().to(rebuild))
+        .route("/upload", web::post().to(upload))
         .route("/{id}", web::patch().to(patch))
         .route("/{id}", web::delete().to(delete))
+        .route("/{id}/cancel", web::post().to(cancel))
         .route("/{id}/logs", web::get().to(action_logs))
+        .route("/{id}/logs/stream", web::get().to(action_logs_stream))
 }
 
 #[derive(Deserialize)]
@@ -47,15 +61,137 @@ async fn index(state: web::Data<HttpState>, query: web::Query<IndexQuery>) -> Js
     Ok(Json(packages.into_iter().map(Into::into).collect()))
 }
 
+/// Rejects requirements for an architecture the repository is not served for.
+async fn validate_requirements(state: &web::Data<HttpState>, requirements: &PackageRequirements) -> Result<(), HttpError> {
+    if let Some(arch) = requirements.arch.as_ref() {
+        if !state.config.read().await.architectures.contains(arch) {
+            return Err(HttpError::new(anyhow!("Unsupported architecture '{}'", arch), StatusCode::BAD_REQUEST));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects environment variable names makepkg would not accept and package extensions the repository cannot read.
+fn validate_build_env(build_env: &BuildEnvironment) -> Result<(), HttpError> {
+    if let Some(pkgext) = build_env.pkgext.as_ref() {
+        if !SUPPORTED_PKGEXTS.contains(&pkgext.as_str()) {
+            return Err(HttpError::new(anyhow!("Invalid package extension '{}'", pkgext), StatusCode::BAD_REQUEST));
+        }
+    }
+    for name in build_env.env.keys() {
+        let valid = name.chars().enumerate()
+            .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        if name.is_empty() || !valid {
+            return Err(HttpError::new(anyhow!("Invalid environment variable name '{}'", name), StatusCode::BAD_REQUEST));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects source paths reaching outside of the cloned repository or of the sources directory.
+fn validate_source(source: &PackageSource) -> Result<(), HttpError> {
+    let (path, max_components) = match source {
+        PackageSource::Git { subdirectory: Some(subdirectory), .. } => (subdirectory, usize::MAX),
+        PackageSource::Upload { file } => (file, 1),
+        _ => return Ok(()),
+    };
+    let components: Vec<Component> = Path::new(path).components().collect();
+    if components.is_empty() || components.len() > max_components || components.iter().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(HttpError::new(anyhow!("Invalid source path '{}'", path), StatusCode::BAD_REQUEST));
+    }
+    Ok(())
+}
+
+fn validate_limits(limits: &ResourceLimits) -> Result<(), HttpError> {
+    if limits.cpus.is_some_and(|cpus| cpus.is_nan() || cpus <= 0.0) {
+        return Err(HttpError::new(anyhow!("The CPU limit must be positive"), StatusCode::BAD_REQUEST));
+    }
+    Ok(())
+}
+
 async fn post(state: web::Data<HttpState>, body: Json<CreatePackagePayload>) -> JsonResult<PackageResponse> {
     let body = body.into_inner();
+    validate_requirements(&state, &body.requirements).await?;
+    validate_source(&body.source)?;
+    validate_build_env(&body.build_env)?;
+    validate_limits(&body.limits)?;
 
-    let package = state.orchestrator.write().await
-        .get_package_store()
+    let mut orchestrator = state.orchestrator.write().await;
+    let package_store = orchestrator.get_package_store();
+
+    // Dependencies created by the server become regular packages once added explicitly
+    if let Some(mut package) = package_store.get_package_by_name(&body.name).await?.filter(|p| p.implicit) {
+        package.implicit = false;
+        package.run_before = body.run_before;
+        package.keep_versions = body.keep_versions;
+        package.set_source(body.source);
+        package.set_requirements(body.requirements);
+        package.set_build_env(body.build_env);
+        package.set_limits(body.limits);
+        let package = package_store.update_package(&package).await?;
+        return Ok(Json(package.into()));
+    }
+
+    let package = package_store
         .create_package(PackageInsert {
             name: body.name,
             run_before: body.run_before,
-        }).await?;
+            keep_versions: body.keep_versions,
+            ..Default::default()
+        }.with_source(body.source).with_requirements(body.requirements).with_build_env(body.build_env).with_limits(body.limits)).await?;
+    Ok(Json(package.into()))
+}
+
+#[derive(Debug, MultipartForm)]
+struct UploadForm {
+    pub name: Text<String>,
+    pub run_before: Option<Text<String>>,
+
+    /// Gzipped tarball containing the PKGBUILD and its local files at its root.
+    pub file: TempFile,
+}
+
+fn is_valid_package_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['-', '.'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c))
+}
+
+async fn upload(state: web::Data<HttpState>, MultipartForm(form): MultipartForm<UploadForm>) -> JsonResult<PackageResponse> {
+    let name = form.name.into_inner();
+    if !is_valid_package_name(&name) {
+        return Err(HttpError::new(anyhow!("Invalid package name '{}'", name), StatusCode::BAD_REQUEST));
+    }
+
+    let sources_path = state.config.read().await.sources_path.clone();
+    tokio::fs::create_dir_all(&sources_path).await
+        .with_context(|| format!("Failed to create sources directory {:?}", sources_path))?;
+
+    let file_name = format!("{}.tar.gz", name);
+    tokio::fs::copy(form.file.file.path(), sources_path.join(&file_name)).await
+        .with_context(|| format!("Failed to store source for {}", name))?;
+    let source = PackageSource::Upload { file: file_name };
+    let run_before = form.run_before.map(|x| x.into_inner());
+
+    let mut orchestrator = state.orchestrator.write().await;
+    let package_store = orchestrator.get_package_store();
+    let package = match package_store.get_package_by_name(&name).await? {
+        Some(mut package) => {
+            package.set_source(source);
+            if run_before.is_some() {
+                package.run_before = run_before;
+            }
+            package_store.update_package(&package).await?;
+            package_store.set_packages_pending(Some(vec![package.get_id()]), true).await?;
+            package_store.get_package(package.get_id()).await?.ok_or(HttpError::not_found())?
+        }
+        None => package_store.create_package(PackageInsert {
+            name,
+            run_before,
+            ..Default::default()
+        }.with_source(source)).await?,
+    };
+
     Ok(Json(package.into()))
 }
 
@@ -75,9 +211,65 @@ async fn patch(state: web::Data<HttpState>, path: web::Path<i32>, body: Json<Upd
     let id = path.into_inner();
     let body = body.into_inner();
 
+    if let Some(Some(schedule)) = body.schedule.as_ref() {
+        if let Err(e) = Schedule::from_str(schedule) {
+            return Err(HttpError::new(anyhow!("Invalid schedule '{}': {}", schedule, e), StatusCode::BAD_REQUEST));
+        }
+    }
+    if let Some(source) = body.source.as_ref() {
+        validate_source(source)?;
+    }
+    if let Some(requirements) = body.requirements.as_ref() {
+        validate_requirements(&state, requirements).await?;
+    }
+    if let Some(build_env) = body.build_env.as_ref() {
+        validate_build_env(build_env)?;
+    }
+    if let Some(limits) = body.limits.as_ref() {
+        validate_limits(limits)?;
+    }
+
     let mut orchestrator = state.orchestrator.write().await;
     if let Some(mut package) = orchestrator.get_package_store().get_package(id).await? {
-        package.run_before = body.run_before;
+        if let Some(run_before) = body.run_before {
+            package.run_before = run_before;
+        }
+        if let Some(keep_versions) = body.keep_versions {
+            package.keep_versions = keep_versions;
+        }
+        if let Some(rebuild_interval) = body.rebuild_interval {
+            package.rebuild_interval = rebuild_interval;
+        }
+        if let Some(schedule) = body.schedule {
+            package.schedule = schedule;
+        }
+        if let Some(build_timeout) = body.build_timeout {
+            package.build_timeout = build_timeout;
+        }
+        if let Some(source) = body.source {
+            package.set_source(source);
+        }
+        if let Some(priority) = body.priority {
+            package.priority = priority;
+        }
+        if let Some(compiler_cache) = body.compiler_cache {
+            package.compiler_cache = compiler_cache;
+        }
+        if let Some(allow_network) = body.allow_network {
+            package.allow_network = allow_network;
+        }
+        if let Some(enabled) = body.enabled {
+            package.enabled = enabled;
+        }
+        if let Some(requirements) = body.requirements {
+            package.set_requirements(requirements);
+        }
+        if let Some(build_env) = body.build_env {
+            package.set_build_env(build_env);
+        }
+        if let Some(limits) = body.limits {
+            package.set_limits(limits);
+        }
         orchestrator.get_package_store().update_package(&package).await?;
         return Ok(Json(package.into()));
     }
@@ -85,17 +277,41 @@ async fn patch(state: web::Data<HttpState>, path: web::Path<i32>, body: Json<Upd
     Err(HttpError::not_found())
 }
 
-async fn delete(state: web::Data<HttpState>, id: web::Path<i32>) -> JsonResult<SuccessResponse> {
+#[derive(Deserialize)]
+struct DeleteQuery {
+    /// Keeps the package files on disk, only removing the package from the repository database.
+    pub keep_files: Option<bool>,
+}
+
+async fn delete(state: web::Data<HttpState>, id: web::Path<i32>, query: web::Query<DeleteQuery>) -> JsonResult<SuccessResponse> {
     let res = state
         .orchestrator
         .write()
         .await
-        .get_package_store()
-        .delete_package(id.into_inner())
+        .delete_package(id.into_inner(), query.keep_files.unwrap_or(false))
         .await;
+    if let Err(e) = &res {
+        error!("Failed to delete package: {}", e);
+    }
     Ok(Json(SuccessResponse::from(res.is_ok())))
 }
 
+async fn cancel(state: web::Data<HttpState>, path: web::Path<i32>) -> JsonResult<PackageResponse> {
+    let id = path.into_inner();
+
+    let mut orchestrator = state.orchestrator.write().await;
+    match orchestrator.get_package_store().get_package(id).await? {
+        None => return Err(HttpError::not_found()),
+        Some(package) if package.get_status() != PackageStatus::BUILDING => {
+            return Err(HttpError::new(anyhow!("Package {} is not building", package.get_name()), StatusCode::CONFLICT));
+        }
+        Some(_) => {}
+    }
+
+    let package = orchestrator.cancel_package(id).await?.ok_or(HttpError::not_found())?;
+    Ok(Json(package.into()))
+}
+
 async fn action_logs(
     state: web::Data<HttpState>,
     id: web::Path<i32>,
@@ -126,12 +342,68 @@ async fn action_logs(
     Err(HttpError::not_found())
 }
 
+/// Formats a server-sent event. Each line of the data is sent in its own field.
+fn sse_event(event: Option<&str>, data: &str) -> web::Bytes {
+    let mut message = String::new();
+    if let Some(event) = event {
+        message.push_str(&format!("event: {}\n", event));
+    }
+    for line in data.split('\n') {
+        message.push_str(&format!("data: {}\n", line));
+    }
+    message.push('\n');
+    web::Bytes::from(message)
+}
+
+/// Streams the logs of the running build of a package as server-sent events, ending with an `end` event.
+async fn action_logs_stream(state: web::Data<HttpState>, id: web::Path<i32>) -> ResponseResult {
+    let id = id.into_inner();
+
+    let (buffered, receiver) = {
+        let mut orchestrator = state.orchestrator.write().await;
+        let Some(package) = orchestrator.get_package_store().get_package(id).await? else {
+            return Err(HttpError::not_found());
+        };
+        if package.get_status() != PackageStatus::BUILDING {
+            return Err(HttpError::new(anyhow!("Package {} is not building", package.get_name()), StatusCode::CONFLICT));
+        }
+        orchestrator.get_worker_manager().get_build_logs().subscribe(id)
+    };
+
+    let buffered = stream::iter(
+        (!buffered.is_empty()).then(|| Ok::<_, actix_web::Error>(sse_event(None, &buffered)))
+    );
+    let live = stream::unfold(Some(receiver), move |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok((package_id, _)) if package_id != id => continue,
+                Ok((_, BuildLogEvent::Content(content))) => {
+                    return Some((Ok(sse_event(None, &content)), Some(receiver)));
+                }
+                Ok((_, BuildLogEvent::End)) | Err(RecvError::Closed) => {
+                    return Some((Ok(sse_event(Some("end"), "")), None));
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("Log stream of package {} skipped {} chunks", id, skipped),
+            }
+        }
+    });
+
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(buffered.chain(live))
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use actix_web::test;
     use tokio::io::AsyncWriteExt;
-    use common::models::PackageStatus;
+    use std::collections::BTreeMap;
+    use common::models::{BuildEnvironment, PackageRequirements, PackageSource, PackageStatus, ResourceLimits};
     use crate::get_test_app;
 
     #[actix_web::test]
@@ -159,6 +431,19 @@ mod tests {
             .set_json(CreatePackagePayload {
                 name: "test-insert".to_string(),
                 run_before: Some("testrun".to_string()),
+                keep_versions: Some(3),
+                source: PackageSource::Git {
+                    url: "https://example.com/test-insert.git".to_string(),
+                    reference: Some("v1.0".to_string()),
+                    subdirectory: None,
+                },
+                requirements: PackageRequirements {
+                    arch: Some("x86_64".to_string()),
+                    min_cpus: Some(4),
+                    ..Default::default()
+                },
+                build_env: Default::default(),
+                limits: Default::default(),
             })
             .to_request();
         let resp = test::call_service(&app, req).await;
@@ -169,10 +454,50 @@ mod tests {
         let parsed: PackageResponse = serde_json::from_slice(&body).unwrap();
         assert_eq!(parsed.name, "test-insert");
         assert_eq!(parsed.run_before, Some("testrun".to_string()));
+        assert_eq!(parsed.keep_versions, Some(3));
+        assert_eq!(parsed.source, PackageSource::Git {
+            url: "https://example.com/test-insert.git".to_string(),
+            reference: Some("v1.0".to_string()),
+            subdirectory: None,
+        });
         assert_eq!(parsed.status, PackageStatus::PENDING);
+        assert_eq!(parsed.requirements.min_cpus, Some(4));
 
         let packages = state.orchestrator.write().await.get_package_store().get_packages().await.unwrap();
         assert_eq!(packages.len(), 3);
+
+        for subdirectory in ["/etc", "../other", "pkg/../../other", ""] {
+            let req = test::TestRequest::post()
+                .insert_header(("Authorization", "api_key"))
+                .uri("/api/packages")
+                .set_json(CreatePackagePayload {
+                    name: "test-subdirectory".to_string(),
+                    run_before: None,
+                    keep_versions: None,
+                    source: PackageSource::Git {
+                        url: "https://example.com/test-insert.git".to_string(),
+                        reference: None,
+                        subdirectory: Some(subdirectory.to_string()),
+                    },
+                    requirements: Default::default(),
+                    build_env: Default::default(),
+                    limits: Default::default(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400, "{}", subdirectory);
+        }
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(UpdatePackagePayload {
+                source: Some(PackageSource::Upload { file: "../config_server.json".to_string() }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
     }
 
     #[actix_web::test]
@@ -207,7 +532,30 @@ mod tests {
             .insert_header(("Authorization", "api_key"))
             .uri("/api/packages/1")
             .set_json(UpdatePackagePayload {
-                run_before: Some("run_before_update".to_string()),
+                run_before: Some(Some("run_before_update".to_string())),
+                keep_versions: None,
+                source: Some(PackageSource::Local { path: "/srv/pkgbuilds/first".to_string() }),
+                priority: Some(10),
+                rebuild_interval: None,
+                schedule: Some(Some("0 0 3 * * *".to_string())),
+                build_timeout: Some(Some(7200)),
+                compiler_cache: Some(true),
+                allow_network: Some(true),
+                enabled: Some(false),
+                requirements: Some(PackageRequirements {
+                    tags: vec!["fast".to_string()],
+                    ..Default::default()
+                }),
+                build_env: Some(BuildEnvironment {
+                    makeflags: Some("-j8".to_string()),
+                    options: vec!["!debug".to_string()],
+                    env: BTreeMap::from([("GOFLAGS".to_string(), "-mod=vendor".to_string())]),
+                    ..Default::default()
+                }),
+                limits: Some(ResourceLimits {
+                    memory: Some(4096),
+                    ..Default::default()
+                }),
             })
             .to_request();
         let resp = test::call_service(&app, req).await;
@@ -219,6 +567,181 @@ mod tests {
         assert_eq!(parsed.run_before, Some("run_before_update".to_string()));
         let package = state.orchestrator.write().await.get_package_store().get_package(1).await.unwrap().unwrap();
         assert_eq!(package.run_before, Some("run_before_update".to_string()));
+        assert_eq!(package.get_source(), PackageSource::Local { path: "/srv/pkgbuilds/first".to_string() });
+        assert_eq!(package.priority, 10);
+        assert_eq!(package.schedule, Some("0 0 3 * * *".to_string()));
+        assert_eq!(package.build_timeout, Some(7200));
+        assert!(package.compiler_cache);
+        assert!(package.allow_network);
+        assert!(!package.enabled);
+        assert_eq!(package.get_requirements().tags, vec!["fast".to_string()]);
+        assert_eq!(package.get_build_env().makeflags, Some("-j8".to_string()));
+        assert_eq!(package.get_build_env().options, vec!["!debug".to_string()]);
+        assert_eq!(package.get_package_job(vec![]).definition.build_env.env["GOFLAGS"], "-mod=vendor");
+        assert_eq!(package.get_limits().memory, Some(4096));
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(serde_json::json!({ "run_before": "run_before_partial" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let package = state.orchestrator.write().await.get_package_store().get_package(1).await.unwrap().unwrap();
+        assert_eq!(package.run_before, Some("run_before_partial".to_string()));
+        assert_eq!(package.schedule, Some("0 0 3 * * *".to_string()));
+        assert_eq!(package.build_timeout, Some(7200));
+        assert_eq!(package.get_source(), PackageSource::Local { path: "/srv/pkgbuilds/first".to_string() });
+        assert_eq!(package.priority, 10);
+        assert!(package.compiler_cache);
+        assert!(package.allow_network);
+        assert!(!package.enabled);
+        assert_eq!(package.get_requirements().tags, vec!["fast".to_string()]);
+        assert_eq!(package.get_build_env().makeflags, Some("-j8".to_string()));
+        assert_eq!(package.get_limits().memory, Some(4096));
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(serde_json::json!({ "schedule": null }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let package = state.orchestrator.write().await.get_package_store().get_package(1).await.unwrap().unwrap();
+        assert_eq!(package.schedule, None);
+        assert_eq!(package.build_timeout, Some(7200));
+        assert_eq!(package.run_before, Some("run_before_partial".to_string()));
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(UpdatePackagePayload {
+                schedule: Some(Some("every night".to_string())),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(UpdatePackagePayload {
+                requirements: Some(PackageRequirements {
+                    arch: Some("riscv64".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(UpdatePackagePayload {
+                build_env: Some(BuildEnvironment {
+                    env: BTreeMap::from([("1FOO=".to_string(), "bar".to_string())]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::patch()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1")
+            .set_json(UpdatePackagePayload {
+                build_env: Some(BuildEnvironment {
+                    pkgext: Some(".pkg.tar.lz4".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_cancel_packages() {
+        let (app, state) = get_test_app!();
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/cancel")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+
+        {
+            let mut orchestrator = state.orchestrator.write().await;
+            let mut package = orchestrator.get_package_store().get_package(1).await.unwrap().unwrap();
+            package.set_status(PackageStatus::BUILDING);
+            orchestrator.get_package_store().update_package(&package).await.unwrap();
+        }
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/cancel")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let parsed: PackageResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.status, PackageStatus::FAILED);
+        assert_eq!(parsed.last_error, Some("Build was cancelled".to_string()));
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/100/cancel")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_logs_stream_packages() {
+        let (app, state) = get_test_app!();
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/logs/stream")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+
+        let build_logs = {
+            let mut orchestrator = state.orchestrator.write().await;
+            let mut package = orchestrator.get_package_store().get_package(1).await.unwrap().unwrap();
+            package.set_status(PackageStatus::BUILDING);
+            orchestrator.get_package_store().update_package(&package).await.unwrap();
+            orchestrator.get_worker_manager().get_build_logs().clone()
+        };
+        build_logs.start(1);
+        build_logs.push(1, "==> Making package\n".to_string());
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/logs/stream")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        build_logs.push(2, "other package".to_string());
+        build_logs.push(1, "==> Finished".to_string());
+        build_logs.finish(1);
+
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            "data: ==> Making package\ndata: \n\ndata: ==> Finished\n\nevent: end\ndata: \n\n",
+            String::from_utf8(body.to_vec()).unwrap()
+        );
     }
 
     #[actix_web::test]
@@ -265,4 +788,69 @@ mod tests {
 
         assert_eq!(String::from_utf8(body.to_vec()).unwrap(), "test file content");
     }
-}
\ No newline at end of file
+
+    fn get_upload_request(name: &str, content: &[u8]) -> test::TestRequest {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--boundary\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\n");
+        body.extend_from_slice(name.as_bytes());
+        body.extend_from_slice(b"\r\n--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"source.tar.gz\"\r\n");
+        body.extend_from_slice(b"Content-Type: application/gzip\r\n\r\n");
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n--boundary--\r\n");
+
+        test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+            .uri("/api/packages/upload")
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn test_upload_packages() {
+        let (app, state) = get_test_app!();
+        let sources_path = state.config.read().await.sources_path.clone();
+
+        let resp = test::call_service(&app, get_upload_request("uploaded", b"new source").to_request()).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let parsed: PackageResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.name, "uploaded");
+        assert_eq!(parsed.status, PackageStatus::PENDING);
+        assert_eq!(parsed.source, PackageSource::Upload { file: "uploaded.tar.gz".to_string() });
+        assert_eq!(tokio::fs::read(sources_path.join("uploaded.tar.gz")).await.unwrap(), b"new source");
+
+        let resp = test::call_service(&app, get_upload_request("second", b"second source").to_request()).await;
+        assert!(resp.status().is_success());
+        let package = state.orchestrator.write().await.get_package_store().get_package(2).await.unwrap().unwrap();
+        assert_eq!(package.get_source(), PackageSource::Upload { file: "second.tar.gz".to_string() });
+        assert_eq!(package.get_status(), PackageStatus::PENDING);
+        assert_eq!(package.run_before, Some("run_before_second".to_string()));
+        assert!(package.last_built_version.is_none());
+
+        let resp = test::call_service(&app, get_upload_request("../second", b"").to_request()).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api_workers/sources/uploaded.tar.gz")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(test::read_body(resp).await.to_vec(), b"new source");
+
+        let req = test::TestRequest::delete()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2?keep_files=true")
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        assert!(sources_path.join("second.tar.gz").exists());
+
+        let id = state.orchestrator.write().await.get_package_store().get_package_by_name("uploaded").await.unwrap().unwrap().get_id();
+        let req = test::TestRequest::delete()
+            .insert_header(("Authorization", "api_key"))
+            .uri(format!("/api/packages/{}", id).as_str())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        assert!(!sources_path.join("uploaded.tar.gz").exists());
+    }
+}
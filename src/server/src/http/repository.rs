@@ -0,0 +1,69 @@
+use crate::http::base::JsonResult;
+use crate::http::HttpState;
+use actix_web::web::{scope, Json};
+use actix_web::{web, Scope};
+use common::http::payloads::RepositoryCheckPayload;
+use common::http::responses::RepositoryCheckResponse;
+
+pub fn register() -> Scope
+{
+    scope("/repository")
+        .route("/check", web::post().to(check))
+}
+
+async fn check(state: web::Data<HttpState>, body: Json<RepositoryCheckPayload>) -> JsonResult<RepositoryCheckResponse> {
+    let report = state.orchestrator.write().await
+        .check_repository(body.fix.unwrap_or(false))
+        .await?;
+
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+    use common::http::payloads::RepositoryCheckPayload;
+    use common::http::responses::RepositoryCheckResponse;
+    use serial_test::serial;
+    use crate::get_test_app;
+
+    #[actix_web::test]
+    #[serial]
+    async fn test_check_repository() {
+        let (app, state) = get_test_app!();
+        let path = state.config.read().await.serve_path.join("test/os/x86_64");
+        tokio::fs::write(path.join("orphan-1.0-1-x86_64.pkg.tar.zst"), "").await.unwrap();
+        tokio::fs::write(path.join("gone-1.0-1-x86_64.pkg.tar.zst.sig"), "").await.unwrap();
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/repository/check")
+            .set_json(RepositoryCheckPayload { fix: Some(false) })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: RepositoryCheckResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vec!["x86_64/file1.tar".to_string()], parsed.missing_files);
+        assert!(parsed.orphan_files.contains(&"x86_64/orphan-1.0-1-x86_64.pkg.tar.zst".to_string()));
+        assert!(parsed.stale_signatures.contains(&"x86_64/gone-1.0-1-x86_64.pkg.tar.zst.sig".to_string()));
+        assert!(!parsed.fixed);
+        assert!(path.join("orphan-1.0-1-x86_64.pkg.tar.zst").exists());
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/repository/check")
+            .set_json(RepositoryCheckPayload { fix: Some(true) })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: RepositoryCheckResponse = serde_json::from_slice(&body).unwrap();
+        assert!(parsed.fixed);
+        assert_eq!(vec!["second".to_string()], parsed.rebuilt_packages);
+        assert!(!path.join("orphan-1.0-1-x86_64.pkg.tar.zst").exists());
+        assert!(!path.join("gone-1.0-1-x86_64.pkg.tar.zst.sig").exists());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::http::base::{HttpError, JsonResult};
+use crate::http::HttpState;
+use actix_web::web::{scope, Json};
+use actix_web::{web, Scope};
+use common::http::responses::{PackageResponse, PackageVersionResponse};
+
+pub fn register() -> Scope
+{
+    scope("/packages/{package_id}/versions")
+        .route("", web::get().to(index))
+        .route("/{id}/rollback", web::post().to(rollback))
+}
+
+async fn index(state: web::Data<HttpState>, path: web::Path<i32>) -> JsonResult<Vec<PackageVersionResponse>> {
+    let versions = state.orchestrator.write().await
+        .get_package_store()
+        .get_package_versions(path.into_inner())
+        .await?;
+
+    Ok(Json(versions.into_iter().map(Into::into).collect()))
+}
+
+async fn rollback(state: web::Data<HttpState>, path: web::Path<(i32, i32)>) -> JsonResult<PackageResponse> {
+    let (package_id, version_id) = path.into_inner();
+
+    if let Some(package) = state.orchestrator.write().await
+        .rollback_package(package_id, version_id).await? {
+        return Ok(Json(package.into()));
+    }
+
+    Err(HttpError::not_found())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+    use common::http::responses::PackageVersionResponse;
+    use crate::get_test_app;
+
+    #[actix_web::test]
+    async fn test_index_versions() {
+        let (app, _) = get_test_app!();
+        let req = test::TestRequest::get()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/versions")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let body = test::read_body(resp).await;
+        let parsed: Vec<PackageVersionResponse> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].version, "lastver");
+        assert_eq!(parsed[1].version, "oldver");
+        assert_eq!(parsed[1].files, vec!["file0.tar".to_string()]);
+    }
+
+    #[actix_web::test]
+    async fn test_rollback_versions() {
+        let (app, state) = get_test_app!();
+
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/1/versions/1/rollback")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        // Files of the retained version are not present in the repository
+        let req = test::TestRequest::post()
+            .insert_header(("Authorization", "api_key"))
+            .uri("/api/packages/2/versions/1/rollback")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(!resp.status().is_success());
+
+        let package = state.orchestrator.write().await.get_package_store().get_package(2).await.unwrap().unwrap();
+        assert_eq!(package.last_built_version, Some("lastver".to_string()));
+        assert_eq!(package.get_files(), &vec!["file1.tar".to_string()]);
+    }
+}
@@ -1,3 +1,4 @@
+mod aur;
 mod http;
 mod models;
 mod orchestrator;
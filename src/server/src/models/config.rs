@@ -6,6 +6,7 @@ use clap::builder::TypedValueParser;
 use clap::Parser;
 use log::LevelFilter;
 use serde::Deserialize;
+use common::models::BuildErrorClass;
 
 macro_rules! merge_config_option {
     ($a:expr, $b:expr, $f: ident) => {
@@ -48,22 +49,52 @@ struct SharedConfig {
     /// Name of the Arch repo to create and serve
     #[clap(short = 'r', long)]
     pub repo_name: Option<String>,
+    /// Architectures to build and serve packages for, packages without a required architecture use the first one. Default: 'x86_64'
+    #[clap(long, value_delimiter = ',')]
+    pub architectures: Option<Vec<String>>,
     /// ID of the GPG key used to sign the packages
     #[clap(short = 's', long)]
     pub sign_key: Option<String>,
     /// The time in seconds between rebuild attempts
     #[clap(short = 't', long)]
     pub rebuild_time: Option<u64>,
+    /// The time in seconds after which a running build is cancelled
+    #[clap(long)]
+    pub build_timeout: Option<u64>,
+    /// The time in seconds between checks of the AUR for package updates. Only updated AUR packages are rebuilt when set
+    #[clap(long)]
+    pub update_check_time: Option<u64>,
+    /// Base URL of the AUR RPC used to check for updates. Default: 'https://aur.archlinux.org/rpc'
+    #[clap(long)]
+    pub aur_rpc_url: Option<String>,
+    /// Amount of built versions to keep in the repository for each package. Default: '1'
+    #[clap(long)]
+    pub keep_versions: Option<u32>,
+    /// Maximum amount of automatic retries of a failed build. Default: '3'
+    #[clap(long)]
+    pub retry_max_attempts: Option<u32>,
+    /// The time in seconds before the first retry of a failed build, doubled on each following retry. Default: '60'
+    #[clap(long)]
+    pub retry_backoff_time: Option<u64>,
+    /// Classes of build errors that are retried. Default: 'network,source,aur_rpc'
+    #[clap(long, value_delimiter = ',')]
+    pub retry_error_classes: Option<Vec<BuildErrorClass>>,
 
     #[clap(long, value_hint = clap::ValueHint::DirPath)]
     /// Path to store built packages and serve them. Default: './server/serve'
     pub serve_path: Option<PathBuf>,
+    /// Move the packages served from the root of serve_path by older versions to the per architecture layout. Default: 'false'
+    #[clap(long)]
+    pub migrate_legacy_layout: Option<bool>,
     /// Path to store built packages and serve them. Default: './server/build_logs'
     #[clap(long, value_hint = clap::ValueHint::DirPath)]
     pub build_logs_path: Option<PathBuf>,
     /// Path to store database. Default: './server/aur-build.sqlite'
     #[clap(short = 'd', long, value_hint = clap::ValueHint::DirPath)]
     pub database_path: Option<PathBuf>,
+    /// Path to store uploaded package sources. Default: './server/sources'
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    pub sources_path: Option<PathBuf>,
 
     #[clap(skip)]
     pub webhooks: Option<Vec<String>>,
@@ -109,12 +140,22 @@ pub struct Config {
     pub port: u16,
 
     pub repo_name: String,
+    pub architectures: Vec<String>,
     pub sign_key: Option<String>,
     pub rebuild_time: Option<u64>,
+    pub build_timeout: Option<u64>,
+    pub update_check_time: Option<u64>,
+    pub aur_rpc_url: String,
+    pub keep_versions: u32,
+    pub retry_max_attempts: u32,
+    pub retry_backoff_time: u64,
+    pub retry_error_classes: Vec<BuildErrorClass>,
 
     pub serve_path: PathBuf,
+    pub migrate_legacy_layout: bool,
     pub build_logs_path: PathBuf,
     pub database_path: PathBuf,
+    pub sources_path: PathBuf,
 
     pub webhooks: Vec<String>,
     pub webhook_verify_ssl: bool,
@@ -137,12 +178,26 @@ impl Config {
             port: cli_config.port.unwrap_or(file_config.port.unwrap_or(8888)),
 
             repo_name: cli_config.repo_name.unwrap_or(file_config.repo_name.unwrap_or(String::from("aurbuild"))),
+            architectures: cli_config.architectures.unwrap_or(file_config.architectures.unwrap_or(vec![String::from("x86_64")])),
             sign_key: merge_config_option!(cli_config, file_config, sign_key),
             rebuild_time: merge_config_option!(cli_config, file_config, rebuild_time),
+            build_timeout: merge_config_option!(cli_config, file_config, build_timeout),
+            update_check_time: merge_config_option!(cli_config, file_config, update_check_time),
+            aur_rpc_url: cli_config.aur_rpc_url.unwrap_or(file_config.aur_rpc_url.unwrap_or(String::from("https://aur.archlinux.org/rpc"))),
+            keep_versions: cli_config.keep_versions.unwrap_or(file_config.keep_versions.unwrap_or(1)),
+            retry_max_attempts: cli_config.retry_max_attempts.unwrap_or(file_config.retry_max_attempts.unwrap_or(3)),
+            retry_backoff_time: cli_config.retry_backoff_time.unwrap_or(file_config.retry_backoff_time.unwrap_or(60)),
+            retry_error_classes: cli_config.retry_error_classes.unwrap_or(file_config.retry_error_classes.unwrap_or(vec![
+                BuildErrorClass::Network,
+                BuildErrorClass::Source,
+                BuildErrorClass::AurRpc,
+            ])),
 
             serve_path: cli_config.serve_path.unwrap_or(file_config.serve_path.unwrap_or(PathBuf::from("./server/serve"))),
+            migrate_legacy_layout: cli_config.migrate_legacy_layout.unwrap_or(file_config.migrate_legacy_layout.unwrap_or(false)),
             build_logs_path: cli_config.build_logs_path.unwrap_or(file_config.build_logs_path.unwrap_or(PathBuf::from("./server/build_logs"))),
             database_path: cli_config.database_path.unwrap_or(file_config.database_path.unwrap_or(PathBuf::from("./server/aur_build.sqlite"))),
+            sources_path: cli_config.sources_path.unwrap_or(file_config.sources_path.unwrap_or(PathBuf::from("./server/sources"))),
 
             webhooks: cli_config.webhooks.unwrap_or(file_config.webhooks.unwrap_or_default()),
             webhook_verify_ssl: cli_config.webhook_verify_ssl.unwrap_or(file_config.webhook_verify_ssl.unwrap_or(true)),
@@ -1,15 +1,18 @@
+use crate::aur::{is_vcs_package, AurClient, AurInfo};
 use crate::models::config::Config;
-use crate::persistence::package_store::{Package, PackageInsert, PackagePatchInsert, PackageStore};
-use crate::repository::Repository;
+use crate::persistence::package_store::{BuildInsert, Package, PackageInsert, PackagePatchInsert, PackageStore, PackageVersionInsert};
+use crate::repository::{DependencyOutput, Repository};
 use crate::webhooks::WebhookManager;
 use crate::worker::worker_manager::{WorkerDispatchResult, WorkerManager};
-use anyhow::Result;
-use common::models::PackageStatus;
-use log::{error, info};
+use anyhow::{bail, Result};
+use common::http::responses::RepositoryCheckResponse;
+use common::models::{BuildErrorClass, BuildStatus, CompilerCacheStats, PackageSource, PackageStatus};
+use log::{debug, error, info, warn};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use actix_multipart::form::tempfile::TempFile;
 use actix_ws::{AggregatedMessageStream, Session};
+use chrono::{DateTime, TimeDelta, Utc};
 use tokio::sync::RwLock;
 use tokio::time::sleep;
 use crate::worker::worker::Worker;
@@ -25,6 +28,7 @@ pub async fn migrate_legacy_package_config(
             match package_store.create_package(PackageInsert {
                 name: legacy_package.name.clone(),
                 run_before: legacy_package.run_before.clone(),
+                ..Default::default()
             }).await {
                 Ok(new_package) => {
                     info!("Imported {} from legacy", legacy_package.name);
@@ -46,6 +50,54 @@ pub async fn migrate_legacy_package_config(
     Ok(())
 }
 
+/// Error reported by a worker for a failed build.
+pub struct BuildFailure {
+    pub message: String,
+    pub class: BuildErrorClass,
+}
+
+/// Metadata reported by a worker along with the outcome of a build.
+#[derive(Default)]
+pub struct BuildReport {
+    /// Package base of the package, telling its files apart from the ones of its dependencies.
+    pub package_base: Option<String>,
+    /// Names of the depends and makedepends of the package, only recorded for successful builds.
+    pub dependencies: Vec<String>,
+    pub compiler_cache: Option<CompilerCacheStats>,
+}
+
+/// Which failed builds are retried automatically and when.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff_time: u64,
+    pub error_classes: Vec<BuildErrorClass>,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts,
+            backoff_time: config.retry_backoff_time,
+            error_classes: config.retry_error_classes.clone(),
+        }
+    }
+
+    /// Time to wait before the given retry attempt, starting at 1, doubled on each attempt.
+    pub fn get_backoff(&self, attempt: u32) -> TimeDelta {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        TimeDelta::seconds(self.backoff_time.saturating_mul(factor).min(i64::MAX as u64 / 1000) as i64)
+    }
+
+    /// Next retry time of a package that failed with the given class, None if it should not be retried.
+    pub fn get_next_retry(&self, package: &Package, class: BuildErrorClass, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !self.error_classes.contains(&class) || package.retry_count as u32 >= self.max_attempts {
+            return None;
+        }
+        Some(now + self.get_backoff(package.retry_count as u32 + 1))
+    }
+}
+
 pub struct Orchestrator {
     worker_manager: WorkerManager,
     webhook_manager: WebhookManager,
@@ -53,14 +105,30 @@ pub struct Orchestrator {
 
     package_store: PackageStore,
     rebuild_interval: Option<u64>,
+    build_timeout: Option<u64>,
+    keep_versions: u32,
+    default_arch: String,
+    retry_policy: RetryPolicy,
+    aur_client: AurClient,
+    update_check_interval: Option<u64>,
+    last_update_check: Option<DateTime<Utc>>,
     is_running: Arc<AtomicBool>,
 }
 
 impl Orchestrator {
     pub async fn new(config: Arc<RwLock<Config>>) -> Result<Orchestrator> {
-        let (database_path, rebuild_interval) = {
+        let (database_path, rebuild_interval, build_timeout, keep_versions, default_arch, retry_policy, update_check_interval, aur_rpc_url) = {
             let config = config.read().await;
-            (config.database_path.clone(), config.rebuild_time.clone())
+            (
+                config.database_path.clone(),
+                config.rebuild_time.clone(),
+                config.build_timeout,
+                config.keep_versions,
+                config.architectures.first().cloned().unwrap_or(String::from("x86_64")),
+                RetryPolicy::from_config(&config),
+                config.update_check_time,
+                config.aur_rpc_url.clone(),
+            )
         };
 
         let should_migrate_packages =  !database_path.exists();
@@ -80,6 +148,13 @@ impl Orchestrator {
             package_store,
 
             rebuild_interval,
+            build_timeout,
+            keep_versions,
+            default_arch,
+            retry_policy,
+            aur_client: AurClient::new(aur_rpc_url),
+            update_check_interval,
+            last_update_check: None,
             is_running: Arc::new(AtomicBool::from(false)),
         })
     }
@@ -126,21 +201,163 @@ impl Orchestrator {
                     current_job.definition.package_id, e
                 );
             };
+            self.worker_manager.get_build_logs().finish(current_job.definition.package_id);
+            if let Err(e) = self.cancel_running_build(
+                current_job.definition.package_id,
+                "Worker was removed before the build finished".to_string(),
+            ).await {
+                error!(
+                    "Failed to cancel running build for {}: '{}'",
+                    current_job.definition.package_id, e
+                );
+            }
         }
     }
 
+    async fn cancel_running_build(&mut self, package_id: i32, reason: String) -> Result<()> {
+        if let Some(mut build) = self.package_store.get_running_build(package_id).await? {
+            build.finish(BuildStatus::CANCELLED, Some(reason));
+            self.package_store.update_build(&build).await?;
+        }
+        Ok(())
+    }
+
+    /// Stops the running build of the package on its worker and marks the package as failed with the given reason.
+    async fn stop_package_build(
+        &mut self,
+        package: &mut Package,
+        status: BuildStatus,
+        reason: String,
+        error_class: Option<BuildErrorClass>,
+    ) -> Result<()> {
+        if !self.worker_manager.cancel_job(package.get_id()).await? {
+            warn!("No worker is building {}, only updating its status", package.get_name());
+        }
+        self.worker_manager.get_build_logs().finish(package.get_id());
+
+        package.set_status(PackageStatus::FAILED);
+        package.last_error = Some(reason.clone());
+        self.schedule_retry(package, error_class);
+        self.package_store.update_package(package).await?;
+
+        if let Some(mut build) = self.package_store.get_running_build(package.get_id()).await? {
+            build.set_error_class(error_class);
+            build.finish(status, Some(reason));
+            self.package_store.update_build(&build).await?;
+        }
+        Ok(())
+    }
+
+    /// Cancels the running build of the package. Returns None if the package does not exist.
+    pub async fn cancel_package(&mut self, package_id: i32) -> Result<Option<Package>> {
+        let Some(mut package) = self.package_store.get_package(package_id).await? else {
+            return Ok(None);
+        };
+        if package.get_status() != PackageStatus::BUILDING {
+            bail!("Package {} is not building", package.get_name());
+        }
+
+        info!("Cancelling build of {}", package.get_name());
+        self.stop_package_build(&mut package, BuildStatus::CANCELLED, "Build was cancelled".to_string(), None).await?;
+
+        Ok(Some(package))
+    }
+
+    /// Cancels the builds running for longer than the timeout of their package or the global build timeout.
+    async fn cancel_timed_out_builds(&mut self) -> Result<()> {
+        let now = Utc::now();
+        for mut package in self.package_store.get_packages().await? {
+            if package.get_status() != PackageStatus::BUILDING {
+                continue;
+            }
+            let Some(timeout) = package.build_timeout.or(self.build_timeout.map(|t| t as i64)).filter(|t| *t > 0) else {
+                continue;
+            };
+            let Some(build) = self.package_store.get_running_build(package.get_id()).await? else {
+                continue;
+            };
+            if now - build.get_started_at() <= TimeDelta::seconds(timeout) {
+                continue;
+            }
+
+            warn!("Build of {} timed out after {}s, cancelling", package.get_name(), timeout);
+            self.stop_package_build(
+                &mut package,
+                BuildStatus::FAILED,
+                format!("Build timed out after {}s", timeout),
+                Some(BuildErrorClass::Timeout),
+            ).await?;
+        }
+        Ok(())
+    }
+
     pub async fn handle_package_build_output(
         &mut self,
         package_name: String,
         version: Option<String>,
-        error: Option<String>,
+        failure: Option<BuildFailure>,
+        report: BuildReport,
         log_files: Vec<TempFile>,
         files: Vec<TempFile>,
     ) -> Result<()> {
         if let Some(mut package) = self.package_store.get_package_by_name(&package_name).await? {
+            self.worker_manager.get_build_logs().finish(package.get_id());
+            let mut build = match self.package_store.get_running_build(package.get_id()).await? {
+                Some(build) => build,
+                None => self.package_store.create_build(BuildInsert::running(package.get_id(), None)).await?,
+            };
+
             let last_version = package.last_built_version.clone();
-            self.repository.handle_package_build_output(&mut package, version, error, log_files, files).await?;
+            let previous_version = package.last_built_version.clone();
+            let previous_files = package.get_files().clone();
+            let has_files = !files.is_empty();
+            if version.is_some() {
+                package.set_dependencies(report.dependencies);
+            }
+            let error = failure.as_ref().map(|f| f.message.clone());
+            let explicit_packages: Vec<String> = self.package_store.get_packages().await?.into_iter()
+                .filter(|p| !p.implicit && p.get_id() != package.get_id())
+                .map(|p| p.get_name().clone())
+                .collect();
+            self.repository.store_build_logs(log_files).await?;
+            let dependencies = self.repository.handle_package_build_output(
+                &mut package, report.package_base, &explicit_packages, version.clone(), error, files
+            ).await?;
+            let error_class = match package.get_status() {
+                PackageStatus::FAILED => Some(failure.map(|f| f.class).unwrap_or(BuildErrorClass::Unknown)),
+                _ => None,
+            };
+            self.schedule_retry(&mut package, error_class);
             self.package_store.update_package(&package).await?;
+
+            let status = match package.get_status() {
+                PackageStatus::FAILED => BuildStatus::FAILED,
+                _ if has_files => BuildStatus::BUILT,
+                _ => BuildStatus::SKIPPED,
+            };
+            build.version = version;
+            build.set_error_class(error_class);
+            build.set_compiler_cache(report.compiler_cache);
+            build.finish(status, package.last_error.clone());
+            build.log_file = self.repository.archive_build_log(&package, build.get_id()).await
+                .unwrap_or_else(|e| {
+                    error!("Failed to archive build log for {}: {}", package.get_name(), e);
+                    None
+                });
+            self.package_store.update_build(&build).await?;
+
+            if status == BuildStatus::BUILT {
+                if let Err(e) = self.record_dependency_outputs(&package, dependencies).await {
+                    error!("Failed to record dependencies of {}: {}", package.get_name(), e);
+                }
+                if let Err(e) = self.record_package_version(&package, previous_version, previous_files).await {
+                    error!("Failed to record version history for {}: {}", package.get_name(), e);
+                }
+                if let Err(e) = self.queue_dependents(&package).await {
+                    error!("Failed to queue dependents of {}: {}", package.get_name(), e);
+                }
+            }
+
             if package.last_built_version.is_none() || package.last_built_version != last_version {
                 self.webhook_manager.trigger_webhook_package_updated(package.into()).await;
             }
@@ -148,31 +365,492 @@ impl Orchestrator {
         Ok(())
     }
 
-    async fn dispatch_packages(&mut self) -> Result<()> {
-        if let Some(rebuild_interval) = self.rebuild_interval {
-            self.package_store
-                .set_packages_rebuild(rebuild_interval as i64).await?;
+    /// Attributes the files of the dependencies built along with the package to packages of their own,
+    /// creating implicit packages for the ones not in the repository yet.
+    /// Explicit packages keep their own builds, they are only marked as required by the package.
+    async fn record_dependency_outputs(&mut self, parent: &Package, outputs: Vec<DependencyOutput>) -> Result<()> {
+        for output in outputs {
+            let mut existing = None;
+            for name in std::iter::once(&output.base).chain(output.names.iter()) {
+                existing = self.package_store.get_package_by_name(name).await?;
+                if existing.is_some() {
+                    break;
+                }
+            }
+
+            let mut dependency = match existing {
+                Some(dependency) => dependency,
+                None => {
+                    let name = match output.names.contains(&output.base) {
+                        true => output.base.clone(),
+                        false => output.names[0].clone(),
+                    };
+                    info!("Creating package {} for dependency of {}", name, parent.get_name());
+                    self.package_store.create_package(PackageInsert {
+                        name,
+                        implicit: true,
+                        ..Default::default()
+                    }).await?
+                }
+            };
+
+            if !dependency.get_required_by().contains(parent.get_name()) {
+                dependency.get_required_by_mut().push(parent.get_name().clone());
+            }
+            if !dependency.implicit {
+                self.package_store.update_package(&dependency).await?;
+                continue;
+            }
+            let previous_version = dependency.last_built_version.clone();
+            let previous_files = dependency.get_files().clone();
+            if dependency.get_status() != PackageStatus::BUILDING {
+                dependency.set_status(PackageStatus::BUILT);
+                dependency.last_error = None;
+            }
+            dependency.set_last_built(Some(Utc::now()));
+            dependency.last_built_version = Some(output.version);
+            *dependency.get_files_mut() = output.files;
+            let dependency = self.package_store.update_package(&dependency).await?;
+            info!("Recorded {} version {:?} built for {}", dependency.get_name(), dependency.last_built_version, parent.get_name());
+
+            if let Err(e) = self.record_package_version(&dependency, previous_version.clone(), previous_files).await {
+                error!("Failed to record version history for {}: {}", dependency.get_name(), e);
+            }
+            if dependency.last_built_version != previous_version {
+                self.webhook_manager.trigger_webhook_package_updated(dependency.into()).await;
+            }
         }
+        Ok(())
+    }
 
-        while let Some(mut package) = self.package_store.get_next_pending_package().await? {
-            let patches = self.package_store.get_patches_for_package(package.get_id()).await?;
-            match self.worker_manager.dispatch(package.get_package_job(patches)).await {
-                WorkerDispatchResult::NoneAvailable => {
-                    return Ok(())
-                },
-                WorkerDispatchResult::Ok => {
-                    package.set_status(PackageStatus::BUILDING);
-                    self.package_store.update_package(&package).await?;
-                    info!("Dispatched package {} to worker", package.get_name());
+    /// Deletes the package and the implicit packages it was the only one to require.
+    /// Implicit packages still depended on by other packages are attributed to them instead.
+    pub async fn delete_package(&mut self, id: i32, keep_files: bool) -> Result<()> {
+        let Some(package) = self.package_store.get_package(id).await? else {
+            return Ok(());
+        };
+        self.remove_package(&package, keep_files).await?;
+
+        let packages = self.package_store.get_packages().await?;
+        for dependency in packages.iter().filter(|p| p.get_required_by().contains(package.get_name())) {
+            let mut dependency = dependency.clone();
+            dependency.get_required_by_mut().retain(|name| name != package.get_name());
+
+            if dependency.implicit && dependency.get_required_by().is_empty() {
+                let names = dependency.get_provided_names();
+                let dependents: Vec<String> = packages.iter()
+                    .filter(|p| p.get_id() != dependency.get_id() && p.depends_on(&names))
+                    .map(|p| p.get_name().clone())
+                    .collect();
+                if dependents.is_empty() {
+                    info!("Deleting {} which was only required by {}", dependency.get_name(), package.get_name());
+                    self.remove_package(&dependency, keep_files).await?;
+                    continue;
+                }
+                info!("Attributing {} to its dependents {:?}", dependency.get_name(), dependents);
+                *dependency.get_required_by_mut() = dependents;
+            }
+
+            self.package_store.update_package(&dependency).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the package from the repository database and deletes it, along with the files of its retained versions
+    /// and its uploaded source unless kept.
+    /// Files now belonging to other packages are left untouched.
+    async fn remove_package(&mut self, package: &Package, keep_files: bool) -> Result<()> {
+        let other_files = self.get_other_packages_files(package).await?;
+
+        let current_files: Vec<String> = package.get_files().iter()
+            .filter(|file| !other_files.contains(file))
+            .cloned()
+            .collect();
+        self.repository.remove_packages_from_repo(&current_files).await?;
+
+        if !keep_files {
+            let mut files = current_files;
+            for version in self.package_store.get_package_versions(package.get_id()).await? {
+                files.extend(version.get_files().iter().filter(|file| !other_files.contains(file)).cloned());
+            }
+            files.sort();
+            files.dedup();
+            self.repository.remove_package_files(&files).await?;
+
+            if let PackageSource::Upload { file } = package.get_source() {
+                self.repository.remove_uploaded_source(&file).await?;
+            }
+        }
+
+        self.package_store.delete_package(package.get_id()).await?;
+        info!("Deleted package {}", package.get_name());
+        self.webhook_manager.trigger_webhook_package_deleted(package.clone().into()).await;
+
+        Ok(())
+    }
+
+    /// Schedules the next retry of a failed package if its error class is retryable and it has retries left.
+    /// The retries are only reset once the package builds, so a package out of retries is not retried again until then.
+    fn schedule_retry(&self, package: &mut Package, error_class: Option<BuildErrorClass>) {
+        let next_retry = error_class
+            .and_then(|class| self.retry_policy.get_next_retry(package, class, Utc::now()));
+
+        match next_retry {
+            Some(retry_at) => {
+                package.retry_count += 1;
+                package.set_retry_at(Some(retry_at));
+                info!(
+                    "Retrying {} at {} (attempt {}/{})",
+                    package.get_name(), retry_at, package.retry_count, self.retry_policy.max_attempts
+                );
+            }
+            None => {
+                if error_class.is_none() {
+                    package.retry_count = 0;
                 }
-                WorkerDispatchResult::Err(e) => {
-                    error!(
-                        "Error while dispatching {} to worker : {}",
-                        package.get_name(),
-                        e
-                    )
+                package.set_retry_at(None);
+            }
+        }
+    }
+
+    async fn record_package_version(
+        &mut self,
+        package: &Package,
+        previous_version: Option<String>,
+        previous_files: Vec<String>,
+    ) -> Result<()> {
+        let version = package.last_built_version.clone().unwrap_or_default();
+        let versions = self.package_store.get_package_versions(package.get_id()).await?;
+
+        // Packages built before versions were tracked get their current files recorded first
+        if versions.is_empty() && !previous_files.is_empty() && previous_files != *package.get_files() {
+            if let Some(previous_version) = previous_version.filter(|v| *v != version) {
+                let created_at = package.get_last_built().unwrap_or_else(Utc::now) - TimeDelta::seconds(1);
+                self.package_store.create_package_version(
+                    PackageVersionInsert::new(package.get_id(), previous_version, previous_files, created_at)
+                ).await?;
+            }
+        }
+
+        // A rebuild of the same version replaces its record, the files it no longer produces are deleted with it
+        if let Some(replaced) = versions.iter().find(|v| v.version == version) {
+            let other_files = self.get_other_packages_files(package).await?;
+            let files: Vec<String> = replaced.get_files().iter()
+                .filter(|file| !package.get_files().contains(file))
+                .filter(|file| !other_files.contains(file))
+                .filter(|file| !versions.iter().any(|v| v.get_id() != replaced.get_id() && v.get_files().contains(file)))
+                .cloned()
+                .collect();
+            self.repository.remove_package_files(&files).await?;
+        }
+
+        self.package_store.create_package_version(
+            PackageVersionInsert::new(package.get_id(), version, package.get_files().clone(), Utc::now())
+        ).await?;
+
+        self.prune_package_versions(package).await
+    }
+
+    /// Current files of every package but the given one.
+    async fn get_other_packages_files(&mut self, package: &Package) -> Result<Vec<String>> {
+        Ok(self.package_store.get_packages().await?.into_iter()
+            .filter(|p| p.get_id() != package.get_id())
+            .flat_map(|p| p.get_files().clone())
+            .collect())
+    }
+
+    async fn prune_package_versions(&mut self, package: &Package) -> Result<()> {
+        let keep = package.keep_versions
+            .map(|k| k.max(1) as usize)
+            .unwrap_or(self.keep_versions.max(1) as usize);
+
+        let versions = self.package_store.get_package_versions(package.get_id()).await?;
+        if versions.len() <= keep {
+            return Ok(());
+        }
+        let (retained, pruned) = versions.split_at(keep);
+        // Files of dependencies once attributed to the package may now belong to packages of their own
+        let other_files = self.get_other_packages_files(package).await?;
+
+        for version in pruned {
+            let files: Vec<String> = version.get_files().iter()
+                .filter(|file| !package.get_files().contains(file))
+                .filter(|file| !other_files.contains(file))
+                .filter(|file| !retained.iter().any(|r| r.get_files().contains(file)))
+                .cloned()
+                .collect();
+            self.repository.remove_package_files(&files).await?;
+            self.package_store.delete_package_version(version.get_id()).await?;
+            info!("Pruned version {} of {}", version.version, package.get_name());
+        }
+
+        Ok(())
+    }
+
+    pub async fn rollback_package(&mut self, package_id: i32, version_id: i32) -> Result<Option<Package>> {
+        let Some(mut package) = self.package_store.get_package(package_id).await? else {
+            return Ok(None);
+        };
+        let Some(version) = self.package_store.get_package_version(package_id, version_id).await? else {
+            return Ok(None);
+        };
+
+        if let Some(missing) = version.get_files().iter().find(|f| !self.repository.package_file_exists(f)) {
+            bail!("Package file {} of version {} is missing from the repository", missing, version.version);
+        }
+
+        self.repository.add_packages_to_repo(version.get_files().clone()).await?;
+        *package.get_files_mut() = version.get_files().clone();
+        package.last_built_version = Some(version.version.clone());
+        let package = self.package_store.update_package(&package).await?;
+        info!("Rolled back {} to version {}", package.get_name(), version.version);
+
+        self.webhook_manager.trigger_webhook_package_updated(package.clone().into()).await;
+
+        Ok(Some(package))
+    }
+
+    /// Public key the repository is signed with, workers import it to verify the packages they install from it.
+    pub async fn export_repository_key(&self) -> Result<Option<String>> {
+        self.repository.export_sign_key().await
+    }
+
+    /// Checks the repository against the packages and their retained versions.
+    /// When fixing, the repository is repaired and the packages whose files are missing are queued for rebuild.
+    pub async fn check_repository(&mut self, fix: bool) -> Result<RepositoryCheckResponse> {
+        let packages = self.package_store.get_packages().await?;
+        let package_files: Vec<String> = packages.iter().flat_map(|p| p.get_files().clone()).collect();
+        let mut retained_files = Vec::new();
+        for package in packages.iter() {
+            for version in self.package_store.get_package_versions(package.get_id()).await? {
+                retained_files.extend(version.get_files().iter().cloned());
+            }
+        }
+
+        let mut report = self.repository.rebuild_repo(&package_files, &retained_files, fix).await?;
+
+        if fix {
+            let missing: Vec<&str> = report.missing_files.iter()
+                .filter_map(|file| file.split_once('/').map(|(_, file)| file))
+                .collect();
+            let rebuilt: Vec<&Package> = packages.iter()
+                .filter(|p| p.get_files().iter().any(|file| missing.contains(&file.as_str())))
+                .collect();
+            if !rebuilt.is_empty() {
+                self.package_store.set_packages_pending(Some(rebuilt.iter().map(|p| p.get_id()).collect()), true).await?;
+                report.rebuilt_packages = rebuilt.iter().map(|p| p.get_name().clone()).collect();
+                info!("Queued {:?} for rebuild to restore their missing files", report.rebuilt_packages);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Whether the package is rebuilt based on its upstream AUR version rather than on a timer.
+    fn is_update_checked(&self, package: &Package) -> bool {
+        self.update_check_interval.is_some()
+            && !package.has_rebuild_policy()
+            && package.get_source() == PackageSource::Aur
+            && !is_vcs_package(package.get_name())
+    }
+
+    /// Queries the AUR for the update checked packages if a check is due and queues the outdated ones.
+    /// The orchestrator is not locked while the AUR is queried.
+    pub async fn check_upstream_updates(orchestrator: Arc<RwLock<Orchestrator>>) -> Result<()> {
+        let (aur_client, names) = {
+            let mut orchestrator = orchestrator.write().await;
+            let Some(update_check_interval) = orchestrator.update_check_interval else {
+                return Ok(());
+            };
+            if let Some(last_update_check) = orchestrator.last_update_check {
+                if Utc::now() - last_update_check < TimeDelta::seconds(update_check_interval as i64) {
+                    return Ok(());
                 }
             }
+            orchestrator.last_update_check = Some(Utc::now());
+
+            let packages = orchestrator.package_store.get_packages().await?;
+            let names: Vec<String> = packages.iter()
+                .filter(|p| orchestrator.is_update_checked(p))
+                .map(|p| p.get_name().clone())
+                .collect();
+            (orchestrator.aur_client.clone(), names)
+        };
+
+        if names.is_empty() {
+            return Ok(());
+        }
+
+        info!("Checking {} packages for updates on the AUR", names.len());
+        let infos = aur_client.info(&names).await?;
+        let queued = orchestrator.write().await.handle_upstream_infos(infos).await?;
+        info!("Queued {} packages with upstream updates", queued);
+
+        Ok(())
+    }
+
+    async fn handle_upstream_infos(&mut self, infos: Vec<AurInfo>) -> Result<usize> {
+        let mut queued = 0;
+
+        for info in infos {
+            let Some(mut package) = self.package_store.get_package_by_name(&info.name).await? else {
+                continue;
+            };
+
+            let upstream_changed = package.upstream_version.as_ref() != Some(&info.version)
+                || package.get_upstream_last_modified() != Some(info.get_last_modified());
+            let outdated = package.last_built_version.as_ref() != Some(&info.get_build_version());
+
+            // Failed packages are only retried once upstream changes to avoid rebuilding them on every check
+            let should_queue = outdated && match package.get_status() {
+                PackageStatus::BUILT => true,
+                PackageStatus::FAILED => upstream_changed,
+                _ => false,
+            };
+
+            package.upstream_version = Some(info.version.clone());
+            package.set_upstream_last_modified(Some(info.get_last_modified()));
+            if should_queue {
+                info!("Upstream version of {} is {}, queuing build", package.get_name(), info.version);
+                package.set_status(PackageStatus::PENDING);
+                queued += 1;
+            }
+            self.package_store.update_package(&package).await?;
+        }
+
+        Ok(queued)
+    }
+
+    /// Marks the packages due for a rebuild as pending.
+    /// Packages with their own schedule or interval follow it, the others follow the global rebuild time
+    /// unless their updates are detected through the AUR.
+    async fn queue_rebuilds(&mut self) -> Result<()> {
+        let packages = self.package_store.get_packages().await?;
+        let now = Utc::now();
+
+        let due_ids: Vec<i32> = packages.iter()
+            .filter(|p| p.enabled && p.has_rebuild_policy() && p.is_rebuild_due(now))
+            .map(|p| p.get_id())
+            .collect();
+        if !due_ids.is_empty() {
+            self.package_store.set_packages_rebuild(0, Some(due_ids)).await?;
+        }
+
+        if let Some(rebuild_interval) = self.rebuild_interval {
+            let package_ids = packages.iter()
+                .filter(|p| p.enabled && !p.has_rebuild_policy() && !self.is_update_checked(p))
+                .map(|p| p.get_id())
+                .collect();
+            self.package_store
+                .set_packages_rebuild(rebuild_interval as i64, Some(package_ids)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks the failed packages whose retry is due as pending.
+    async fn queue_retries(&mut self) -> Result<()> {
+        let now = Utc::now();
+        for mut package in self.package_store.get_packages().await? {
+            let is_due = package.get_retry_at().is_some_and(|retry_at| retry_at <= now);
+            if !package.enabled || package.get_status() != PackageStatus::FAILED || !is_due {
+                continue;
+            }
+            info!("Queuing retry {} of {}", package.retry_count, package.get_name());
+            package.set_retry_at(None);
+            package.set_status(PackageStatus::PENDING);
+            self.package_store.update_package(&package).await?;
+        }
+        Ok(())
+    }
+
+    /// Marks the packages depending on the given package as pending so they are rebuilt against its new version.
+    async fn queue_dependents(&mut self, package: &Package) -> Result<()> {
+        let names = package.get_provided_names();
+        let dependent_ids: Vec<i32> = self.package_store.get_packages().await?.iter()
+            .filter(|p| p.get_id() != package.get_id() && p.enabled && p.depends_on(&names))
+            .map(|p| p.get_id())
+            .collect();
+
+        if !dependent_ids.is_empty() {
+            let queued = self.package_store.set_packages_rebuild(0, Some(dependent_ids)).await?;
+            info!("Queued {} dependents of {} for rebuild", queued, package.get_name());
+        }
+        Ok(())
+    }
+
+    /// Whether the package has to wait for one of its dependencies in the repository to be built first.
+    /// Pending dependencies depending back on the package do not hold it, to avoid deadlocks.
+    fn is_waiting_on_dependencies(package: &Package, packages: &[Package]) -> bool {
+        let names = package.get_provided_names();
+        packages.iter()
+            .filter(|p| p.get_id() != package.get_id() && package.depends_on(&p.get_provided_names()))
+            .any(|dependency| match dependency.get_status() {
+                PackageStatus::BUILDING => true,
+                PackageStatus::PENDING => dependency.enabled && !dependency.depends_on(&names),
+                _ => false,
+            })
+    }
+
+    /// Sends the package to an available worker matching its requirements. Returns false if none was available.
+    async fn dispatch_package(&mut self, package: &mut Package) -> Result<bool> {
+        let patches = self.package_store.get_patches_for_package(package.get_id()).await?;
+        let mut job = package.get_package_job(patches);
+        job.repo_name = Some(self.repository.get_repo_name().clone());
+        match self.worker_manager.dispatch(job, &package.get_requirements(), &self.default_arch).await {
+            WorkerDispatchResult::NoneAvailable => return Ok(false),
+            WorkerDispatchResult::Ok(worker_id) => {
+                self.worker_manager.get_build_logs().start(package.get_id());
+                package.set_status(PackageStatus::BUILDING);
+                self.package_store.update_package(package).await?;
+                self.package_store.create_build(
+                    BuildInsert::running(package.get_id(), Some(worker_id as i32))
+                ).await?;
+                info!("Dispatched package {} to worker", package.get_name());
+            }
+            WorkerDispatchResult::Err(e) => {
+                error!(
+                    "Error while dispatching {} to worker : {}",
+                    package.get_name(),
+                    e
+                )
+            }
+        }
+        Ok(true)
+    }
+
+    async fn dispatch_packages(&mut self) -> Result<()> {
+        self.cancel_timed_out_builds().await?;
+        self.queue_rebuilds().await?;
+        self.queue_retries().await?;
+
+        let mut packages = self.package_store.get_packages().await?;
+        let mut held = Vec::new();
+        for mut package in self.package_store.get_pending_packages().await? {
+            if Self::is_waiting_on_dependencies(&package, &packages) {
+                debug!("Holding {} until its dependencies are built", package.get_name());
+                held.push(package);
+                continue;
+            }
+
+            if !self.worker_manager.has_free_worker().await {
+                return Ok(());
+            }
+            if !self.dispatch_package(&mut package).await? {
+                debug!("No worker available matching the requirements of {}", package.get_name());
+                continue;
+            }
+            if let Some(p) = packages.iter_mut().find(|p| p.get_id() == package.get_id()) {
+                *p = package;
+            }
+        }
+
+        // Only pending packages holding each other means a dependency cycle, build the first one to break it.
+        if !held.is_empty() && !packages.iter().any(|p| p.get_status() == PackageStatus::BUILDING) {
+            let mut package = held.remove(0);
+            warn!("Dependency cycle detected between pending packages, dispatching {}", package.get_name());
+            self.dispatch_package(&mut package).await?;
         }
 
         Ok(())
@@ -188,6 +866,9 @@ impl Orchestrator {
         is_running.store(true, Ordering::SeqCst);
 
         while is_running.load(Ordering::SeqCst) {
+            if let Err(e) = Orchestrator::check_upstream_updates(orchestrator.clone()).await {
+                error!("Error while checking for upstream updates : {}", e);
+            }
             if let Err(e) = orchestrator.write().await.dispatch_packages().await {
                 error!("Error while dispatching packages : {}", e);
             }
@@ -200,8 +881,12 @@ impl Orchestrator {
 #[cfg(test)]
 mod tests {
     use crate::models::config::Config;
-    use crate::orchestrator::Orchestrator;
-    use crate::persistence::package_store::PackageInsert;
+    use crate::orchestrator::{BuildFailure, BuildReport, Orchestrator};
+    use crate::aur::tests::start_mock_rpc;
+    use crate::aur::AurClient;
+    use crate::persistence::package_store::{BuildInsert, PackageInsert, PackageVersionInsert};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use std::sync::atomic::Ordering;
     use log::LevelFilter;
     use std::path::{Path, PathBuf};
     use std::sync::Arc;
@@ -210,7 +895,7 @@ mod tests {
     use std::io::Write;
     use tokio::fs::create_dir_all;
     use tokio::sync::RwLock;
-    use common::models::PackageStatus;
+    use common::models::{BuildErrorClass, BuildStatus, CompilerCacheStats, PackageStatus};
 
     async fn get_instance() -> (Config, Orchestrator) {
         let config = Config {
@@ -219,11 +904,21 @@ mod tests {
             api_key: "api_key".to_string(),
             port: 3000,
             repo_name: "test".to_string(),
+            architectures: vec!["x86_64".to_string()],
             sign_key: None,
             rebuild_time: None,
+            build_timeout: None,
+            update_check_time: None,
+            aur_rpc_url: "http://127.0.0.1/rpc".to_string(),
+            keep_versions: 1,
+            retry_max_attempts: 3,
+            retry_backoff_time: 10,
+            retry_error_classes: vec![BuildErrorClass::Network],
             serve_path: PathBuf::from("/tmp/aur-build-server-test/repo"),
+            migrate_legacy_layout: false,
             build_logs_path: PathBuf::from("/tmp/aur-build-server-test/logs"),
             database_path: ":memory:".into(),
+            sources_path: PathBuf::from("/tmp/aur-build-server-test/sources"),
             webhooks: vec![],
             webhook_verify_ssl: false,
             webhook_certificate: None,
@@ -235,6 +930,7 @@ mod tests {
         orchestrator.package_store.create_package(PackageInsert {
             name: "test-package".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
 
         let test_dir = Path::new("/tmp/aur-build-server-test");
@@ -253,6 +949,15 @@ mod tests {
     async fn handle_package_build_output_success_test() {
         let (config, mut orchestrator) = get_instance().await;
 
+        let mut dependent = orchestrator.package_store.create_package(PackageInsert {
+            name: "dependent".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        dependent.set_status(PackageStatus::BUILT);
+        dependent.set_last_built(Some(Utc::now() - TimeDelta::hours(1)));
+        dependent.set_dependencies(vec!["aur-build-cli".to_string()]);
+        orchestrator.package_store.update_package(&dependent).await.unwrap();
+
         let mut file = tempfile::Builder::new()
             .prefix("aur-build-cli-0.10.0-1-any")
             .suffix(".pkg.tar.zst")
@@ -280,12 +985,17 @@ mod tests {
                 "test-package".to_string(),
                 Some("11.2.3".to_string()),
                 None,
+                BuildReport {
+                    package_base: None,
+                    dependencies: vec!["glibc".to_string()],
+                    compiler_cache: Some(CompilerCacheStats { hits: 12, misses: 3 }),
+                },
                 vec![log_file],
                 vec![package_file])
             .await.unwrap();
 
         assert!(config.serve_path
-            .join("aur-build-cli-0.10.0-1-any.pkg.tar.zst")
+            .join("test/os/x86_64/aur-build-cli-0.10.0-1-any.pkg.tar.zst")
             .exists());
 
         let package = orchestrator
@@ -304,11 +1014,134 @@ mod tests {
             package.get_files()[0].as_str()
         );
         assert!(Path::new("/tmp/aur-build-server-test/logs/test-package.log").exists());
+        assert_eq!(&vec!["glibc".to_string()], package.get_dependencies());
+
+        let dependent = orchestrator.package_store.get_package_by_name("dependent").await.unwrap().unwrap();
+        assert_eq!(PackageStatus::PENDING, dependent.get_status());
 
-        let database_path = PathBuf::from("/tmp/aur-build-server-test/repo/test.db");
+        let builds = orchestrator.package_store
+            .get_builds_for_package(package.get_id(), None, None).await
+            .unwrap();
+        assert_eq!(1, builds.len());
+        assert_eq!(BuildStatus::BUILT, builds[0].get_status());
+        assert_eq!(Some("11.2.3".to_string()), builds[0].version);
+        assert_eq!(Some("test-package-1.log".to_string()), builds[0].log_file);
+        assert_eq!(Some(CompilerCacheStats { hits: 12, misses: 3 }), builds[0].get_compiler_cache());
+        assert!(Path::new("/tmp/aur-build-server-test/logs/builds/test-package-1.log").exists());
+
+        let database_path = PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64/test.db");
         assert!(database_path.exists());
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn handle_package_build_output_dependencies_test() {
+        let (_, mut orchestrator) = get_instance().await;
+
+        orchestrator.package_store.create_package(PackageInsert {
+            name: "test-package".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+
+        let mut file = tempfile::Builder::new().tempfile().unwrap();
+        file.write_all(&tokio::fs::read("tests/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap()).unwrap();
+        let package_file = TempFile {
+            file,
+            content_type: None,
+            file_name: Some("aur-build-cli-0.10.0-1-any.pkg.tar.zst".to_string()),
+            size: 1
+        };
+
+        orchestrator
+            .handle_package_build_output(
+                "test-package".to_string(),
+                Some("1.0.01".to_string()),
+                None,
+                BuildReport {
+                    package_base: Some("test-package".to_string()),
+                    dependencies: vec!["aur-build-cli".to_string()],
+                    ..Default::default()
+                },
+                vec![],
+                vec![package_file])
+            .await.unwrap();
+
+        let package = orchestrator.package_store.get_package_by_name("test-package").await.unwrap().unwrap();
+        assert!(package.get_files().is_empty());
+
+        let dependency = orchestrator.package_store.get_package_by_name("aur-build-cli").await.unwrap().unwrap();
+        assert!(dependency.implicit);
+        assert_eq!(&vec!["test-package".to_string()], dependency.get_required_by());
+        assert_eq!(PackageStatus::BUILT, dependency.get_status());
+        assert_eq!(Some("0.10.01".to_string()), dependency.last_built_version);
+        assert_eq!(&vec!["aur-build-cli-0.10.0-1-any.pkg.tar.zst".to_string()], dependency.get_files());
+
+        let mut dependent = orchestrator.package_store.create_package(PackageInsert {
+            name: "dependent".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        dependent.set_dependencies(vec!["aur-build-cli".to_string()]);
+        orchestrator.package_store.update_package(&dependent).await.unwrap();
+
+        orchestrator.delete_package(package.get_id(), false).await.unwrap();
+        let dependency = orchestrator.package_store.get_package_by_name("aur-build-cli").await.unwrap().unwrap();
+        assert_eq!(&vec!["dependent".to_string()], dependency.get_required_by());
+
+        orchestrator.delete_package(dependent.get_id(), false).await.unwrap();
+        assert!(orchestrator.package_store.get_package_by_name("aur-build-cli").await.unwrap().is_none());
+        assert!(!orchestrator.repository.package_file_exists("aur-build-cli-0.10.0-1-any.pkg.tar.zst"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn handle_package_build_output_explicit_dependency_test() {
+        let (_, mut orchestrator) = get_instance().await;
+
+        orchestrator.package_store.create_package(PackageInsert {
+            name: "test-package".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let mut explicit = orchestrator.package_store.create_package(PackageInsert {
+            name: "aur-build-cli".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        explicit.set_status(PackageStatus::FAILED);
+        explicit.last_built_version = Some("0.9.01".to_string());
+        *explicit.get_files_mut() = vec!["aur-build-cli-0.9.0-1-any.pkg.tar.zst".to_string()];
+        orchestrator.package_store.update_package(&explicit).await.unwrap();
+
+        let mut file = tempfile::Builder::new().tempfile().unwrap();
+        file.write_all(&tokio::fs::read("tests/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap()).unwrap();
+        let package_file = TempFile {
+            file,
+            content_type: None,
+            file_name: Some("aur-build-cli-0.10.0-1-any.pkg.tar.zst".to_string()),
+            size: 1
+        };
+
+        orchestrator
+            .handle_package_build_output(
+                "test-package".to_string(),
+                Some("1.0.01".to_string()),
+                None,
+                BuildReport {
+                    package_base: Some("test-package".to_string()),
+                    dependencies: vec!["aur-build-cli".to_string()],
+                    ..Default::default()
+                },
+                vec![],
+                vec![package_file])
+            .await.unwrap();
+
+        let dependency = orchestrator.package_store.get_package_by_name("aur-build-cli").await.unwrap().unwrap();
+        assert!(!dependency.implicit);
+        assert_eq!(&vec!["test-package".to_string()], dependency.get_required_by());
+        assert_eq!(PackageStatus::FAILED, dependency.get_status());
+        assert_eq!(Some("0.9.01".to_string()), dependency.last_built_version);
+        assert_eq!(&vec!["aur-build-cli-0.9.0-1-any.pkg.tar.zst".to_string()], dependency.get_files());
+        assert!(!orchestrator.repository.package_file_exists("aur-build-cli-0.10.0-1-any.pkg.tar.zst"));
+    }
+
     #[tokio::test]
     #[serial]
     async fn handle_package_build_output_fail_test() {
@@ -328,7 +1161,8 @@ mod tests {
             .handle_package_build_output(
                 "test-package".to_string(),
                 Some("11.2.3".to_string()),
-                Some("Error test".to_string()),
+                Some(BuildFailure { message: "Error test".to_string(), class: BuildErrorClass::Build }),
+                BuildReport::default(),
                 vec![log_file],
                 vec![])
             .await.unwrap();
@@ -341,5 +1175,351 @@ mod tests {
         assert!(package.last_built_version.is_none());
         assert_eq!(0, package.get_files().len());
         assert!(Path::new("/tmp/aur-build-server-test/logs/test-package.log").exists());
+
+        let build = orchestrator.package_store
+            .get_build(package.get_id(), 1).await
+            .unwrap().unwrap();
+        assert_eq!(BuildStatus::FAILED, build.get_status());
+        assert_eq!(Some("Error test".to_string()), build.error);
+        assert_eq!(Some(BuildErrorClass::Build), build.get_error_class());
+        assert!(build.get_ended_at().is_some());
+
+        assert_eq!(0, package.retry_count);
+        assert!(package.get_retry_at().is_none());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    #[serial]
+    async fn retry_failed_build_test() {
+        let (_, mut orchestrator) = get_instance().await;
+
+        for attempt in 1..=4 {
+            orchestrator
+                .handle_package_build_output(
+                    "test-package".to_string(),
+                    None,
+                    Some(BuildFailure { message: "Connection refused".to_string(), class: BuildErrorClass::Network }),
+                    BuildReport::default(),
+                    vec![],
+                    vec![])
+                .await.unwrap();
+
+            let mut package = orchestrator.package_store
+                .get_package_by_name("test-package").await
+                .unwrap().unwrap();
+            assert_eq!(PackageStatus::FAILED, package.get_status());
+            if attempt > 3 {
+                assert_eq!(3, package.retry_count);
+                assert!(package.get_retry_at().is_none());
+                break;
+            }
+            assert_eq!(attempt, package.retry_count);
+            let retry_at = package.get_retry_at().unwrap();
+            let backoff = TimeDelta::seconds(10 * 2i64.pow(attempt as u32 - 1));
+            assert!(retry_at > Utc::now() + backoff - TimeDelta::seconds(5) && retry_at <= Utc::now() + backoff);
+
+            orchestrator.queue_retries().await.unwrap();
+            let package_after = orchestrator.package_store.get_package(package.get_id()).await.unwrap().unwrap();
+            assert_eq!(PackageStatus::FAILED, package_after.get_status());
+
+            package.set_retry_at(Some(Utc::now() - TimeDelta::seconds(1)));
+            orchestrator.package_store.update_package(&package).await.unwrap();
+            orchestrator.queue_retries().await.unwrap();
+            let package = orchestrator.package_store.get_package(package.get_id()).await.unwrap().unwrap();
+            assert_eq!(PackageStatus::PENDING, package.get_status());
+            assert!(package.get_retry_at().is_none());
+        }
+
+        let builds = orchestrator.package_store
+            .get_builds_for_package(1, None, None).await
+            .unwrap();
+        assert_eq!(4, builds.len());
+        assert!(builds.iter().all(|b| b.get_error_class() == Some(BuildErrorClass::Network)));
+
+        orchestrator
+            .handle_package_build_output(
+                "test-package".to_string(),
+                Some("1.0.0".to_string()),
+                None,
+                BuildReport::default(),
+                vec![],
+                vec![])
+            .await.unwrap();
+        let package = orchestrator.package_store.get_package_by_name("test-package").await.unwrap().unwrap();
+        assert_ne!(PackageStatus::FAILED, package.get_status());
+        assert_eq!(0, package.retry_count);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn prune_package_versions_test() {
+        let (config, mut orchestrator) = get_instance().await;
+        let repo_path = config.serve_path.join("test/os/x86_64");
+        create_dir_all(&repo_path).await.unwrap();
+
+        let mut package = orchestrator.package_store
+            .get_package_by_name("test-package").await
+            .unwrap().unwrap();
+        package.keep_versions = Some(2);
+        *package.get_files_mut() = vec!["test-package-3-1-x86_64.pkg.tar.zst".to_string()];
+        let package = orchestrator.package_store.update_package(&package).await.unwrap();
+
+        let versions = [
+            ("1", vec!["test-package-1-1-x86_64.pkg.tar.zst", "test-package-common-1-1-x86_64.pkg.tar.zst"]),
+            ("2", vec!["test-package-2-1-x86_64.pkg.tar.zst", "test-package-common-1-1-x86_64.pkg.tar.zst"]),
+            ("3", vec!["test-package-3-1-x86_64.pkg.tar.zst"]),
+        ];
+        for (i, (version, files)) in versions.iter().enumerate() {
+            for file in files {
+                std::fs::write(repo_path.join(file), "").unwrap();
+            }
+            std::fs::write(repo_path.join(format!("{}.sig", files[0])), "").unwrap();
+            orchestrator.package_store.create_package_version(PackageVersionInsert::new(
+                package.get_id(),
+                version.to_string(),
+                files.iter().map(|f| f.to_string()).collect(),
+                Utc::now() + TimeDelta::seconds(i as i64),
+            )).await.unwrap();
+        }
+
+        orchestrator.prune_package_versions(&package).await.unwrap();
+
+        let versions = orchestrator.package_store.get_package_versions(package.get_id()).await.unwrap();
+        assert_eq!(2, versions.len());
+        assert_eq!("3", versions[0].version);
+        assert_eq!("2", versions[1].version);
+        assert!(!repo_path.join("test-package-1-1-x86_64.pkg.tar.zst").exists());
+        assert!(!repo_path.join("test-package-1-1-x86_64.pkg.tar.zst.sig").exists());
+        assert!(repo_path.join("test-package-common-1-1-x86_64.pkg.tar.zst").exists());
+        assert!(repo_path.join("test-package-2-1-x86_64.pkg.tar.zst").exists());
+        assert!(repo_path.join("test-package-3-1-x86_64.pkg.tar.zst").exists());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn record_same_package_version_test() {
+        let (config, mut orchestrator) = get_instance().await;
+        let repo_path = config.serve_path.join("test/os/x86_64");
+        create_dir_all(&repo_path).await.unwrap();
+
+        let mut package = orchestrator.package_store
+            .get_package_by_name("test-package").await
+            .unwrap().unwrap();
+        let previous_files = vec!["test-package-1-1-x86_64.pkg.tar.zst".to_string(), "test-package-extra-1-1-x86_64.pkg.tar.zst".to_string()];
+        for file in previous_files.iter() {
+            std::fs::write(repo_path.join(file), "").unwrap();
+        }
+        orchestrator.package_store.create_package_version(PackageVersionInsert::new(
+            package.get_id(),
+            "1".to_string(),
+            previous_files.clone(),
+            Utc::now() - TimeDelta::seconds(1),
+        )).await.unwrap();
+
+        package.last_built_version = Some("1".to_string());
+        *package.get_files_mut() = vec!["test-package-1-1-x86_64.pkg.tar.zst".to_string()];
+        let package = orchestrator.package_store.update_package(&package).await.unwrap();
+        orchestrator.record_package_version(&package, Some("1".to_string()), previous_files).await.unwrap();
+
+        let versions = orchestrator.package_store.get_package_versions(package.get_id()).await.unwrap();
+        assert_eq!(1, versions.len());
+        assert_eq!(&vec!["test-package-1-1-x86_64.pkg.tar.zst".to_string()], versions[0].get_files());
+        assert!(repo_path.join("test-package-1-1-x86_64.pkg.tar.zst").exists());
+        assert!(!repo_path.join("test-package-extra-1-1-x86_64.pkg.tar.zst").exists());
+    }
+
+    #[actix_web::test]
+    #[serial]
+    async fn check_upstream_updates_test() {
+        let (_, mut orchestrator) = get_instance().await;
+        let (url, handle, requests) = start_mock_rpc(vec![
+            ("up-to-date", "1.0.0-1", 100),
+            ("outdated", "1.0.0-1", 100),
+            ("failed", "1.0.0-1", 100),
+            ("vcs-git", "1.0.0-1", 100),
+        ]);
+        orchestrator.aur_client = AurClient::new(url);
+        orchestrator.update_check_interval = Some(3600);
+
+        for (name, status, version) in [
+            ("up-to-date", PackageStatus::BUILT, "1.0.01"),
+            ("outdated", PackageStatus::BUILT, "0.9.01"),
+            ("failed", PackageStatus::FAILED, "0.9.01"),
+            ("vcs-git", PackageStatus::BUILT, "0.9.01"),
+        ] {
+            let mut package = orchestrator.package_store.create_package(PackageInsert {
+                name: name.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            package.set_status(status);
+            package.last_built_version = Some(version.to_string());
+            if name == "failed" {
+                package.upstream_version = Some("1.0.0-1".to_string());
+                package.set_upstream_last_modified(DateTime::from_timestamp(100, 0));
+            }
+            orchestrator.package_store.update_package(&package).await.unwrap();
+        }
+
+        let orchestrator = Arc::new(RwLock::new(orchestrator));
+        Orchestrator::check_upstream_updates(orchestrator.clone()).await.unwrap();
+        Orchestrator::check_upstream_updates(orchestrator.clone()).await.unwrap();
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+
+        let mut orchestrator = orchestrator.write().await;
+        for (name, status) in [
+            ("up-to-date", PackageStatus::BUILT),
+            ("outdated", PackageStatus::PENDING),
+            ("failed", PackageStatus::FAILED),
+            ("vcs-git", PackageStatus::BUILT),
+        ] {
+            let package = orchestrator.package_store.get_package_by_name(name).await.unwrap().unwrap();
+            assert_eq!(status, package.get_status(), "{}", name);
+        }
+
+        let package = orchestrator.package_store.get_package_by_name("up-to-date").await.unwrap().unwrap();
+        assert_eq!(Some("1.0.0-1".to_string()), package.upstream_version);
+        assert_eq!(Some(100), package.get_upstream_last_modified().map(|d| d.timestamp()));
+        let package = orchestrator.package_store.get_package_by_name("vcs-git").await.unwrap().unwrap();
+        assert!(package.upstream_version.is_none());
+
+        handle.stop(true).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn queue_rebuilds_test() {
+        let (_, mut orchestrator) = get_instance().await;
+        orchestrator.rebuild_interval = Some(3600);
+
+        for (name, rebuild_interval, schedule, enabled, hours_ago) in [
+            ("global", None, None, true, 2),
+            ("global-recent", None, None, true, 0),
+            ("on-demand", Some(0), None, true, 48),
+            ("interval", Some(60), None, true, 1),
+            ("scheduled", None, Some("0 0 * * * *"), true, 2),
+            ("paused", Some(60), None, false, 2),
+        ] {
+            let mut package = orchestrator.package_store.create_package(PackageInsert {
+                name: name.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            package.set_status(PackageStatus::BUILT);
+            package.set_last_built(Some(Utc::now() - TimeDelta::hours(hours_ago) - TimeDelta::minutes(1)));
+            package.rebuild_interval = rebuild_interval;
+            package.schedule = schedule.map(|s| s.to_string());
+            package.enabled = enabled;
+            orchestrator.package_store.update_package(&package).await.unwrap();
+        }
+
+        orchestrator.queue_rebuilds().await.unwrap();
+
+        for (name, status) in [
+            ("global", PackageStatus::PENDING),
+            ("global-recent", PackageStatus::BUILT),
+            ("on-demand", PackageStatus::BUILT),
+            ("interval", PackageStatus::PENDING),
+            ("scheduled", PackageStatus::PENDING),
+            ("paused", PackageStatus::BUILT),
+        ] {
+            let package = orchestrator.package_store.get_package_by_name(name).await.unwrap().unwrap();
+            assert_eq!(status, package.get_status(), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn cancel_timed_out_builds_test() {
+        let (_, mut orchestrator) = get_instance().await;
+        orchestrator.build_timeout = Some(3600);
+
+        for (name, build_timeout, minutes_ago) in [
+            ("global", None, 61),
+            ("global-recent", None, 30),
+            ("package", Some(600), 11),
+            ("disabled", Some(0), 600),
+        ] {
+            let mut package = orchestrator.package_store.create_package(PackageInsert {
+                name: name.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            package.set_status(PackageStatus::BUILDING);
+            package.build_timeout = build_timeout;
+            orchestrator.package_store.update_package(&package).await.unwrap();
+            orchestrator.package_store.create_build(BuildInsert {
+                started_at: (Utc::now() - TimeDelta::minutes(minutes_ago)).timestamp(),
+                ..BuildInsert::running(package.get_id(), None)
+            }).await.unwrap();
+        }
+
+        orchestrator.cancel_timed_out_builds().await.unwrap();
+
+        for (name, status, build_status) in [
+            ("global", PackageStatus::FAILED, BuildStatus::FAILED),
+            ("global-recent", PackageStatus::BUILDING, BuildStatus::RUNNING),
+            ("package", PackageStatus::FAILED, BuildStatus::FAILED),
+            ("disabled", PackageStatus::BUILDING, BuildStatus::RUNNING),
+        ] {
+            let package = orchestrator.package_store.get_package_by_name(name).await.unwrap().unwrap();
+            assert_eq!(status, package.get_status(), "{}", name);
+            let builds = orchestrator.package_store.get_builds_for_package(package.get_id(), None, None).await.unwrap();
+            assert_eq!(build_status, builds[0].get_status(), "{}", name);
+            if build_status == BuildStatus::FAILED {
+                assert_eq!(Some(BuildErrorClass::Timeout), builds[0].get_error_class());
+                assert!(package.last_error.as_ref().unwrap().starts_with("Build timed out"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn cancel_package_test() {
+        let (_, mut orchestrator) = get_instance().await;
+
+        let mut package = orchestrator.package_store.get_package_by_name("test-package").await.unwrap().unwrap();
+        assert!(orchestrator.cancel_package(package.get_id()).await.is_err());
+        assert!(orchestrator.cancel_package(1000).await.unwrap().is_none());
+
+        package.set_status(PackageStatus::BUILDING);
+        orchestrator.package_store.update_package(&package).await.unwrap();
+        orchestrator.package_store.create_build(BuildInsert::running(package.get_id(), None)).await.unwrap();
+
+        let package = orchestrator.cancel_package(package.get_id()).await.unwrap().unwrap();
+        assert_eq!(PackageStatus::FAILED, package.get_status());
+        let builds = orchestrator.package_store.get_builds_for_package(package.get_id(), None, None).await.unwrap();
+        assert_eq!(BuildStatus::CANCELLED, builds[0].get_status());
+        assert_eq!(Some("Build was cancelled".to_string()), builds[0].error);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn is_waiting_on_dependencies_test() {
+        let (_, mut orchestrator) = get_instance().await;
+
+        let mut packages = Vec::new();
+        for (name, status, dependencies) in [
+            ("lib", PackageStatus::BUILDING, vec![]),
+            ("app", PackageStatus::PENDING, vec!["lib"]),
+            ("tool", PackageStatus::PENDING, vec!["glibc"]),
+            ("cycle-a", PackageStatus::PENDING, vec!["cycle-b"]),
+            ("cycle-b", PackageStatus::PENDING, vec!["cycle-a"]),
+            ("plugin", PackageStatus::PENDING, vec!["tool"]),
+        ] {
+            let mut package = orchestrator.package_store.create_package(PackageInsert {
+                name: name.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            package.set_status(status);
+            package.set_dependencies(dependencies.into_iter().map(String::from).collect());
+            packages.push(package);
+        }
+
+        let waiting: Vec<&str> = packages.iter()
+            .filter(|p| Orchestrator::is_waiting_on_dependencies(p, &packages))
+            .map(|p| p.get_name().as_str())
+            .collect();
+        assert_eq!(vec!["app", "plugin"], waiting);
+
+        packages[0].set_status(PackageStatus::BUILT);
+        assert!(!Orchestrator::is_waiting_on_dependencies(&packages[1], &packages));
+    }
+}
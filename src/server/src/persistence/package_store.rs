@@ -5,20 +5,25 @@ use diesel::deserialize::FromSql;
 use diesel::serialize::{IsNull, Output, ToSql};
 use diesel::sql_types::Text;
 use diesel::{AsChangeset, AsExpression, Connection, ExpressionMethods, FromSqlRow, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl, Selectable, SelectableHelper, SqliteConnection, TextExpressionMethods};
+use std::fmt::Debug;
 use std::ops::{DerefMut, Sub};
 use std::path::PathBuf;
 use std::sync::{Arc};
 use chrono::{DateTime, TimeDelta, Utc};
+use cron::Schedule;
+use std::str::FromStr;
 use diesel::sqlite::Sqlite;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use log::{debug, info};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tokio::sync::Mutex;
-use common::http::responses::{PackagePatchResponse, PackageResponse};
-use common::models::{PackageDefinition, PackageJob, PackagePatchDefinition, PackageStatus};
+use common::http::responses::{BuildResponse, PackagePatchResponse, PackageResponse, PackageVersionResponse};
+use common::models::{BuildEnvironment, BuildErrorClass, BuildStatus, CompilerCacheStats, PackageDefinition, PackageJob, PackagePatchDefinition, PackageRequirements, PackageSource, PackageStatus, ResourceLimits};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
-#[derive(Debug, AsExpression, FromSqlRow, Clone)]
+#[derive(Debug, AsExpression, FromSqlRow, Clone, Default)]
 #[diesel(sql_type = diesel::sql_types::Text)]
 pub struct StringArray(Vec<String>);
 
@@ -36,9 +41,29 @@ impl FromSql<Text, Sqlite> for StringArray {
     }
 }
 
-#[derive(Queryable, Selectable, Debug, AsChangeset)]
+/// Value of a package stored as JSON, like its build environment or resource limits.
+#[derive(Debug, AsExpression, FromSqlRow, Clone, Default)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub struct JsonColumn<T: Serialize + DeserializeOwned + Debug>(T);
+
+impl<T: Serialize + DeserializeOwned + Debug> ToSql<Text, Sqlite> for JsonColumn<T> {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> diesel::serialize::Result {
+        out.set_value(serde_json::to_string(&self.0)?);
+        Ok(IsNull::No)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Debug> FromSql<Text, Sqlite> for JsonColumn<T> {
+    fn from_sql(mut bytes: <Sqlite as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        Ok(JsonColumn(serde_json::from_reader(bytes.read_blob())?))
+    }
+}
+
+#[derive(Queryable, Selectable, Debug, AsChangeset, Clone)]
 #[diesel(table_name = schema::packages)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+// A package is always loaded whole before being updated, so a None is a value to store and not a column to skip
+#[diesel(treat_none_as_null = true)]
 pub struct Package {
     id: i32,
     name: String,
@@ -47,7 +72,68 @@ pub struct Package {
     last_built: Option<i64>,
     files: StringArray,
     pub last_built_version: Option<String>,
-    pub last_error: Option<String>
+    pub last_error: Option<String>,
+    pub keep_versions: Option<i32>,
+    source_type: i16,
+    source_url: Option<String>,
+    source_ref: Option<String>,
+    source_subdirectory: Option<String>,
+    pub upstream_version: Option<String>,
+    upstream_last_modified: Option<i64>,
+    pub priority: i32,
+    /// Seconds between rebuilds of this package, overriding the global rebuild time. 0 means on demand only.
+    pub rebuild_interval: Option<i64>,
+    /// Cron expression of when this package should be rebuilt, overriding any rebuild interval.
+    pub schedule: Option<String>,
+    pub enabled: bool,
+    /// Names of the depends and makedepends of the package as last reported by a worker.
+    dependencies: StringArray,
+    required_arch: Option<String>,
+    min_cpus: Option<i32>,
+    min_disk: Option<i64>,
+    required_tags: StringArray,
+    /// Amount of automatic retries since the last successful build.
+    pub retry_count: i32,
+    retry_at: Option<i64>,
+    /// Seconds after which a running build of this package is cancelled, overriding the global build timeout.
+    pub build_timeout: Option<i64>,
+    /// Whether compilations are cached across builds of this package.
+    pub compiler_cache: bool,
+    /// makepkg settings and environment variables overriding the ones of the workers.
+    build_env: JsonColumn<BuildEnvironment>,
+    /// Resources the builds of this package may use, overriding the limits of the workers.
+    limits: JsonColumn<ResourceLimits>,
+    /// Whether builds of this package keep network access on workers isolating builds from the network.
+    pub allow_network: bool,
+    /// Whether the package was created by the server for a dependency built along with another package.
+    pub implicit: bool,
+    /// Names of the packages whose builds built this package as one of their dependencies.
+    required_by: StringArray,
+}
+
+const SOURCE_TYPE_AUR: i16 = 0;
+const SOURCE_TYPE_GIT: i16 = 1;
+const SOURCE_TYPE_LOCAL: i16 = 2;
+const SOURCE_TYPE_UPLOAD: i16 = 3;
+
+/// Splits a source into its (source_type, source_url, source_ref, source_subdirectory) columns.
+fn source_to_columns(source: PackageSource) -> (i16, Option<String>, Option<String>, Option<String>) {
+    match source {
+        PackageSource::Aur => (SOURCE_TYPE_AUR, None, None, None),
+        PackageSource::Git { url, reference, subdirectory } => (SOURCE_TYPE_GIT, Some(url), reference, subdirectory),
+        PackageSource::Local { path } => (SOURCE_TYPE_LOCAL, Some(path), None, None),
+        PackageSource::Upload { file } => (SOURCE_TYPE_UPLOAD, Some(file), None, None),
+    }
+}
+
+/// Splits requirements into their (required_arch, min_cpus, min_disk, required_tags) columns.
+fn requirements_to_columns(requirements: PackageRequirements) -> (Option<String>, Option<i32>, Option<i64>, StringArray) {
+    (
+        requirements.arch,
+        requirements.min_cpus.map(|c| c as i32),
+        requirements.min_disk.map(|d| d as i64),
+        StringArray(requirements.tags),
+    )
 }
 
 impl Package {
@@ -75,6 +161,49 @@ impl Package {
         self.last_built = last_built.map(|t| t.timestamp());
     }
 
+    pub fn get_retry_at(&self) -> Option<DateTime<Utc>> {
+        self.retry_at.map(|ts| DateTime::from_timestamp(ts, 0).unwrap())
+    }
+
+    pub fn set_retry_at(&mut self, retry_at: Option<DateTime<Utc>>) {
+        self.retry_at = retry_at.map(|t| t.timestamp());
+    }
+
+    pub fn get_upstream_last_modified(&self) -> Option<DateTime<Utc>> {
+        self.upstream_last_modified.map(|ts| DateTime::from_timestamp(ts, 0).unwrap())
+    }
+
+    pub fn set_upstream_last_modified(&mut self, upstream_last_modified: Option<DateTime<Utc>>) {
+        self.upstream_last_modified = upstream_last_modified.map(|t| t.timestamp());
+    }
+
+    /// Whether the package has its own rebuild policy instead of the global one.
+    pub fn has_rebuild_policy(&self) -> bool {
+        self.schedule.is_some() || self.rebuild_interval.is_some()
+    }
+
+    /// Whether the package should be rebuilt at the given time according to its own rebuild policy.
+    pub fn is_rebuild_due(&self, now: DateTime<Utc>) -> bool {
+        let Some(last_built) = self.get_last_built() else {
+            return false;
+        };
+
+        if let Some(schedule) = self.schedule.as_ref() {
+            return match Schedule::from_str(schedule) {
+                Ok(schedule) => schedule.after(&last_built).next().is_some_and(|next| next <= now),
+                Err(e) => {
+                    warn!("Invalid schedule '{}' for {}: {}", schedule, self.name, e);
+                    false
+                }
+            };
+        }
+
+        match self.rebuild_interval {
+            Some(interval) if interval > 0 => last_built + TimeDelta::seconds(interval) <= now,
+            _ => false,
+        }
+    }
+
     pub fn get_files(&self) -> &Vec<String>
     {
         &self.files.0
@@ -84,6 +213,95 @@ impl Package {
         &mut self.files.0
     }
 
+    pub fn get_dependencies(&self) -> &Vec<String> {
+        &self.dependencies.0
+    }
+
+    pub fn set_dependencies(&mut self, dependencies: Vec<String>) {
+        self.dependencies = StringArray(dependencies);
+    }
+
+    /// Names the package can be depended on with, its own name and the names of the packages it last built.
+    pub fn get_provided_names(&self) -> Vec<String> {
+        let mut names = vec![self.name.clone()];
+        for file in self.files.0.iter() {
+            let Some(index) = file.find(".pkg.tar") else {
+                continue;
+            };
+            // name-pkgver-pkgrel-arch.pkg.tar.*
+            let mut parts = file[..index].rsplitn(4, '-');
+            if let Some(name) = parts.nth(3) {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    pub fn get_required_by(&self) -> &Vec<String> {
+        &self.required_by.0
+    }
+
+    pub fn get_required_by_mut(&mut self) -> &mut Vec<String> {
+        &mut self.required_by.0
+    }
+
+    /// Whether the package depends on one of the given names.
+    pub fn depends_on(&self, names: &[String]) -> bool {
+        self.dependencies.0.iter().any(|d| names.contains(d))
+    }
+
+    pub fn get_requirements(&self) -> PackageRequirements {
+        PackageRequirements {
+            arch: self.required_arch.clone(),
+            min_cpus: self.min_cpus.map(|c| c as usize),
+            min_disk: self.min_disk.map(|d| d as u64),
+            tags: self.required_tags.0.clone(),
+        }
+    }
+
+    pub fn set_requirements(&mut self, requirements: PackageRequirements) {
+        (self.required_arch, self.min_cpus, self.min_disk, self.required_tags) = requirements_to_columns(requirements);
+    }
+
+    pub fn get_build_env(&self) -> &BuildEnvironment {
+        &self.build_env.0
+    }
+
+    pub fn set_build_env(&mut self, build_env: BuildEnvironment) {
+        self.build_env = JsonColumn(build_env);
+    }
+
+    pub fn get_limits(&self) -> ResourceLimits {
+        self.limits.0
+    }
+
+    pub fn set_limits(&mut self, limits: ResourceLimits) {
+        self.limits = JsonColumn(limits);
+    }
+
+    pub fn get_source(&self) -> PackageSource {
+        match self.source_type {
+            SOURCE_TYPE_GIT => PackageSource::Git {
+                url: self.source_url.clone().unwrap_or_default(),
+                reference: self.source_ref.clone(),
+                subdirectory: self.source_subdirectory.clone(),
+            },
+            SOURCE_TYPE_LOCAL => PackageSource::Local {
+                path: self.source_url.clone().unwrap_or_default(),
+            },
+            SOURCE_TYPE_UPLOAD => PackageSource::Upload {
+                file: self.source_url.clone().unwrap_or_default(),
+            },
+            _ => PackageSource::Aur,
+        }
+    }
+
+    pub fn set_source(&mut self, source: PackageSource) {
+        (self.source_type, self.source_url, self.source_ref, self.source_subdirectory) = source_to_columns(source);
+    }
+
     pub fn get_package_job(&self, patches: Vec<PackagePatch>) -> PackageJob {
         PackageJob {
             definition: PackageDefinition {
@@ -91,8 +309,14 @@ impl Package {
                 name: self.name.clone(),
                 run_before: self.run_before.clone(),
                 patches: patches.into_iter().map(Into::into).collect(),
+                source: self.get_source(),
+                compiler_cache: self.compiler_cache,
+                build_env: self.get_build_env().clone(),
+                limits: self.get_limits(),
+                allow_network: self.allow_network,
             },
             last_built_version: self.last_built_version.clone(),
+            repo_name: None,
         }
     }
 
@@ -108,6 +332,31 @@ impl Package {
             ]),
             last_built_version: Some(String::from("1.2.3")),
             last_error: Some(String::from("When an error occurs it will show up here !")),
+            keep_versions: None,
+            source_type: SOURCE_TYPE_AUR,
+            source_url: None,
+            source_ref: None,
+            source_subdirectory: None,
+            upstream_version: Some(String::from("1.2.3-1")),
+            upstream_last_modified: Some(Utc::now().timestamp()),
+            priority: 0,
+            rebuild_interval: None,
+            schedule: None,
+            enabled: true,
+            dependencies: StringArray(vec![]),
+            required_arch: None,
+            min_cpus: None,
+            min_disk: None,
+            required_tags: StringArray(vec![]),
+            retry_count: 0,
+            retry_at: None,
+            build_timeout: None,
+            compiler_cache: false,
+            build_env: JsonColumn::default(),
+            limits: JsonColumn::default(),
+            allow_network: false,
+            implicit: false,
+            required_by: StringArray(vec![]),
         }
     }
 }
@@ -115,6 +364,14 @@ impl Package {
 impl Into<PackageResponse> for Package {
     fn into(self) -> PackageResponse {
         PackageResponse {
+            source: self.get_source(),
+            requirements: self.get_requirements(),
+            build_env: self.get_build_env().clone(),
+            limits: self.get_limits(),
+            allow_network: self.allow_network,
+            implicit: self.implicit,
+            next_retry: self.get_retry_at(),
+            upstream_last_modified: self.get_upstream_last_modified(),
             id: self.get_id(),
             name: self.get_name().to_string(),
             status: self.get_status(),
@@ -123,15 +380,61 @@ impl Into<PackageResponse> for Package {
             run_before: self.run_before,
             last_built_version: self.last_built_version,
             last_error: self.last_error,
+            keep_versions: self.keep_versions,
+            upstream_version: self.upstream_version,
+            priority: self.priority,
+            rebuild_interval: self.rebuild_interval,
+            build_timeout: self.build_timeout,
+            compiler_cache: self.compiler_cache,
+            schedule: self.schedule,
+            enabled: self.enabled,
+            retry_count: self.retry_count,
+            dependencies: self.dependencies.0,
+            required_by: self.required_by.0,
         }
     }
 }
 
-#[derive(Insertable)]
+#[derive(Insertable, Default)]
 #[diesel(table_name = schema::packages)]
 pub struct PackageInsert {
     pub name: String,
     pub run_before: Option<String>,
+    pub keep_versions: Option<i32>,
+    pub source_type: i16,
+    pub source_url: Option<String>,
+    pub source_ref: Option<String>,
+    pub source_subdirectory: Option<String>,
+    pub required_arch: Option<String>,
+    pub min_cpus: Option<i32>,
+    pub min_disk: Option<i64>,
+    pub required_tags: StringArray,
+    pub build_env: JsonColumn<BuildEnvironment>,
+    pub limits: JsonColumn<ResourceLimits>,
+    pub implicit: bool,
+    pub required_by: StringArray,
+}
+
+impl PackageInsert {
+    pub fn with_source(mut self, source: PackageSource) -> Self {
+        (self.source_type, self.source_url, self.source_ref, self.source_subdirectory) = source_to_columns(source);
+        self
+    }
+
+    pub fn with_requirements(mut self, requirements: PackageRequirements) -> Self {
+        (self.required_arch, self.min_cpus, self.min_disk, self.required_tags) = requirements_to_columns(requirements);
+        self
+    }
+
+    pub fn with_build_env(mut self, build_env: BuildEnvironment) -> Self {
+        self.build_env = JsonColumn(build_env);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = JsonColumn(limits);
+        self
+    }
 }
 
 #[derive(Queryable, Selectable, Debug, AsChangeset)]
@@ -183,6 +486,180 @@ pub struct PackagePatchInsert {
     pub sha_512: Option<String>
 }
 
+#[derive(Queryable, Selectable, Debug, AsChangeset)]
+#[diesel(table_name = schema::builds)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Build {
+    id: i32,
+    package_id: i32,
+    pub worker_id: Option<i32>,
+    status: i16,
+    started_at: i64,
+    ended_at: Option<i64>,
+    pub version: Option<String>,
+    pub error: Option<String>,
+    pub log_file: Option<String>,
+    error_class: Option<String>,
+    compiler_cache_hits: Option<i64>,
+    compiler_cache_misses: Option<i64>,
+}
+
+impl Build {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_package_id(&self) -> i32 {
+        self.package_id
+    }
+
+    pub fn get_status(&self) -> BuildStatus {
+        BuildStatus::from_u8(self.status as u8)
+    }
+
+    pub fn set_status(&mut self, status: BuildStatus) {
+        self.status = status.into();
+    }
+
+    pub fn get_started_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.started_at, 0).unwrap()
+    }
+
+    pub fn get_ended_at(&self) -> Option<DateTime<Utc>> {
+        self.ended_at.map(|ts| DateTime::from_timestamp(ts, 0).unwrap())
+    }
+
+    pub fn set_ended_at(&mut self, ended_at: Option<DateTime<Utc>>) {
+        self.ended_at = ended_at.map(|t| t.timestamp());
+    }
+
+    pub fn get_error_class(&self) -> Option<BuildErrorClass> {
+        self.error_class.as_ref().and_then(|c| c.parse().ok())
+    }
+
+    pub fn set_error_class(&mut self, error_class: Option<BuildErrorClass>) {
+        self.error_class = error_class.map(|c| c.to_string());
+    }
+
+    pub fn get_compiler_cache(&self) -> Option<CompilerCacheStats> {
+        Some(CompilerCacheStats {
+            hits: self.compiler_cache_hits? as u64,
+            misses: self.compiler_cache_misses? as u64,
+        })
+    }
+
+    pub fn set_compiler_cache(&mut self, stats: Option<CompilerCacheStats>) {
+        self.compiler_cache_hits = stats.map(|s| s.hits as i64);
+        self.compiler_cache_misses = stats.map(|s| s.misses as i64);
+    }
+
+    /// Marks the build as finished now with the given outcome.
+    pub fn finish(&mut self, status: BuildStatus, error: Option<String>) {
+        self.set_status(status);
+        self.set_ended_at(Some(Utc::now()));
+        self.error = error;
+    }
+}
+
+impl From<Build> for BuildResponse {
+    fn from(build: Build) -> Self {
+        BuildResponse {
+            id: build.id,
+            package_id: build.package_id,
+            worker_id: build.worker_id,
+            status: build.get_status(),
+            started_at: build.get_started_at(),
+            ended_at: build.get_ended_at(),
+            error_class: build.get_error_class(),
+            compiler_cache: build.get_compiler_cache(),
+            version: build.version,
+            error: build.error,
+            log_file: build.log_file,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::builds)]
+pub struct BuildInsert {
+    pub package_id: i32,
+    pub worker_id: Option<i32>,
+    pub status: i16,
+    pub started_at: i64,
+}
+
+impl BuildInsert {
+    pub fn running(package_id: i32, worker_id: Option<i32>) -> Self {
+        BuildInsert {
+            package_id,
+            worker_id,
+            status: BuildStatus::RUNNING.into(),
+            started_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = schema::package_versions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PackageVersion {
+    id: i32,
+    package_id: i32,
+    pub version: String,
+    files: StringArray,
+    created_at: i64,
+}
+
+impl PackageVersion {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn get_package_id(&self) -> i32 {
+        self.package_id
+    }
+
+    pub fn get_files(&self) -> &Vec<String> {
+        &self.files.0
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.created_at, 0).unwrap()
+    }
+}
+
+impl From<PackageVersion> for PackageVersionResponse {
+    fn from(version: PackageVersion) -> Self {
+        PackageVersionResponse {
+            id: version.id,
+            package_id: version.package_id,
+            created_at: version.get_created_at(),
+            version: version.version,
+            files: version.files.0,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schema::package_versions)]
+pub struct PackageVersionInsert {
+    package_id: i32,
+    version: String,
+    files: StringArray,
+    created_at: i64,
+}
+
+impl PackageVersionInsert {
+    pub fn new(package_id: i32, version: String, files: Vec<String>, created_at: DateTime<Utc>) -> Self {
+        PackageVersionInsert {
+            package_id,
+            version,
+            files: StringArray(files),
+            created_at: created_at.timestamp(),
+        }
+    }
+}
+
 pub struct PackageStore {
     connection: Arc<Mutex<SqliteConnection>>
 }
@@ -259,10 +736,17 @@ impl PackageStore {
         Ok(())
     }
 
-    pub async fn set_packages_rebuild(&mut self, rebuild_interval: i64) -> Result<usize> {
+    /// Marks packages last built before the given interval as pending.
+    /// When `package_ids` is given only those packages are considered.
+    pub async fn set_packages_rebuild(&mut self, rebuild_interval: i64, package_ids: Option<Vec<i32>>) -> Result<usize> {
         let cutoff = Utc::now().sub(TimeDelta::seconds(rebuild_interval)).timestamp();
 
-        let res = diesel::update(schema::packages::table)
+        let mut query = diesel::update(schema::packages::table).into_boxed();
+        if let Some(package_ids) = package_ids {
+            query = query.filter(schema::packages::id.eq_any(package_ids));
+        }
+
+        let res = query
             .filter(schema::packages::last_built.lt(cutoff))
             .filter(schema::packages::status.eq_any::<Vec<i16>>(vec![
                 PackageStatus::FAILED.into(),
@@ -318,15 +802,16 @@ impl PackageStore {
         Ok(package)
     }
 
-    pub async fn get_next_pending_package(&mut self) -> Result<Option<Package>>
+    /// Returns the enabled pending packages in the order they should be dispatched.
+    pub async fn get_pending_packages(&mut self) -> Result<Vec<Package>>
     {
-        let package = schema::packages::dsl::packages
-            .order(schema::packages::id.asc())
+        let packages = schema::packages::dsl::packages
+            .order((schema::packages::priority.desc(), schema::packages::id.asc()))
             .filter(schema::packages::status.eq(PackageStatus::PENDING as i16))
-            .limit(1)
+            .filter(schema::packages::enabled.eq(true))
             .select(Package::as_select())
-            .first::<Package>(self.connection.lock().await.deref_mut()).optional()?;
-        Ok(package)
+            .load::<Package>(self.connection.lock().await.deref_mut())?;
+        Ok(packages)
     }
 
     pub async fn create_patch(&mut self, patch: PackagePatchInsert) -> Result<PackagePatch> {
@@ -373,13 +858,120 @@ impl PackageStore {
 
         Ok(patches)
     }
+
+    /// Stores a new published version of a package, replacing any previous record of the same version.
+    pub async fn create_package_version(&mut self, version: PackageVersionInsert) -> Result<PackageVersion> {
+        let mut connection = self.connection.lock().await;
+
+        diesel::delete(schema::package_versions::table)
+            .filter(schema::package_versions::package_id.eq(version.package_id))
+            .filter(schema::package_versions::version.eq(&version.version))
+            .execute(connection.deref_mut())?;
+
+        let version = diesel::insert_into(schema::package_versions::table)
+            .values(&version)
+            .returning(PackageVersion::as_returning())
+            .get_result(connection.deref_mut())?;
+        Ok(version)
+    }
+
+    pub async fn delete_package_version(&mut self, id: i32) -> Result<()> {
+        diesel::delete(schema::package_versions::table)
+            .filter(schema::package_versions::id.eq(id))
+            .execute(self.connection.lock().await.deref_mut())?;
+        Ok(())
+    }
+
+    pub async fn get_package_version(&mut self, package_id: i32, id: i32) -> Result<Option<PackageVersion>> {
+        Ok(
+            schema::package_versions::dsl::package_versions
+                .filter(schema::package_versions::package_id.eq(package_id))
+                .filter(schema::package_versions::id.eq(id))
+                .select(PackageVersion::as_select())
+                .first::<PackageVersion>(self.connection.lock().await.deref_mut())
+                .optional()?
+        )
+    }
+
+    /// Returns the retained versions of a package, most recent first.
+    pub async fn get_package_versions(&mut self, package_id: i32) -> Result<Vec<PackageVersion>> {
+        let versions = schema::package_versions::dsl::package_versions
+            .filter(schema::package_versions::package_id.eq(package_id))
+            .order((schema::package_versions::created_at.desc(), schema::package_versions::id.desc()))
+            .select(PackageVersion::as_select())
+            .load::<PackageVersion>(self.connection.lock().await.deref_mut())?;
+        Ok(versions)
+    }
+
+    pub async fn create_build(&mut self, build: BuildInsert) -> Result<Build> {
+        let build = diesel::insert_into(schema::builds::table)
+            .values(&build)
+            .returning(Build::as_returning())
+            .get_result(self.connection.lock().await.deref_mut())?;
+        Ok(build)
+    }
+
+    pub async fn update_build(&mut self, build: &Build) -> Result<Build> {
+        let build = diesel::update(schema::builds::table)
+            .filter(schema::builds::id.eq(build.id))
+            .set(build)
+            .returning(Build::as_returning())
+            .get_result(self.connection.lock().await.deref_mut())?;
+        Ok(build)
+    }
+
+    pub async fn get_build(&mut self, package_id: i32, id: i32) -> Result<Option<Build>> {
+        Ok(
+            schema::builds::dsl::builds
+                .filter(schema::builds::package_id.eq(package_id))
+                .filter(schema::builds::id.eq(id))
+                .select(Build::as_select())
+                .first::<Build>(self.connection.lock().await.deref_mut())
+                .optional()?
+        )
+    }
+
+    /// Returns the builds of a package, most recent first.
+    pub async fn get_builds_for_package(
+        &mut self,
+        package_id: i32,
+        status: Option<BuildStatus>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Build>> {
+        let mut query = schema::builds::dsl::builds
+            .filter(schema::builds::package_id.eq(package_id))
+            .order(schema::builds::id.desc())
+            .select(Build::as_select())
+            .into_boxed();
+
+        if let Some(status) = status {
+            query = query.filter(schema::builds::status.eq::<i16>(status.into()));
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+
+        Ok(query.load::<Build>(self.connection.lock().await.deref_mut())?)
+    }
+
+    pub async fn get_running_build(&mut self, package_id: i32) -> Result<Option<Build>> {
+        Ok(
+            schema::builds::dsl::builds
+                .filter(schema::builds::package_id.eq(package_id))
+                .filter(schema::builds::status.eq::<i16>(BuildStatus::RUNNING.into()))
+                .order(schema::builds::id.desc())
+                .select(Build::as_select())
+                .first::<Build>(self.connection.lock().await.deref_mut())
+                .optional()?
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::{TimeDelta, Utc};
-    use common::models::PackageStatus;
-    use crate::persistence::package_store::{PackageInsert, PackagePatchInsert, PackageStore};
+    use common::models::{BuildStatus, PackageSource, PackageStatus};
+    use crate::persistence::package_store::{BuildInsert, Package, PackageInsert, StringArray, PackagePatchInsert, PackageStore, PackageVersionInsert};
 
     async fn get_instance() -> PackageStore {
         let mut package_repository = PackageStore::in_memory().unwrap();
@@ -395,6 +987,7 @@ mod tests {
         let package = package_repository.create_package(PackageInsert {
             name: "Name".to_string(),
             run_before: Some("echo 1".to_string()),
+            ..Default::default()
         }).await.unwrap();
         assert_eq!(1, package.id);
         assert_eq!("Name", package.name);
@@ -402,6 +995,36 @@ mod tests {
         assert_eq!(PackageStatus::PENDING, package.get_status());
     }
 
+    #[tokio::test]
+    async fn test_package_source() {
+        let mut package_repository = get_instance().await;
+
+        let source = PackageSource::Git {
+            url: "https://git.example.com/pkgbuilds.git".to_string(),
+            reference: Some("main".to_string()),
+            subdirectory: Some("packages/name".to_string()),
+        };
+        let mut package = package_repository.create_package(PackageInsert {
+            name: "Name".to_string(),
+            ..Default::default()
+        }.with_source(source.clone())).await.unwrap();
+        assert_eq!(source, package.get_source());
+        assert_eq!(source, package.get_package_job(vec![]).definition.source);
+
+        package.set_source(PackageSource::Local { path: "/srv/name".to_string() });
+        package_repository.update_package(&package).await.unwrap();
+        let package = package_repository.get_package(package.id).await.unwrap().unwrap();
+        assert_eq!(PackageSource::Local { path: "/srv/name".to_string() }, package.get_source());
+        assert_eq!(None, package.source_ref);
+
+        package_repository.create_package(PackageInsert {
+            name: "Other".to_string(),
+            ..Default::default()
+        }).await.unwrap();
+        let package = package_repository.get_package_by_name("Other").await.unwrap().unwrap();
+        assert_eq!(PackageSource::Aur, package.get_source());
+    }
+
     #[tokio::test]
     async fn test_update_package() {
         let mut package_repository = get_instance().await;
@@ -409,6 +1032,7 @@ mod tests {
         let mut package = package_repository.create_package(PackageInsert {
             name: "Name".to_string(),
             run_before: Some("echo 1".to_string()),
+            ..Default::default()
         }).await.unwrap();
         let built_time = Utc::now();
 
@@ -434,6 +1058,39 @@ mod tests {
         assert_eq!(Some("last_error".to_string()), packages[0].last_error);
     }
 
+    #[tokio::test]
+    async fn test_update_package_clears_optional_fields() {
+        let mut package_repository = get_instance().await;
+
+        let mut package = package_repository.create_package(PackageInsert {
+            name: "Name".to_string(),
+            run_before: Some("echo 1".to_string()),
+            keep_versions: Some(3),
+            ..Default::default()
+        }).await.unwrap();
+        package.set_last_built(Some(Utc::now()));
+        package.last_built_version = Some("last_version".to_string());
+        package.last_error = Some("last_error".to_string());
+        package.schedule = Some("0 0 3 * * *".to_string());
+        package.set_retry_at(Some(Utc::now()));
+        package_repository.update_package(&package).await.unwrap();
+
+        package.run_before = None;
+        package.last_error = None;
+        package.schedule = None;
+        package.set_retry_at(None);
+        package_repository.update_package(&package).await.unwrap();
+
+        let package = package_repository.get_package(package.id).await.unwrap().unwrap();
+        assert_eq!(None, package.run_before);
+        assert_eq!(None, package.last_error);
+        assert_eq!(None, package.schedule);
+        assert!(package.get_retry_at().is_none());
+        assert_eq!(Some(3), package.keep_versions);
+        assert!(package.get_last_built().is_some());
+        assert_eq!(Some("last_version".to_string()), package.last_built_version);
+    }
+
     #[tokio::test]
     async fn test_update_package_status() {
         let mut package_repository = get_instance().await;
@@ -441,6 +1098,7 @@ mod tests {
         let package = package_repository.create_package(PackageInsert {
             name: "name".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
 
         assert_ne!(PackageStatus::BUILT, package.get_status());
@@ -457,10 +1115,12 @@ mod tests {
         package_repository.create_package(PackageInsert {
             name: "first".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
         package_repository.create_package(PackageInsert {
             name: "second".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
 
         package_repository.update_package_status(1, PackageStatus::BUILT).await.unwrap();
@@ -496,19 +1156,20 @@ mod tests {
         let mut package = package_repository.create_package(PackageInsert {
             name: "first".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
         package.set_status(PackageStatus::BUILT);
         package.set_last_built(Some(Utc::now()));
         package_repository.update_package(&package).await.unwrap();
 
-        package_repository.set_packages_rebuild(100).await.unwrap();
+        package_repository.set_packages_rebuild(100, None).await.unwrap();
 
         let mut package = package_repository.get_package_by_name("first").await.unwrap().unwrap();
         assert_eq!(PackageStatus::BUILT, package.get_status());
         package.set_last_built(Some(Utc::now() - TimeDelta::seconds(200)));
         package_repository.update_package(&package).await.unwrap();
 
-        package_repository.set_packages_rebuild(100).await.unwrap();
+        package_repository.set_packages_rebuild(100, None).await.unwrap();
 
         let mut package = package_repository.get_package_by_name("first").await.unwrap().unwrap();
         assert_eq!(PackageStatus::PENDING, package.get_status());
@@ -516,10 +1177,79 @@ mod tests {
         package.set_last_built(Some(Utc::now() - TimeDelta::seconds(200)));
         package_repository.update_package(&package).await.unwrap();
 
-        package_repository.set_packages_rebuild(100).await.unwrap();
+        package_repository.set_packages_rebuild(100, None).await.unwrap();
 
-        let package = package_repository.get_package_by_name("first").await.unwrap().unwrap();
+        let mut package = package_repository.get_package_by_name("first").await.unwrap().unwrap();
         assert_eq!(PackageStatus::BUILDING, package.get_status());
+
+        package.set_status(PackageStatus::BUILT);
+        package_repository.update_package(&package).await.unwrap();
+        package_repository.set_packages_rebuild(100, Some(vec![package.get_id() + 1])).await.unwrap();
+        let package = package_repository.get_package_by_name("first").await.unwrap().unwrap();
+        assert_eq!(PackageStatus::BUILT, package.get_status());
+    }
+
+    #[tokio::test]
+    async fn test_get_pending_packages() {
+        let mut package_repository = get_instance().await;
+
+        for (name, priority, enabled) in [("low", 0, true), ("high", 10, true), ("paused", 20, false)] {
+            let mut package = package_repository.create_package(PackageInsert {
+                name: name.to_string(),
+                ..Default::default()
+            }).await.unwrap();
+            package.priority = priority;
+            package.enabled = enabled;
+            package_repository.update_package(&package).await.unwrap();
+        }
+
+        let packages = package_repository.get_pending_packages().await.unwrap();
+        assert_eq!(vec!["high", "low"], packages.iter().map(|p| p.name.as_str()).collect::<Vec<_>>());
+
+        package_repository.update_package_status(packages[0].id, PackageStatus::BUILDING).await.unwrap();
+        let packages = package_repository.get_pending_packages().await.unwrap();
+        assert_eq!(1, packages.len());
+        assert_eq!("low", packages[0].name);
+    }
+
+    #[test]
+    fn test_package_get_provided_names() {
+        let mut package = Package::get_dummy();
+        package.files = StringArray(vec![
+            "test-package-1.2.3-1-x86_64.pkg.tar.zst".to_string(),
+            "test-package-docs-1.2.3-1-any.pkg.tar.zst".to_string(),
+            "test-package-docs-1.2.3-1-any.pkg.tar.zst.sig".to_string(),
+        ]);
+        assert_eq!(vec!["test-package", "test-package-docs"], package.get_provided_names());
+
+        package.set_dependencies(vec!["glibc".to_string(), "test-package-docs".to_string()]);
+        assert!(package.depends_on(&["test-package-docs".to_string()]));
+        assert!(!package.depends_on(&["other".to_string()]));
+    }
+
+    #[test]
+    fn test_package_is_rebuild_due() {
+        let now = Utc::now();
+        let mut package = Package::get_dummy();
+        package.set_last_built(Some(now - TimeDelta::hours(2)));
+        assert!(!package.has_rebuild_policy());
+        assert!(!package.is_rebuild_due(now));
+
+        package.rebuild_interval = Some(3600);
+        assert!(package.has_rebuild_policy());
+        assert!(package.is_rebuild_due(now));
+        package.rebuild_interval = Some(3 * 3600);
+        assert!(!package.is_rebuild_due(now));
+        package.rebuild_interval = Some(0);
+        assert!(!package.is_rebuild_due(now));
+
+        // Every minute
+        package.schedule = Some("0 * * * * *".to_string());
+        assert!(package.is_rebuild_due(now));
+        package.set_last_built(Some(now));
+        assert!(!package.is_rebuild_due(now));
+        package.schedule = Some("invalid".to_string());
+        assert!(!package.is_rebuild_due(now + TimeDelta::days(1)));
     }
 
     #[tokio::test]
@@ -529,10 +1259,12 @@ mod tests {
         package_repository.create_package(PackageInsert {
             name: "first".to_string(),
             run_before: Some("echo 1".to_string()),
+            ..Default::default()
         }).await.unwrap();
         package_repository.create_package(PackageInsert {
             name: "second".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
 
         package_repository.delete_package(1).await.unwrap();
@@ -548,10 +1280,12 @@ mod tests {
         package_repository.create_package(PackageInsert {
             name: "first".to_string(),
             run_before: Some("echo 1".to_string()),
+            ..Default::default()
         }).await.unwrap();
         package_repository.create_package(PackageInsert {
             name: "second".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
 
         let packages = package_repository.get_packages().await.unwrap();
@@ -567,8 +1301,8 @@ mod tests {
         let package = package_repository.get_package_by_name("none").await.unwrap();
         assert!(package.is_none());
 
-        let package = package_repository.get_next_pending_package().await.unwrap();
-        assert_eq!("first", package.unwrap().name);
+        let packages = package_repository.get_pending_packages().await.unwrap();
+        assert_eq!("first", packages[0].name);
     }
 
     #[tokio::test]
@@ -578,6 +1312,7 @@ mod tests {
         let package = package_repository.create_package(PackageInsert {
             name: "package".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
         package_repository.create_patch(PackagePatchInsert {
             package_id: package.id,
@@ -601,6 +1336,7 @@ mod tests {
         let package = package_repository.create_package(PackageInsert {
             name: "package".to_string(),
             run_before: None,
+            ..Default::default()
         }).await.unwrap();
         let mut patch = package_repository.create_patch(PackagePatchInsert {
             package_id: package.id,
@@ -615,4 +1351,80 @@ mod tests {
         assert_eq!("test_url_update", patches[0].url);
         assert_eq!(Some("sha".to_string()), patches[0].sha_512);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_create_list_builds() {
+        let mut package_repository = get_instance().await;
+
+        let package = package_repository.create_package(PackageInsert {
+            name: "package".to_string(),
+            run_before: None,
+            ..Default::default()
+        }).await.unwrap();
+
+        let mut first = package_repository.create_build(BuildInsert::running(package.id, Some(3))).await.unwrap();
+        assert_eq!(BuildStatus::RUNNING, first.get_status());
+        assert!(first.get_ended_at().is_none());
+        first.version = Some("1.0.0".to_string());
+        first.finish(BuildStatus::BUILT, None);
+        package_repository.update_build(&first).await.unwrap();
+
+        package_repository.create_build(BuildInsert::running(package.id, None)).await.unwrap();
+
+        let running = package_repository.get_running_build(package.id).await.unwrap().unwrap();
+        assert_eq!(2, running.get_id());
+        assert_eq!(None, running.worker_id);
+
+        let builds = package_repository.get_builds_for_package(package.id, None, None).await.unwrap();
+        assert_eq!(2, builds.len());
+        assert_eq!(2, builds[0].get_id());
+        assert_eq!(1, builds[1].get_id());
+
+        let builds = package_repository
+            .get_builds_for_package(package.id, Some(BuildStatus::BUILT), None).await.unwrap();
+        assert_eq!(1, builds.len());
+        assert_eq!(Some(3), builds[0].worker_id);
+        assert_eq!(Some("1.0.0".to_string()), builds[0].version);
+        assert!(builds[0].get_ended_at().is_some());
+
+        assert_eq!(1, package_repository.get_builds_for_package(package.id, None, Some(1)).await.unwrap().len());
+        assert!(package_repository.get_build(package.id + 1, 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_list_delete_package_versions() {
+        let mut package_repository = get_instance().await;
+
+        let package = package_repository.create_package(PackageInsert {
+            name: "package".to_string(),
+            run_before: None,
+            ..Default::default()
+        }).await.unwrap();
+
+        let now = Utc::now();
+        package_repository.create_package_version(PackageVersionInsert::new(
+            package.id, "1.0.0".to_string(), vec!["package-1.0.0.pkg.tar.zst".to_string()], now - TimeDelta::hours(1)
+        )).await.unwrap();
+        package_repository.create_package_version(PackageVersionInsert::new(
+            package.id, "1.1.0".to_string(), vec!["package-1.1.0.pkg.tar.zst".to_string()], now
+        )).await.unwrap();
+
+        let versions = package_repository.get_package_versions(package.id).await.unwrap();
+        assert_eq!(2, versions.len());
+        assert_eq!("1.1.0", versions[0].version);
+        assert_eq!("1.0.0", versions[1].version);
+
+        // Rebuilding an existing version replaces its record
+        let rebuilt = package_repository.create_package_version(PackageVersionInsert::new(
+            package.id, "1.0.0".to_string(), vec!["package-1.0.0-rebuilt.pkg.tar.zst".to_string()], now + TimeDelta::hours(1)
+        )).await.unwrap();
+        let versions = package_repository.get_package_versions(package.id).await.unwrap();
+        assert_eq!(2, versions.len());
+        assert_eq!(rebuilt.get_id(), versions[0].get_id());
+        assert_eq!(&vec!["package-1.0.0-rebuilt.pkg.tar.zst".to_string()], versions[0].get_files());
+
+        package_repository.delete_package_version(rebuilt.get_id()).await.unwrap();
+        assert!(package_repository.get_package_version(package.id, rebuilt.get_id()).await.unwrap().is_none());
+        assert_eq!(1, package_repository.get_package_versions(package.id).await.unwrap().len());
+    }
+}
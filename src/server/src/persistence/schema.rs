@@ -1,5 +1,22 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    builds (id) {
+        id -> Integer,
+        package_id -> Integer,
+        worker_id -> Nullable<Integer>,
+        status -> SmallInt,
+        started_at -> BigInt,
+        ended_at -> Nullable<BigInt>,
+        version -> Nullable<Text>,
+        error -> Nullable<Text>,
+        log_file -> Nullable<Text>,
+        error_class -> Nullable<Text>,
+        compiler_cache_hits -> Nullable<BigInt>,
+        compiler_cache_misses -> Nullable<BigInt>,
+    }
+}
+
 diesel::table! {
     package_patches (id) {
         id -> Integer,
@@ -9,6 +26,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    package_versions (id) {
+        id -> Integer,
+        package_id -> Integer,
+        version -> Text,
+        files -> Text,
+        created_at -> BigInt,
+    }
+}
+
 diesel::table! {
     packages (id) {
         id -> Integer,
@@ -19,12 +46,41 @@ diesel::table! {
         files -> Text,
         last_built_version -> Nullable<Text>,
         last_error -> Nullable<Text>,
+        keep_versions -> Nullable<Integer>,
+        source_type -> SmallInt,
+        source_url -> Nullable<Text>,
+        source_ref -> Nullable<Text>,
+        source_subdirectory -> Nullable<Text>,
+        upstream_version -> Nullable<Text>,
+        upstream_last_modified -> Nullable<BigInt>,
+        priority -> Integer,
+        rebuild_interval -> Nullable<BigInt>,
+        schedule -> Nullable<Text>,
+        enabled -> Bool,
+        dependencies -> Text,
+        required_arch -> Nullable<Text>,
+        min_cpus -> Nullable<Integer>,
+        min_disk -> Nullable<BigInt>,
+        required_tags -> Text,
+        retry_count -> Integer,
+        retry_at -> Nullable<BigInt>,
+        build_timeout -> Nullable<BigInt>,
+        compiler_cache -> Bool,
+        build_env -> Text,
+        limits -> Text,
+        allow_network -> Bool,
+        implicit -> Bool,
+        required_by -> Text,
     }
 }
 
+diesel::joinable!(builds -> packages (package_id));
 diesel::joinable!(package_patches -> packages (package_id));
+diesel::joinable!(package_versions -> packages (package_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    builds,
     package_patches,
+    package_versions,
     packages,
 );
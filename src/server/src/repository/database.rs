@@ -0,0 +1,364 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::repository::package_file::{read_package_contents, PackageInfo};
+
+/// Sections of a `desc` file and the `.PKGINFO` keys they are read from, in the order repo-add writes them.
+const DESC_SECTIONS: &[(&str, &str)] = &[
+    ("NAME", "pkgname"),
+    ("BASE", "pkgbase"),
+    ("VERSION", "pkgver"),
+    ("DESC", "pkgdesc"),
+    ("GROUPS", "group"),
+];
+const DESC_LATE_SECTIONS: &[(&str, &str)] = &[
+    ("URL", "url"),
+    ("LICENSE", "license"),
+    ("ARCH", "arch"),
+    ("BUILDDATE", "builddate"),
+    ("PACKAGER", "packager"),
+    ("REPLACES", "replaces"),
+    ("CONFLICTS", "conflict"),
+    ("PROVIDES", "provides"),
+    ("DEPENDS", "depend"),
+    ("OPTDEPENDS", "optdepend"),
+    ("MAKEDEPENDS", "makedepend"),
+    ("CHECKDEPENDS", "checkdepend"),
+];
+
+/// Entry of a package in the repository databases, a directory holding its `desc` and `files`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseEntry {
+    pub name: String,
+    pub version: String,
+    pub file: String,
+    directory: String,
+    desc: String,
+    files: String,
+}
+
+impl DatabaseEntry {
+    /// Generates the entry of the given package file like repo-add does, including its signature if it has one.
+    pub async fn from_package_file(path: PathBuf) -> Result<DatabaseEntry>
+    {
+        let file = path.file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .with_context(|| format!("Invalid package path {:?}", path))?;
+        let contents = read_package_contents(path.clone()).await
+            .with_context(|| format!("Failed to read package {}", file))?;
+        let (size, sha256) = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || hash_file(&path)
+        }).await??;
+        let signature = match tokio::fs::read(format!("{}.sig", path.display())).await {
+            Ok(signature) => Some(STANDARD.encode(signature)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read signature of {}", file)),
+        };
+
+        let info = &contents.info;
+        if info.get_name().is_empty() || info.get_version().is_empty() {
+            bail!("Package {} has no name or version in its .PKGINFO", file);
+        }
+
+        let mut desc = format_section("FILENAME", &[file.as_str()]);
+        desc += &format_info_sections(info, DESC_SECTIONS);
+        desc += &format_section("CSIZE", &[size.to_string().as_str()]);
+        desc += &format_section("ISIZE", &info.get_all("size"));
+        desc += &format_section("SHA256SUM", &[sha256.as_str()]);
+        if let Some(signature) = signature.as_ref() {
+            desc += &format_section("PGPSIG", &[signature.as_str()]);
+        }
+        desc += &format_info_sections(info, DESC_LATE_SECTIONS);
+
+        let files: Vec<&str> = contents.files.iter().map(|f| f.as_str()).collect();
+
+        Ok(DatabaseEntry {
+            name: info.get_name().to_string(),
+            version: info.get_version().to_string(),
+            directory: format!("{}-{}", info.get_name(), info.get_version()),
+            file,
+            desc,
+            files: format_section("FILES", &files),
+        })
+    }
+
+    /// Builds an entry from the `desc` and `files` read from a database.
+    fn from_database(directory: String, desc: String, files: String) -> Result<DatabaseEntry>
+    {
+        let sections = parse_sections(&desc);
+        let get = |key: &str| sections.get(key).and_then(|values| values.first()).cloned();
+        let (Some(name), Some(version), Some(file)) = (get("NAME"), get("VERSION"), get("FILENAME")) else {
+            bail!("Database entry {} has no name, version or file name", directory);
+        };
+        Ok(DatabaseEntry { name, version, file, directory, desc, files })
+    }
+
+    /// Whether the size, checksum and signature of the entry are the ones of the given package file on disk.
+    pub async fn matches_package_file(&self, path: PathBuf) -> Result<bool>
+    {
+        let (size, sha256) = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || hash_file(&path)
+        }).await??;
+        let signature = match tokio::fs::read(format!("{}.sig", path.display())).await {
+            Ok(signature) => Some(STANDARD.encode(signature)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read signature of {:?}", path)),
+        };
+
+        let sections = parse_sections(&self.desc);
+        let get = |key: &str| sections.get(key).and_then(|values| values.first()).cloned();
+        Ok(get("CSIZE") == Some(size.to_string()) && get("SHA256SUM") == Some(sha256) && get("PGPSIG") == signature)
+    }
+}
+
+fn format_section(key: &str, values: &[&str]) -> String
+{
+    if values.is_empty() {
+        return String::new();
+    }
+    format!("%{}%\n{}\n\n", key, values.join("\n"))
+}
+
+fn format_info_sections(info: &PackageInfo, sections: &[(&str, &str)]) -> String
+{
+    sections.iter()
+        .map(|(section, key)| match *key {
+            "pkgbase" => format_section(section, &[info.get_base()]),
+            _ => format_section(section, &info.get_all(key)),
+        })
+        .collect()
+}
+
+/// Parses the `%SECTION%` blocks of a `desc` file.
+fn parse_sections(content: &str) -> BTreeMap<String, Vec<String>>
+{
+    let mut sections: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut current = None;
+    for line in content.lines() {
+        if let Some(section) = line.strip_prefix('%').and_then(|l| l.strip_suffix('%')) {
+            current = Some(section.to_string());
+            sections.entry(section.to_string()).or_default();
+        } else if line.is_empty() {
+            current = None;
+        } else if let Some(section) = current.as_ref() {
+            sections.entry(section.clone()).or_default().push(line.to_string());
+        }
+    }
+    sections
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)>
+{
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher)?;
+    Ok((size, base16ct::lower::encode_string(&hasher.finalize())))
+}
+
+/// Reads the entries of a files database, the `$repo.files.tar.gz` written by repo-add or [`write_databases`].
+pub fn read_files_database(path: &Path) -> Result<Vec<DatabaseEntry>>
+{
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut archive = Archive::new(GzDecoder::new(file));
+    let mut contents: BTreeMap<String, (String, String)> = BTreeMap::new();
+
+    for entry in archive.entries().with_context(|| format!("Failed to read {:?}", path))? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        let Some((directory, name)) = entry_path.split_once('/') else {
+            continue;
+        };
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        let (desc, files) = contents.entry(directory.to_string()).or_default();
+        match name {
+            "desc" => *desc = content,
+            "files" => *files = content,
+            _ => {}
+        }
+    }
+
+    contents.into_iter()
+        .map(|(directory, (desc, files))| DatabaseEntry::from_database(directory, desc, files))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("Invalid database {:?}", path))
+}
+
+/// Kinds of the databases of a repository, `$repo.db.tar.gz` and `$repo.files.tar.gz`.
+pub const DATABASE_KINDS: [&str; 2] = ["db", "files"];
+
+/// Temporary file a database is written to, and signed as, before being moved in place.
+pub fn get_temporary_database_path(path: &Path, repo_name: &str, kind: &str) -> PathBuf
+{
+    path.join(format!(".{}.{}.tar.gz.tmp", repo_name, kind))
+}
+
+/// Writes `$repo.db.tar.gz` and `$repo.files.tar.gz` with the given entries to their temporary files in the repository directory.
+/// They are moved over the previous ones by [`install_database`], so clients never sync a partial database.
+pub fn write_databases(path: &Path, repo_name: &str, entries: &[DatabaseEntry]) -> Result<Vec<PathBuf>>
+{
+    let mut entries: Vec<&DatabaseEntry> = entries.iter().collect();
+    entries.sort_by(|a, b| a.directory.cmp(&b.directory));
+
+    let mut written = Vec::new();
+    for kind in DATABASE_KINDS {
+        let temporary = get_temporary_database_path(path, repo_name, kind);
+        write_database_archive(&temporary, &entries, kind == "files")
+            .with_context(|| format!("Failed to write {:?}", temporary))?;
+        written.push(temporary);
+    }
+    Ok(written)
+}
+
+/// Moves a database written by [`write_databases`] in place, immediately followed by its signature when it was signed.
+/// Signatures of the previous database are removed otherwise.
+pub fn install_database(path: &Path, repo_name: &str, kind: &str, signed: bool) -> Result<PathBuf>
+{
+    let temporary = get_temporary_database_path(path, repo_name, kind);
+    let temporary_signature = PathBuf::from(format!("{}.sig", temporary.display()));
+    let database = path.join(format!("{}.{}.tar.gz", repo_name, kind));
+    let signature = PathBuf::from(format!("{}.sig", database.display()));
+
+    std::fs::rename(&temporary, &database)
+        .with_context(|| format!("Failed to move {:?} to {:?}", temporary, database))?;
+    if signed {
+        std::fs::rename(&temporary_signature, &signature)
+            .with_context(|| format!("Failed to move {:?} to {:?}", temporary_signature, signature))?;
+    }
+    link_database(path, &format!("{}.{}", repo_name, kind), &format!("{}.{}.tar.gz", repo_name, kind))?;
+
+    let link = path.join(format!("{}.{}.sig", repo_name, kind));
+    if signed {
+        link_database(path, &format!("{}.{}.sig", repo_name, kind), &format!("{}.{}.tar.gz.sig", repo_name, kind))?;
+    } else {
+        for path in [&link, &signature] {
+            if std::fs::symlink_metadata(path).is_ok() {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {:?}", path))?;
+            }
+        }
+    }
+    Ok(database)
+}
+
+fn write_database_archive(path: &Path, entries: &[&DatabaseEntry], with_files: bool) -> Result<()>
+{
+    let file = BufWriter::new(File::create(path)?);
+    let mut builder = Builder::new(GzEncoder::new(file, Compression::default()));
+    let mtime = Utc::now().timestamp() as u64;
+
+    for entry in entries {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_mtime(mtime);
+        header.set_size(0);
+        builder.append_data(&mut header, format!("{}/", entry.directory), std::io::empty())?;
+
+        let mut files = vec![("desc", &entry.desc)];
+        if with_files {
+            files.push(("files", &entry.files));
+        }
+        for (name, content) in files {
+            let mut header = Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_size(content.len() as u64);
+            builder.append_data(&mut header, format!("{}/{}", entry.directory, name), content.as_bytes())?;
+        }
+    }
+
+    let mut file = builder.into_inner()?.finish()?;
+    file.flush()?;
+    file.get_ref().sync_all()?;
+    Ok(())
+}
+
+/// Points the `$repo.db` like names pacman downloads to the given archive, as repo-add does.
+pub fn link_database(path: &Path, name: &str, target: &str) -> Result<()>
+{
+    let link = path.join(name);
+    match std::fs::read_link(&link) {
+        Ok(current) if current == Path::new(target) => return Ok(()),
+        Ok(_) => std::fs::remove_file(&link)?,
+        Err(_) if link.exists() => std::fs::remove_file(&link)?,
+        Err(_) => {}
+    }
+    std::os::unix::fs::symlink(target, &link)
+        .with_context(|| format!("Failed to link {:?} to {}", link, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::repository::database::{install_database, parse_sections, read_files_database, write_databases, DatabaseEntry};
+
+    #[tokio::test]
+    async fn write_and_read_databases() {
+        let path = tempfile::tempdir().unwrap();
+        let package = path.path().join("aur-build-cli-0.10.0-1-any.pkg.tar.zst");
+        tokio::fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("aur-build-cli-0.10.0-1-any.pkg.tar.zst"),
+            &package,
+        ).await.unwrap();
+
+        let entry = DatabaseEntry::from_package_file(package.clone()).await.unwrap();
+        assert_eq!("aur-build-cli", entry.name);
+        assert_eq!("0.10.0-1", entry.version);
+        assert_eq!("aur-build-cli-0.10.0-1", entry.directory);
+
+        let sections = parse_sections(&entry.desc);
+        assert_eq!(vec!["aur-build-cli-0.10.0-1-any.pkg.tar.zst"], sections["FILENAME"]);
+        assert_eq!(vec!["0.10.0-1"], sections["VERSION"]);
+        assert_eq!(vec!["4420088"], sections["ISIZE"]);
+        assert_eq!(vec!["cargo"], sections["MAKEDEPENDS"]);
+        assert_eq!(64, sections["SHA256SUM"][0].len());
+        assert!(!sections.contains_key("PGPSIG"));
+        assert_eq!("%FILES%\nusr/\nusr/bin/\nusr/bin/aur-build-cli\n\n", entry.files);
+
+        write_databases(path.path(), "test", std::slice::from_ref(&entry)).unwrap();
+        assert!(!path.path().join("test.db").exists());
+        assert!(path.path().join(".test.db.tar.gz.tmp").exists());
+
+        std::fs::write(path.path().join("test.db.tar.gz.sig"), "old signature").unwrap();
+        std::os::unix::fs::symlink("test.db.tar.gz.sig", path.path().join("test.db.sig")).unwrap();
+        install_database(path.path(), "test", "db", false).unwrap();
+        assert!(path.path().join("test.db").exists());
+        assert!(path.path().join("test.db.tar.gz").exists());
+        assert!(!path.path().join(".test.db.tar.gz.tmp").exists());
+        assert!(std::fs::symlink_metadata(path.path().join("test.db.sig")).is_err());
+        assert!(!path.path().join("test.db.tar.gz.sig").exists());
+
+        std::fs::write(path.path().join(".test.files.tar.gz.tmp.sig"), "signature").unwrap();
+        install_database(path.path(), "test", "files", true).unwrap();
+        assert_eq!("signature", std::fs::read_to_string(path.path().join("test.files.sig")).unwrap());
+        assert!(!path.path().join(".test.files.tar.gz.tmp.sig").exists());
+
+        let entries = read_files_database(&path.path().join("test.files")).unwrap();
+        assert_eq!(vec![entry.clone()], entries);
+        assert!(entry.matches_package_file(package.clone()).await.unwrap());
+
+        tokio::fs::write(format!("{}.sig", package.display()), "signature").await.unwrap();
+        assert!(!entry.matches_package_file(package).await.unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use crate::models::config::Config;
+
+/// Location of the repository files on disk, one directory per architecture following the `$repo/os/$arch` layout.
+#[derive(Clone, Debug)]
+pub struct RepositoryLayout {
+    path: PathBuf,
+    repo_name: String,
+    architectures: Vec<String>,
+}
+
+impl RepositoryLayout {
+    pub fn new(path: PathBuf, repo_name: String, architectures: Vec<String>) -> Self
+    {
+        RepositoryLayout {
+            path,
+            repo_name,
+            architectures,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self
+    {
+        Self::new(config.serve_path.clone(), config.repo_name.clone(), config.architectures.clone())
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_repo_name(&self) -> &String {
+        &self.repo_name
+    }
+
+    pub fn get_architectures(&self) -> &Vec<String> {
+        &self.architectures
+    }
+
+    pub fn get_arch_path(&self, arch: &str) -> PathBuf {
+        self.path.join(&self.repo_name).join("os").join(arch)
+    }
+
+    /// Architectures whose repository the given package file belongs to.
+    /// `any` packages belong to all of them, files without an architecture in their name go to the first one.
+    pub fn get_file_architectures(&self, file: &str) -> Vec<String> {
+        match get_file_arch(file) {
+            Some("any") => self.architectures.clone(),
+            Some(arch) => vec![arch.to_string()],
+            None => self.architectures.first().cloned().into_iter().collect(),
+        }
+    }
+
+    /// Paths of the given package file in every repository it belongs to.
+    pub fn get_file_paths(&self, file: &str) -> Vec<PathBuf> {
+        self.get_file_architectures(file)
+            .iter()
+            .map(|arch| self.get_arch_path(arch).join(file))
+            .collect()
+    }
+}
+
+/// Extracts the architecture of a package file named `name-pkgver-pkgrel-arch.pkg.tar.*`.
+fn get_file_arch(file: &str) -> Option<&str> {
+    let index = file.find(".pkg.tar")?;
+    let mut parts = file[..index].rsplitn(4, '-');
+    let arch = parts.next()?;
+    // name, pkgver and pkgrel are expected before the architecture
+    parts.nth(2)?;
+    Some(arch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::repository::layout::RepositoryLayout;
+
+    #[test]
+    fn get_file_paths() {
+        let layout = RepositoryLayout::new(
+            PathBuf::from("/srv/repo"),
+            "test".to_string(),
+            vec!["x86_64".to_string(), "aarch64".to_string()],
+        );
+
+        assert_eq!(
+            vec![PathBuf::from("/srv/repo/test/os/aarch64/package-1.0-1-aarch64.pkg.tar.zst")],
+            layout.get_file_paths("package-1.0-1-aarch64.pkg.tar.zst")
+        );
+        assert_eq!(
+            vec![
+                PathBuf::from("/srv/repo/test/os/x86_64/package-1.0-1-any.pkg.tar.zst"),
+                PathBuf::from("/srv/repo/test/os/aarch64/package-1.0-1-any.pkg.tar.zst"),
+            ],
+            layout.get_file_paths("package-1.0-1-any.pkg.tar.zst")
+        );
+        assert_eq!(
+            vec![PathBuf::from("/srv/repo/test/os/x86_64/package.pkg.tar.zst")],
+            layout.get_file_paths("package.pkg.tar.zst")
+        );
+    }
+}
@@ -1,29 +1,38 @@
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use tokio::fs::{remove_file, try_exists};
 use tokio::process::Command;
 
+use common::http::responses::RepositoryCheckResponse;
+use common::version::vercmp;
+
 use crate::models::config::Config;
+use crate::repository::database::{install_database, read_files_database, write_databases, DatabaseEntry, DATABASE_KINDS};
+use crate::repository::layout::RepositoryLayout;
+use crate::repository::package_file::read_package_info;
 
 pub struct RepositoryManager {
-    pub repo_name: String,
     pub sign_key: Option<String>,
-    pub path: PathBuf,
+    pub layout: RepositoryLayout,
 }
 
 impl RepositoryManager {
-    pub async fn new(repo_name: String, sign_key: Option<String>, path: PathBuf) -> Result<Self>
+    pub async fn new(sign_key: Option<String>, layout: RepositoryLayout) -> Result<Self>
     {
         let instance = RepositoryManager {
-            repo_name,
             sign_key,
-            path,
+            layout,
         };
 
-        if !instance.path.exists() {
-            tokio::fs::create_dir_all(&instance.path).await?;
+        for arch in instance.layout.get_architectures() {
+            let path = instance.layout.get_arch_path(arch);
+            if !path.exists() {
+                tokio::fs::create_dir_all(&path).await?;
+            }
         }
 
         Ok(instance)
@@ -31,27 +40,68 @@ impl RepositoryManager {
 
     pub async fn from_config(config: &Config) -> Result<Self>
     {
-        Self::new(config.repo_name.clone(), config.sign_key.clone(), config.serve_path.clone()).await
+        Self::new(config.sign_key.clone(), RepositoryLayout::from_config(config)).await
+    }
+
+    pub async fn get_package_files(&self, arch: &str) -> Result<Vec<String>>
+    {
+        Ok(self.read_arch_directory(arch).await?.into_iter().filter(|name| !name.ends_with(".sig")).collect())
     }
 
-    #[allow(dead_code)]
-    pub async fn get_package_files(&self) -> Result<Vec<String>>
+    /// Names of the package files and package signatures in the repository of the given architecture.
+    async fn read_arch_directory(&self, arch: &str) -> Result<Vec<String>>
     {
-        let mut dir = tokio::fs::read_dir(&self.path).await
-            .with_context(|| format!("Failed to read directory {:?}", self.path))?;
+        let path = self.layout.get_arch_path(arch);
+        let mut dir = tokio::fs::read_dir(&path).await
+            .with_context(|| format!("Failed to read directory {:?}", path))?;
 
         let mut packages = Vec::new();
 
         while let Some(entry) = dir.next_entry().await? {
-            if entry.file_name().to_str().unwrap().ends_with(".pkg.tar.zst") {
-                packages.push(entry.file_name().to_str().unwrap().to_string());
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.contains(".pkg.tar.") {
+                packages.push(name);
             }
         }
 
+        packages.sort();
         Ok(packages)
     }
 
+    /// Copies a package file into the repository of every architecture it belongs to.
+    pub async fn store_package_file(&self, source: &Path, file: &str) -> Result<()> {
+        for path in self.layout.get_file_paths(file) {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            debug!("Copying {:?} to {:?}", source, path);
+            tokio::fs::copy(source, &path).await
+                .with_context(|| format!("Failed to copy {:?} to {:?}", source, path))?;
+        }
+        Ok(())
+    }
+
     pub async fn add_packages_to_repo(&self, package_files: Vec<String>) -> Result<()> {
+        let mut files_by_arch: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for file in package_files {
+            for arch in self.layout.get_file_architectures(&file) {
+                files_by_arch.entry(arch).or_default().push(file.clone());
+            }
+        }
+
+        for (arch, files) in files_by_arch {
+            self.add_packages_to_arch_repo(&self.layout.get_arch_path(&arch), files).await?;
+        }
+        Ok(())
+    }
+
+    async fn add_packages_to_arch_repo(&self, path: &Path, package_files: Vec<String>) -> Result<()> {
+        self.sign_package_files(path, &package_files).await?;
+        self.add_to_database(path, package_files).await
+    }
+
+    /// Signs the package files with the configured key, removes their old signatures without one.
+    async fn sign_package_files(&self, path: &Path, package_files: &[String]) -> Result<()> {
         if let Some(sign_key) = self.sign_key.as_ref() {
             for file in package_files.iter() {
                 let out = Command::new("gpg")
@@ -59,9 +109,9 @@ impl RepositoryManager {
                     .arg(sign_key)
                     .arg("--yes")
                     .arg("--output")
-                    .arg(self.path.join(format!("{}.sig", file)).to_str().unwrap())
+                    .arg(path.join(format!("{}.sig", file)).to_str().unwrap())
                     .arg("--detach-sig")
-                    .arg(self.path.join(file))
+                    .arg(path.join(file))
                     .output().await?;
 
                 if !out.status.success() {
@@ -73,7 +123,7 @@ impl RepositoryManager {
         } else {
             info!("Skipping signature ...");
             for file in package_files.iter() {
-                let sig_path = self.path.join(format!("{}.sig", file));
+                let sig_path = path.join(format!("{}.sig", file));
                 if let Ok(exists) = try_exists(&sig_path).await {
                     if exists {
                         match remove_file(&sig_path).await {
@@ -84,59 +134,374 @@ impl RepositoryManager {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Armored public key the repository is signed with, `None` when it is not signed.
+    pub async fn export_sign_key(&self) -> Result<Option<String>> {
+        let Some(sign_key) = self.sign_key.as_ref() else {
+            return Ok(None);
+        };
+        let out = Command::new("gpg")
+            .arg("--armor")
+            .arg("--export")
+            .arg(sign_key)
+            .output().await
+            .context("Failed to run gpg to export the signing key")?;
+        if !out.status.success() || out.stdout.is_empty() {
+            bail!("Failed to export the signing key {}, gpg exited with {:?}: {}", sign_key, out.status.code(), String::from_utf8_lossy(&out.stderr));
+        }
+        Ok(Some(String::from_utf8(out.stdout)?))
+    }
+
+    /// Removes the packages of the given files from the repository databases, leaving the files in place.
+    pub async fn remove_packages_from_repo(&self, package_files: &[String]) -> Result<()> {
+        let mut files_by_arch: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for file in package_files {
+            for arch in self.layout.get_file_architectures(file) {
+                files_by_arch.entry(arch).or_default().push(file.clone());
+            }
+        }
+
+        for (arch, files) in files_by_arch {
+            self.remove_from_database(&self.layout.get_arch_path(&arch), &files).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the given package files and their signatures from the repository directories.
+    pub async fn remove_package_files(&self, package_files: &[String]) -> Result<()> {
+        for file in package_files.iter() {
+            for file_path in self.layout.get_file_paths(file) {
+                for path in [file_path.clone(), PathBuf::from(format!("{}.sig", file_path.display()))] {
+                    if try_exists(&path).await? {
+                        remove_file(&path).await
+                            .with_context(|| format!("Failed to remove {:?}", path))?;
+                        info!("Removed {:?}", path);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
-        return self.repo_add_cmd(package_files).await;
+    /// Checks the repository of every architecture against the files of the packages and of their retained versions.
+    /// Database entries are compared with the size, checksum and signature of the files on disk.
+    /// When fixing, orphan files and stale signatures are deleted, missing signatures created and the databases
+    /// rebuilt from the package files on disk, keeping the highest version of each package.
+    pub async fn check(&self, package_files: &[String], retained_files: &[String], fix: bool) -> Result<RepositoryCheckResponse> {
+        let mut report = RepositoryCheckResponse { fixed: fix, ..Default::default() };
+        for arch in self.layout.get_architectures().iter() {
+            self.check_arch(arch, package_files, retained_files, fix, &mut report).await
+                .with_context(|| format!("Failed to check repository {}", arch))?;
+        }
+        Ok(report)
     }
 
-    async fn repo_add_cmd(&self, package_files: Vec<String>) -> Result<()> {
-        debug!("Building repository");
+    async fn check_arch(
+        &self,
+        arch: &str,
+        package_files: &[String],
+        retained_files: &[String],
+        fix: bool,
+        report: &mut RepositoryCheckResponse,
+    ) -> Result<()> {
+        let path = self.layout.get_arch_path(arch);
+        let qualify = |files: &[String]| files.iter().map(|file| format!("{}/{}", arch, file)).collect::<Vec<_>>();
+
+        let on_disk = self.get_package_files(arch).await?;
+        let expected: Vec<String> = package_files.iter()
+            .filter(|file| self.layout.get_file_architectures(file).iter().any(|a| a == arch))
+            .cloned()
+            .collect();
+        let present: Vec<String> = expected.iter().filter(|file| on_disk.contains(file)).cloned().collect();
+        let missing: Vec<String> = expected.iter().filter(|file| !on_disk.contains(file)).cloned().collect();
+        let orphans: Vec<String> = on_disk.iter()
+            .filter(|file| !package_files.contains(file) && !retained_files.contains(file))
+            .cloned()
+            .collect();
+
+        let mut missing_signatures = Vec::new();
+        let mut stale_signatures = Vec::new();
+        for file in on_disk.iter().filter(|file| !orphans.contains(file)) {
+            let file_modified = tokio::fs::metadata(path.join(file)).await?.modified()?;
+            let signature_modified = match tokio::fs::metadata(path.join(format!("{}.sig", file))).await {
+                Ok(metadata) => Some(metadata.modified()?),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+            match signature_modified {
+                None if self.sign_key.is_some() => missing_signatures.push(file.clone()),
+                Some(modified) if self.sign_key.is_none() || modified < file_modified => stale_signatures.push(file.clone()),
+                _ => {}
+            }
+        }
+        let unmatched_signatures: Vec<String> = self.read_arch_directory(arch).await?.into_iter()
+            .filter(|name| name.strip_suffix(".sig").is_some_and(|file| !on_disk.contains(&file.to_string())))
+            .collect();
+
+        // Only the highest version of a package is served, a parent built before its dependencies were published
+        // separately can still reference an older file of one of them
+        let mut current: BTreeMap<String, (String, String)> = BTreeMap::new();
+        for file in present.iter() {
+            let info = match read_package_info(path.join(file)).await {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Failed to read package {} of repository {}: {:#}", file, arch, e);
+                    continue;
+                }
+            };
+            let is_newer = current.get(info.get_name())
+                .is_none_or(|(version, _)| vercmp(info.get_version(), version) == Ordering::Greater);
+            if is_newer {
+                current.insert(info.get_name().to_string(), (info.get_version().to_string(), file.clone()));
+            }
+        }
+        let current: Vec<String> = current.into_values().map(|(_, file)| file).collect();
+
+        let database = match self.read_database(&path).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read the database of repository {}: {:#}", arch, e);
+                Vec::new()
+            }
+        };
+        let missing_entries: Vec<String> = current.iter()
+            .filter(|file| !database.iter().any(|entry| &entry.file == *file))
+            .cloned()
+            .collect();
+        let mut stale_entries = Vec::new();
+        for entry in database.iter() {
+            let is_current = current.contains(&entry.file)
+                && entry.matches_package_file(path.join(&entry.file)).await
+                    .with_context(|| format!("Failed to compare {} with its database entry", entry.file))?;
+            if !is_current {
+                stale_entries.push(entry.file.clone());
+            }
+        }
+
+        report.missing_files.extend(qualify(&missing));
+        report.orphan_files.extend(qualify(&orphans));
+        report.missing_signatures.extend(qualify(&missing_signatures));
+        report.stale_signatures.extend(qualify(&stale_signatures));
+        report.stale_signatures.extend(unmatched_signatures.iter().map(|name| format!("{}/{}", arch, name)));
+        report.missing_db_entries.extend(qualify(&missing_entries));
+        report.stale_db_entries.extend(qualify(&stale_entries));
+
+        if !fix {
+            return Ok(());
+        }
+
+        for file in orphans.iter() {
+            for path in [path.join(file), path.join(format!("{}.sig", file))] {
+                if try_exists(&path).await? {
+                    remove_file(&path).await.with_context(|| format!("Failed to remove {:?}", path))?;
+                    info!("Removed orphan {:?}", path);
+                }
+            }
+        }
+        for name in unmatched_signatures.iter() {
+            remove_file(path.join(name)).await?;
+            info!("Removed signature {:?} without package", path.join(name));
+        }
+        let resigned: Vec<String> = missing_signatures.into_iter().chain(stale_signatures).collect();
+        self.sign_package_files(&path, &resigned).await?;
+
+        let signed_current = resigned.iter().any(|file| current.contains(file));
+        if !missing_entries.is_empty() || !stale_entries.is_empty() || signed_current {
+            info!("Rebuilding repository {} from {} package files", arch, current.len());
+            let mut entries = Vec::new();
+            for file in current {
+                entries.push(DatabaseEntry::from_package_file(path.join(&file)).await?);
+            }
+            self.write_database(&path, entries).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves packages served from the root of the serve path by older versions into the per architecture directories.
+    /// Clients of the root repository stop working once migrated, so the packages are only moved when enabled.
+    pub async fn migrate_legacy_layout(&self, migrate: bool) -> Result<()> {
+        let root = self.layout.get_path();
+        let mut package_files = Vec::new();
+        let mut dir = tokio::fs::read_dir(root).await
+            .with_context(|| format!("Failed to read directory {:?}", root))?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().await?.is_file() && name.contains(".pkg.tar.") && !name.ends_with(".sig") {
+                package_files.push(name);
+            }
+        }
 
         if package_files.is_empty() {
             return Ok(());
         }
+        if !migrate {
+            warn!(
+                "Found {} packages served from {:?} by an older version, they are no longer updated. \
+                Enable migrate_legacy_layout to move them to {:?} once clients use the $repo/os/$arch Server url",
+                package_files.len(), root, self.layout.get_arch_path("$arch")
+            );
+            return Ok(());
+        }
 
-        let mut args = vec!["--remove"];
-        if let Some(sign_key) = self.sign_key.as_ref() {
-            args.push("--verify");
-            args.push("--sign");
-            args.push("--key");
-            args.push(sign_key.as_str());
+        info!("Moving {} packages to the per architecture repository layout", package_files.len());
+        for file in package_files.iter() {
+            self.store_package_file(&root.join(file), file).await?;
+            remove_file(root.join(file)).await?;
+            let sig = root.join(format!("{}.sig", file));
+            if try_exists(&sig).await? {
+                remove_file(sig).await?;
+            }
         }
+        self.add_packages_to_repo(package_files).await?;
 
-        let repo_output_name = format!("{}.db.tar.gz", self.repo_name);
-        args.push(repo_output_name.as_str());
+        let repo_name = self.layout.get_repo_name();
+        for suffix in ["db", "db.tar.gz", "db.sig", "db.tar.gz.sig", "files", "files.tar.gz", "files.sig", "files.tar.gz.sig"] {
+            let path = root.join(format!("{}.{}", repo_name, suffix));
+            if tokio::fs::symlink_metadata(&path).await.is_ok() {
+                remove_file(&path).await?;
+            }
+        }
 
-        for package in package_files.iter() {
-            args.push(package.as_str());
+        Ok(())
+    }
+
+    /// Adds the given package files to the databases of the repository directory, replacing the entries of the same packages.
+    /// Files that cannot be read are left out so they do not keep the others from being added.
+    async fn add_to_database(&self, path: &Path, package_files: Vec<String>) -> Result<()> {
+        if package_files.is_empty() {
+            return Ok(());
         }
 
-        let out = Command::new("repo-add")
-            .current_dir(&self.path)
-            .args(&args)
-            .output()
-            .await
-            .with_context(|| format!("Failed to run repo-add with args {:?}", args))?;
+        let mut entries = self.read_database(path).await?;
+        for file in package_files {
+            let entry = match DatabaseEntry::from_package_file(path.join(&file)).await {
+                Ok(entry) => entry,
+                Err(e) => {
+                    error!("Skipping {} which could not be added to repository {:?}: {:#}", file, path, e);
+                    continue;
+                }
+            };
+            entries.retain(|e| e.name != entry.name);
+            debug!("Adding {} to repository {:?}", entry.file, path);
+            entries.push(entry);
+        }
 
-        debug!("repository-add output exit code : {:?} {:?} {:?}", out.status.code() ,String::from_utf8(out.stdout), String::from_utf8(out.stderr));
+        self.write_database(path, entries).await
+    }
+
+    async fn remove_from_database(&self, path: &Path, package_files: &[String]) -> Result<()> {
+        let mut entries = self.read_database(path).await?;
+        let count = entries.len();
+        entries.retain(|e| !package_files.contains(&e.file));
+        if entries.len() == count {
+            return Ok(());
+        }
+
+        debug!("Removing {:?} from repository {:?}", package_files, path);
+        self.write_database(path, entries).await
+    }
+
+    /// Reads the current entries of the databases of the repository directory.
+    /// Without a files database, the entries of the package database are generated again from the package files.
+    async fn read_database(&self, path: &Path) -> Result<Vec<DatabaseEntry>> {
+        let repo_name = self.layout.get_repo_name();
+        let files_database = path.join(format!("{}.files.tar.gz", repo_name));
+        if try_exists(&files_database).await? {
+            return tokio::task::spawn_blocking(move || read_files_database(&files_database)).await?;
+        }
+
+        let database = path.join(format!("{}.db.tar.gz", repo_name));
+        if !try_exists(&database).await? {
+            return Ok(Vec::new());
+        }
+        warn!("No files database in {:?}, generating the entries of {:?} again from the package files", path, database);
+        let previous = tokio::task::spawn_blocking(move || read_files_database(&database)).await??;
+        let mut entries = Vec::new();
+        for entry in previous {
+            match DatabaseEntry::from_package_file(path.join(&entry.file)).await {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Dropping {} from the repository: {:#}", entry.file, e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Writes the databases of the repository directory and signs them with the configured key.
+    /// They only replace the previous ones once signed, a failed signature leaves the previous ones in place.
+    async fn write_database(&self, path: &Path, entries: Vec<DatabaseEntry>) -> Result<()> {
+        let repo_name = self.layout.get_repo_name().clone();
+        let temporaries = {
+            let path = path.to_path_buf();
+            let repo_name = repo_name.clone();
+            tokio::task::spawn_blocking(move || write_databases(&path, &repo_name, &entries)).await??
+        };
+
+        if let Some(sign_key) = self.sign_key.as_ref() {
+            for temporary in temporaries.iter() {
+                if let Err(e) = self.sign_database(sign_key, temporary).await {
+                    for temporary in temporaries.iter() {
+                        let _ = remove_file(temporary).await;
+                        let _ = remove_file(format!("{}.sig", temporary.display())).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        for kind in DATABASE_KINDS {
+            let database = install_database(path, &repo_name, kind, self.sign_key.is_some())?;
+            info!("Wrote repository database {:?}", database);
+        }
 
         Ok(())
     }
+
+    /// Writes the detached signature of the given database next to it.
+    async fn sign_database(&self, sign_key: &str, database: &Path) -> Result<()> {
+        let out = Command::new("gpg")
+            .arg("--default-key")
+            .arg(sign_key)
+            .arg("--yes")
+            .arg("--no-armor")
+            .arg("--output")
+            .arg(format!("{}.sig", database.display()))
+            .arg("--detach-sig")
+            .arg(database)
+            .output().await
+            .with_context(|| format!("Failed to run gpg to sign {:?}", database))?;
+        if !out.status.success() {
+            bail!("Failed to sign {:?}, gpg exited with {:?}: {}", database, out.status.code(), String::from_utf8_lossy(&out.stderr));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
     use serial_test::serial;
 
     use tokio::fs::{remove_dir_all, try_exists};
 
+    use crate::repository::database::read_files_database;
+    use crate::repository::layout::RepositoryLayout;
     use crate::repository::manager::RepositoryManager;
 
     async fn setup() -> RepositoryManager {
         let _ = remove_dir_all("/tmp/aur-build-server-test").await;
 
-        RepositoryManager::new("test".to_string(), None, PathBuf::from("/tmp/aur-build-server-test/repo"))
-            .await.unwrap()
+        let layout = RepositoryLayout::new(
+            PathBuf::from("/tmp/aur-build-server-test/repo"),
+            "test".to_string(),
+            vec!["x86_64".to_string(), "aarch64".to_string()],
+        );
+        RepositoryManager::new(None, layout).await.unwrap()
     }
 
     #[tokio::test]
@@ -144,17 +509,125 @@ mod tests {
     async fn can_add_package_to_repository_no_sign() {
         let manager = setup().await;
 
+        manager.store_package_file(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("aur-build-cli-0.10.0-1-any.pkg.tar.zst"),
+            "aur-build-cli-0.10.0-1-any.pkg.tar.zst"
+        ).await.unwrap();
+
+        let files = manager.get_package_files("x86_64").await.unwrap();
+        assert_eq!(1, files.len());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/aarch64/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap());
+
+        manager.add_packages_to_repo(files).await.unwrap();
+
+        assert_eq!(true, try_exists("/tmp/aur-build-server-test/repo/test/os/x86_64/test.db").await.unwrap());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/aarch64/test.db").await.unwrap());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/aarch64/test.files").await.unwrap());
+
+        let entries = read_files_database(&PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64/test.db")).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("aur-build-cli-0.10.0-1-any.pkg.tar.zst", entries[0].file);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn can_remove_package_from_repository() {
+        let manager = setup().await;
+        let file = "aur-build-cli-0.10.0-1-any.pkg.tar.zst".to_string();
+
+        manager.store_package_file(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join(&file),
+            &file
+        ).await.unwrap();
+        manager.add_packages_to_repo(vec![file.clone()]).await.unwrap();
+        manager.remove_packages_from_repo(std::slice::from_ref(&file)).await.unwrap();
+
+        let entries = read_files_database(&PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64/test.files")).unwrap();
+        assert!(entries.is_empty());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/x86_64/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn failed_signature_keeps_previous_database() {
+        let mut manager = setup().await;
+        let file = "aur-build-cli-0.10.0-1-any.pkg.tar.zst".to_string();
+        let path = PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64");
+
+        manager.store_package_file(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join(&file),
+            &file
+        ).await.unwrap();
+        manager.add_packages_to_repo(vec![file.clone()]).await.unwrap();
+
+        manager.sign_key = Some("missing-key@aur-build-server.invalid".to_string());
+        assert!(manager.remove_packages_from_repo(std::slice::from_ref(&file)).await.is_err());
+
+        let entries = read_files_database(&path.join("test.files")).unwrap();
+        assert_eq!(1, entries.len());
+        for name in ["test.db.sig", "test.db.tar.gz.sig", ".test.db.tar.gz.tmp", ".test.db.tar.gz.tmp.sig", ".test.files.tar.gz.tmp"] {
+            assert!(!try_exists(path.join(name)).await.unwrap(), "{}", name);
+        }
+    }
+
+    /// Writes a package file holding only a `.PKGINFO` with the given version and description.
+    fn write_package(path: &Path, version: &str, description: &str) {
+        let info = format!("pkgname = foo\npkgver = {}\npkgdesc = {}\narch = any\n", version, description);
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(info.len() as u64);
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::default()));
+        builder.append_data(&mut header, ".PKGINFO", info.as_bytes()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn check_keeps_highest_version_of_packages() {
+        let manager = setup().await;
+        let files = vec!["foo-1.0-1-any.pkg.tar.gz".to_string(), "foo-2.0-1-any.pkg.tar.gz".to_string()];
+        let source = PathBuf::from("/tmp/aur-build-server-test/package.pkg.tar.gz");
+        write_package(&source, "1.0-1", "old");
+        manager.store_package_file(&source, &files[0]).await.unwrap();
+        write_package(&source, "2.0-1", "new");
+        manager.store_package_file(&source, &files[1]).await.unwrap();
+
+        let report = manager.check(&files, &[], true).await.unwrap();
+        assert_eq!(vec!["x86_64/foo-2.0-1-any.pkg.tar.gz", "aarch64/foo-2.0-1-any.pkg.tar.gz"], report.missing_db_entries);
+
+        let entries = read_files_database(&PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64/test.files")).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("2.0-1", entries[0].version);
+
+        let report = manager.check(&files, &[], false).await.unwrap();
+        assert!(report.missing_db_entries.is_empty());
+        assert!(report.stale_db_entries.is_empty());
+
+        write_package(&PathBuf::from("/tmp/aur-build-server-test/repo/test/os/x86_64").join(&files[1]), "2.0-1", "replaced");
+        let report = manager.check(&files, &[], false).await.unwrap();
+        assert_eq!(vec!["x86_64/foo-2.0-1-any.pkg.tar.gz"], report.stale_db_entries);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn migrate_legacy_layout() {
+        let manager = setup().await;
+
         tokio::fs::copy(
             PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("aur-build-cli-0.10.0-1-any.pkg.tar.zst"),
             "/tmp/aur-build-server-test/repo/aur-build-cli-0.10.0-1-any.pkg.tar.zst"
         ).await.unwrap();
+        tokio::fs::write("/tmp/aur-build-server-test/repo/test.db.tar.gz", "").await.unwrap();
 
-        let files = manager.get_package_files().await.unwrap();
-        assert_eq!(1, files.len());
+        manager.migrate_legacy_layout(false).await.unwrap();
+        assert!(try_exists("/tmp/aur-build-server-test/repo/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test.db.tar.gz").await.unwrap());
 
-        manager.add_packages_to_repo(files).await.unwrap();
+        manager.migrate_legacy_layout(true).await.unwrap();
 
-        assert_eq!(true, try_exists("/tmp/aur-build-server-test/repo/test.db").await.unwrap())
+        assert!(!try_exists("/tmp/aur-build-server-test/repo/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap());
+        assert!(!try_exists("/tmp/aur-build-server-test/repo/test.db.tar.gz").await.unwrap());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/aarch64/aur-build-cli-0.10.0-1-any.pkg.tar.zst").await.unwrap());
+        assert!(try_exists("/tmp/aur-build-server-test/repo/test/os/aarch64/test.db").await.unwrap());
     }
 }
-
@@ -1,10 +1,17 @@
+mod database;
+mod layout;
 mod manager;
+mod package_file;
 
 use crate::models::config::Config;
 use crate::persistence::package_store::{Package};
+use crate::repository::layout::RepositoryLayout;
 use crate::repository::manager::RepositoryManager;
-use anyhow::{Result};
+use crate::repository::package_file::{read_package_info, PackageInfo};
+pub use crate::repository::package_file::SUPPORTED_PKGEXTS;
+use anyhow::{Context, Result};
 use chrono::Utc;
+use common::http::responses::RepositoryCheckResponse;
 use common::models::PackageStatus;
 use log::{debug, error, info, warn};
 use std::ops::Deref;
@@ -14,9 +21,20 @@ use actix_multipart::form::tempfile::TempFile;
 use tokio::fs;
 use tokio::sync::{Mutex, RwLock};
 
+/// Files of another package base built along with a package, usually one of its AUR dependencies.
+#[derive(Debug)]
+pub struct DependencyOutput {
+    pub base: String,
+    pub names: Vec<String>,
+    /// Version in the format reported by the workers.
+    pub version: String,
+    pub files: Vec<String>,
+}
+
 pub struct Repository {
-    path: PathBuf,
+    layout: RepositoryLayout,
     build_logs_path: PathBuf,
+    sources_path: PathBuf,
 
     manager: Arc<Mutex<RepositoryManager>>,
 }
@@ -24,23 +42,23 @@ pub struct Repository {
 impl Repository {
     pub async fn from_config(config: Arc<RwLock<Config>>) -> Result<Self> {
         let manager = RepositoryManager::from_config(config.read().await.deref()).await?;
+        manager.migrate_legacy_layout(config.read().await.migrate_legacy_layout).await?;
 
         Ok(Repository {
-            path: config.read().await.serve_path.clone(),
+            layout: manager.layout.clone(),
             build_logs_path: config.read().await.build_logs_path.clone(),
+            sources_path: config.read().await.sources_path.clone(),
 
             manager: Arc::new(Mutex::new(manager)),
         })
     }
 
-    pub async fn handle_package_build_output(
-        &mut self,
-        package: &mut Package,
-        version: Option<String>,
-        error: Option<String>,
-        log_files: Vec<TempFile>,
-        files: Vec<TempFile>,
-    ) -> Result<()> {
+    pub fn get_repo_name(&self) -> &String {
+        self.layout.get_repo_name()
+    }
+
+    /// Stores the log files uploaded for a build.
+    pub async fn store_build_logs(&self, log_files: Vec<TempFile>) -> Result<()> {
         if !self.build_logs_path.exists() {
             if let Err(e) = tokio::fs::create_dir_all(&self.build_logs_path).await {
                 warn!("Unable to create build logs directory: {}", e);
@@ -58,26 +76,58 @@ impl Repository {
                 fs::remove_file(log_file.file.path()).await?;
             }
         }
-        let mut package_files = Vec::new();
+        Ok(())
+    }
+
+    /// Stores the files uploaded for a build and adds them to the repository.
+    /// Returns the files of the other package bases built along with the package, which are not attributed to it.
+    /// The files of the ones built from packages of their own, `explicit_packages`, are not kept.
+    pub async fn handle_package_build_output(
+        &mut self,
+        package: &mut Package,
+        package_base: Option<String>,
+        explicit_packages: &[String],
+        version: Option<String>,
+        error: Option<String>,
+        files: Vec<TempFile>,
+    ) -> Result<Vec<DependencyOutput>> {
+        let mut infos = Vec::new();
+        for file in files.iter() {
+            if let Some(filename) = file.file_name.as_ref() {
+                let info = read_package_info(file.file.path().to_path_buf()).await
+                    .inspect_err(|e| warn!("Failed to read package info of {}: {}", filename, e))
+                    .ok();
+                infos.push((filename.clone(), info));
+            }
+        }
+        let (mut own_files, mut dependencies) = self.split_dependency_files(package, package_base, explicit_packages, infos);
 
+        let mut package_files = Vec::new();
         for file in files {
             if let Some(filename) = file.file_name {
-                let dest = &self.path.join(&filename);
-                debug!("Copying {:?} to {:?}", file.file.path(), dest);
-                match fs::copy(file.file.path(), &self.path.join(&filename)).await {
-                    Ok(_) => {
-                        info!("Successfully persisted package file '{}'", filename);
-                        package_files.push(filename)
-                    },
-                    Err(e) => error!("Unable to persist package file '{}': '{}'", filename, e),
+                let is_kept = own_files.contains(&filename) || dependencies.iter().any(|d| d.files.contains(&filename));
+                if !is_kept {
+                    info!("Discarding package file '{}' built from a package of its own", filename);
+                } else {
+                    match self.manager.lock().await.store_package_file(file.file.path(), &filename).await {
+                        Ok(_) => {
+                            info!("Successfully persisted package file '{}'", filename);
+                            package_files.push(filename)
+                        },
+                        Err(e) => error!("Unable to persist package file '{}': '{}'", filename, e),
+                    }
                 }
                 fs::remove_file(file.file.path()).await?;
             }
         }
+        own_files.retain(|file| package_files.contains(file));
+        for dependency in dependencies.iter_mut() {
+            dependency.files.retain(|file| package_files.contains(file));
+        }
 
         self.update_package_state_from_build_data(
             package,
-            package_files.clone(),
+            own_files,
             error,
             version,
         );
@@ -98,7 +148,82 @@ impl Repository {
             }
         }
 
-        Ok(())
+        Ok(dependencies)
+    }
+
+    /// Separates the files of the package base of the package from the ones of its dependencies, grouped by package base.
+    /// Without the package base reported by the worker it is the one of the file named after the package.
+    /// Dependencies in `explicit_packages` are returned without files.
+    fn split_dependency_files(
+        &self,
+        package: &Package,
+        package_base: Option<String>,
+        explicit_packages: &[String],
+        infos: Vec<(String, Option<PackageInfo>)>,
+    ) -> (Vec<String>, Vec<DependencyOutput>) {
+        let package_base = package_base.or_else(|| infos.iter()
+            .filter_map(|(_, info)| info.as_ref())
+            .find(|info| info.get_name() == package.get_name())
+            .map(|info| info.get_base().to_string()));
+        let Some(package_base) = package_base else {
+            warn!("Could not find the package base of {}, attributing all its files to it", package.get_name());
+            return (infos.into_iter().map(|(file, _)| file).collect(), Vec::new());
+        };
+
+        let mut own_files = Vec::new();
+        let mut dependencies: Vec<DependencyOutput> = Vec::new();
+        for (file, info) in infos {
+            let Some(info) = info.filter(|info| info.get_base() != package_base) else {
+                own_files.push(file);
+                continue;
+            };
+            let dependency = match dependencies.iter_mut().position(|d| d.base == info.get_base()) {
+                Some(index) => &mut dependencies[index],
+                None => {
+                    dependencies.push(DependencyOutput {
+                        base: info.get_base().to_string(),
+                        names: Vec::new(),
+                        version: info.get_build_version(),
+                        files: Vec::new(),
+                    });
+                    dependencies.last_mut().unwrap()
+                }
+            };
+            dependency.names.push(info.get_name().to_string());
+            dependency.files.push(file);
+        }
+
+        for dependency in dependencies.iter_mut() {
+            let is_explicit = std::iter::once(&dependency.base).chain(dependency.names.iter())
+                .any(|name| explicit_packages.contains(name));
+            if is_explicit {
+                dependency.files.clear();
+            }
+        }
+
+        (own_files, dependencies)
+    }
+
+    /// Keeps a copy of the current log file of the package for the given build.
+    /// Returns the name of the archived log file relative to the builds log directory.
+    pub async fn archive_build_log(&self, package: &Package, build_id: i32) -> Result<Option<String>> {
+        let source = self.build_logs_path.join(format!("{}.log", package.get_name()));
+        if !fs::try_exists(&source).await? {
+            return Ok(None);
+        }
+
+        let builds_logs_path = self.get_builds_logs_path();
+        fs::create_dir_all(&builds_logs_path).await?;
+
+        let filename = format!("{}-{}.log", package.get_name(), build_id);
+        fs::copy(&source, builds_logs_path.join(&filename)).await?;
+        debug!("Archived build log {:?} as {}", source, filename);
+
+        Ok(Some(filename))
+    }
+
+    fn get_builds_logs_path(&self) -> PathBuf {
+        self.build_logs_path.join("builds")
     }
 
     fn update_package_state_from_build_data(
@@ -120,20 +245,50 @@ impl Repository {
             info!("Built {}", package.get_name());
         }
 
-        *package.get_files_mut() = package_files;
+        if !package_files.is_empty() {
+            *package.get_files_mut() = package_files;
+        }
     }
 
-    // pub async fn rebuild_repo(&mut self) -> Result<()> {
-    //     let packages = self.package_store.get_packages()?;
-    //
-    //     let files = packages.iter().fold(Vec::new(), |mut acc, i| {
-    //         acc.append(&mut i.get_files().clone());
-    //         acc
-    //     });
-    //
-    //     if !files.is_empty() {
-    //         return self.manager.lock().await.add_packages_to_repo(files).await;
-    //     }
-    //     Ok(())
-    // }
+    pub async fn add_packages_to_repo(&self, package_files: Vec<String>) -> Result<()> {
+        self.manager.lock().await.add_packages_to_repo(package_files).await
+    }
+
+    pub async fn remove_packages_from_repo(&self, package_files: &[String]) -> Result<()> {
+        self.manager.lock().await.remove_packages_from_repo(package_files).await
+    }
+
+    pub async fn export_sign_key(&self) -> Result<Option<String>> {
+        self.manager.lock().await.export_sign_key().await
+    }
+
+    pub async fn remove_package_files(&self, package_files: &[String]) -> Result<()> {
+        self.manager.lock().await.remove_package_files(package_files).await
+    }
+
+    /// Deletes the source tarball uploaded for a package.
+    pub async fn remove_uploaded_source(&self, file: &str) -> Result<()> {
+        let path = self.sources_path.join(file);
+        if fs::try_exists(&path).await? {
+            fs::remove_file(&path).await
+                .with_context(|| format!("Failed to remove {:?}", path))?;
+            info!("Removed {:?}", path);
+        }
+        Ok(())
+    }
+
+    pub fn package_file_exists(&self, file: &str) -> bool {
+        self.layout.get_file_paths(file).iter().all(|p| p.exists())
+    }
+
+    /// Checks the repository against the files of the packages and of their retained versions,
+    /// rebuilding it from the package files on disk when fixing.
+    pub async fn rebuild_repo(
+        &self,
+        package_files: &[String],
+        retained_files: &[String],
+        fix: bool,
+    ) -> Result<RepositoryCheckResponse> {
+        self.manager.lock().await.check(package_files, retained_files, fix).await
+    }
 }
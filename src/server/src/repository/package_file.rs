@@ -0,0 +1,311 @@
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use tar::Archive;
+
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+/// Package extensions whose compression can be read to add the packages to the repository databases.
+pub const SUPPORTED_PKGEXTS: [&str; 5] = [".pkg.tar", ".pkg.tar.gz", ".pkg.tar.zst", ".pkg.tar.xz", ".pkg.tar.bz2"];
+
+/// Metadata of a built package, read from the `.PKGINFO` file at the root of its archive.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackageInfo {
+    fields: Vec<(String, String)>,
+}
+
+impl PackageInfo {
+    pub fn parse(content: &str) -> PackageInfo
+    {
+        let fields = content.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once(" = "))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        PackageInfo { fields }
+    }
+
+    /// Every value of the given key, in the order of the file.
+    pub fn get_all(&self, key: &str) -> Vec<&str>
+    {
+        self.fields.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect()
+    }
+
+    /// First value of the given key.
+    pub fn get(&self, key: &str) -> Option<&str>
+    {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_name(&self) -> &str
+    {
+        self.get("pkgname").unwrap_or_default()
+    }
+
+    /// Name of the package base the package was built from, the package name itself if not set.
+    pub fn get_base(&self) -> &str
+    {
+        self.get("pkgbase").unwrap_or_else(|| self.get_name())
+    }
+
+    /// Full version of the package, as `[epoch:]pkgver-pkgrel`.
+    pub fn get_version(&self) -> &str
+    {
+        self.get("pkgver").unwrap_or_default()
+    }
+
+    /// Version of the package in the format reported by the workers, pkgver, pkgrel and epoch concatenated.
+    pub fn get_build_version(&self) -> String
+    {
+        let (epoch, version) = self.get_version().split_once(':').unwrap_or(("", self.get_version()));
+        let (pkgver, pkgrel) = version.rsplit_once('-').unwrap_or((version, ""));
+        format!("{}{}{}", pkgver, pkgrel, epoch)
+    }
+}
+
+/// Metadata and installed files of a package archive.
+#[derive(Debug, Clone, Default)]
+pub struct PackageContents {
+    pub info: PackageInfo,
+    /// Paths installed by the package, directories ending with a `/`, sorted.
+    pub files: Vec<String>,
+}
+
+/// Lists the paths of the entries of an mtree file, skipping the metadata files at the root of the package.
+fn parse_mtree(content: &str) -> Vec<String>
+{
+    let mut default_type = "file".to_string();
+    let mut files = Vec::new();
+
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("/unset") {
+            continue;
+        }
+        let mut keywords = line.split_whitespace();
+        let Some(path) = keywords.next() else {
+            continue;
+        };
+        let entry_type = keywords.find_map(|keyword| keyword.strip_prefix("type="));
+        if path == "/set" {
+            if let Some(entry_type) = entry_type {
+                default_type = entry_type.to_string();
+            }
+            continue;
+        }
+
+        let path = decode_mtree_path(path);
+        let Some(path) = path.strip_prefix("./").filter(|p| !p.starts_with('.')) else {
+            continue;
+        };
+        match entry_type.unwrap_or(&default_type) {
+            "dir" => files.push(format!("{}/", path)),
+            _ => files.push(path.to_string()),
+        }
+    }
+
+    files
+}
+
+/// Decodes the octal escapes, such as `\040` for spaces, of a path in an mtree file.
+fn decode_mtree_path(path: &str) -> String
+{
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 4;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Opens the tar archive of a package, decompressing it according to its magic number.
+fn open_archive(path: &Path) -> Result<Archive<Box<dyn Read>>>
+{
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut magic = [0u8; 6];
+    let read = file.read(&mut magic)?;
+    let magic = &magic[..read];
+    file.seek(SeekFrom::Start(0))?;
+    let file = BufReader::new(file);
+
+    let reader: Box<dyn Read> = if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(file)?)
+    } else if magic.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(file))
+    } else if magic.starts_with(XZ_MAGIC) {
+        Box::new(xz2::read::XzDecoder::new(file))
+    } else if magic.starts_with(BZIP2_MAGIC) {
+        Box::new(bzip2::read::BzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    Ok(Archive::new(reader))
+}
+
+fn read_package_info_blocking(path: &Path) -> Result<PackageInfo>
+{
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()? == Path::new(".PKGINFO") {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(PackageInfo::parse(&content));
+        }
+    }
+    bail!("No .PKGINFO found in {:?}", path)
+}
+
+fn read_package_contents_blocking(path: &Path) -> Result<PackageContents>
+{
+    let mut archive = open_archive(path)?;
+    let mut info = None;
+    let mut mtree = None;
+    let mut listed_files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        match entry_path.as_str() {
+            ".PKGINFO" => {
+                let mut content = String::new();
+                entry.read_to_string(&mut content)?;
+                info = Some(PackageInfo::parse(&content));
+            }
+            ".MTREE" => {
+                let mut content = String::new();
+                GzDecoder::new(entry).read_to_string(&mut content)
+                    .with_context(|| format!("Failed to read .MTREE of {:?}", path))?;
+                mtree = Some(parse_mtree(&content));
+            }
+            _ if entry_path.starts_with('.') => {}
+            _ => listed_files.push(entry_path),
+        }
+        if info.is_some() && mtree.is_some() {
+            break;
+        }
+    }
+
+    let Some(info) = info else {
+        bail!("No .PKGINFO found in {:?}", path);
+    };
+    let mut files = mtree.unwrap_or(listed_files);
+    files.sort();
+    files.dedup();
+
+    Ok(PackageContents { info, files })
+}
+
+/// Reads the `.PKGINFO` and the list of files of the given package file, from its `.MTREE` if it has one.
+pub async fn read_package_contents(path: PathBuf) -> Result<PackageContents>
+{
+    tokio::task::spawn_blocking(move || read_package_contents_blocking(&path)).await?
+}
+
+/// Reads the `.PKGINFO` of the given package file.
+pub async fn read_package_info(path: PathBuf) -> Result<PackageInfo>
+{
+    tokio::task::spawn_blocking(move || read_package_info_blocking(&path)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::repository::package_file::{decode_mtree_path, parse_mtree, read_package_contents, read_package_info, PackageInfo};
+
+    /// Writes a package holding only a `.PKGINFO`, the compression is finished when the writer is dropped.
+    fn write_package(writer: Box<dyn Write>) {
+        let pkginfo = b"pkgname = compressed\npkgbase = compressed\npkgver = 1.0-1\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(pkginfo.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(writer);
+        builder.append_data(&mut header, ".PKGINFO", &pkginfo[..]).unwrap();
+        builder.into_inner().unwrap().flush().unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_package_info_from_compressed_archives() {
+        let dir = std::env::temp_dir().join("aur-build-server-package-file-test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let xz = dir.join("compressed-1.0-1-any.pkg.tar.xz");
+        write_package(Box::new(xz2::write::XzEncoder::new(std::fs::File::create(&xz).unwrap(), 6)));
+        let bz2 = dir.join("compressed-1.0-1-any.pkg.tar.bz2");
+        write_package(Box::new(bzip2::write::BzEncoder::new(std::fs::File::create(&bz2).unwrap(), Default::default())));
+
+        for path in [xz, bz2] {
+            let info = read_package_info(path.clone()).await.unwrap();
+            assert_eq!("compressed", info.get_name(), "{:?}", path);
+            assert_eq!("1.0-1", info.get_version(), "{:?}", path);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_package_info_from_archive() {
+        let info = read_package_info(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("aur-build-cli-0.10.0-1-any.pkg.tar.zst")
+        ).await.unwrap();
+
+        assert_eq!("aur-build-cli", info.get_name());
+        assert_eq!("aur-build-cli", info.get_base());
+        assert_eq!("0.10.0-1", info.get_version());
+        assert_eq!("0.10.01", info.get_build_version());
+        assert_eq!(vec!["cargo"], info.get_all("makedepend"));
+    }
+
+    #[tokio::test]
+    async fn read_package_contents_from_archive() {
+        let contents = read_package_contents(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("aur-build-cli-0.10.0-1-any.pkg.tar.zst")
+        ).await.unwrap();
+
+        assert_eq!("aur-build-cli", contents.info.get_name());
+        assert_eq!(vec!["usr/", "usr/bin/", "usr/bin/aur-build-cli"], contents.files);
+    }
+
+    #[test]
+    fn parse_mtree_files() {
+        let mtree = "#mtree\n/set type=file uid=0 gid=0 mode=644\n./.PKGINFO time=1.0 size=373\n./usr time=1.0 mode=755 type=dir\n./usr/share/my\\040file time=1.0 size=2\n./usr/bin/link time=1.0 type=link link=foo\n";
+
+        assert_eq!(vec!["usr/", "usr/share/my file", "usr/bin/link"], parse_mtree(mtree));
+        assert_eq!("a\\b", decode_mtree_path("a\\134b"));
+    }
+
+    #[test]
+    fn parse_package_info() {
+        let info = PackageInfo::parse("# Generated by makepkg\npkgname = foo-libs\npkgbase = foo\npkgver = 2:1.2-3\n");
+
+        assert_eq!("foo-libs", info.get_name());
+        assert_eq!("foo", info.get_base());
+        assert_eq!("1.232", info.get_build_version());
+        assert_eq!("bar", PackageInfo::parse("pkgname = bar\npkgver = 1-1").get_base());
+    }
+}
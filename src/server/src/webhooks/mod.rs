@@ -36,9 +36,17 @@ impl WebhookManager {
     }
 
     pub async fn trigger_webhook_package_updated(&self, package: PackageResponse) {
+        self.trigger_webhook(WebhookPayload::PackageUpdated(package)).await
+    }
+
+    pub async fn trigger_webhook_package_deleted(&self, package: PackageResponse) {
+        self.trigger_webhook(WebhookPayload::PackageDeleted(package)).await
+    }
+
+    async fn trigger_webhook(&self, payload: WebhookPayload) {
         for endpoint in self.config.read().await.webhooks.iter() {
             let response = self.client.post(endpoint)
-                .json(&WebhookPayload::PackageUpdated(package.clone()))
+                .json(&payload)
                 .send()
                 .await;
 
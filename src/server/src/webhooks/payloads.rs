@@ -5,4 +5,5 @@ use common::http::responses::PackageResponse;
 #[serde(tag = "type", content = "payload")]
 pub enum WebhookPayload {
     PackageUpdated(PackageResponse),
+    PackageDeleted(PackageResponse),
 }
\ No newline at end of file
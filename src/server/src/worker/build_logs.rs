@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Maximum amount of bytes of the running build logs kept for clients starting to follow a build.
+const MAX_BUFFERED_LOGS: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildLogEvent {
+    Content(String),
+    End,
+}
+
+struct InnerBuildLogs {
+    buffers: HashMap<i32, String>,
+    sender: broadcast::Sender<(i32, BuildLogEvent)>,
+}
+
+/// Fans out the logs streamed by the workers for the running builds to the clients following them.
+#[derive(Clone)]
+pub struct BuildLogs {
+    inner: Arc<Mutex<InnerBuildLogs>>,
+}
+
+impl BuildLogs {
+    pub fn new() -> Self
+    {
+        let (sender, _) = broadcast::channel(1024);
+        BuildLogs {
+            inner: Arc::new(Mutex::new(InnerBuildLogs {
+                buffers: HashMap::new(),
+                sender,
+            })),
+        }
+    }
+
+    /// Resets the logs of the package when a new build starts.
+    pub fn start(&self, package_id: i32) {
+        self.inner.lock().unwrap().buffers.insert(package_id, String::new());
+    }
+
+    pub fn push(&self, package_id: i32, content: String) {
+        let mut inner = self.inner.lock().unwrap();
+        let buffer = inner.buffers.entry(package_id).or_default();
+        buffer.push_str(&content);
+        if buffer.len() > MAX_BUFFERED_LOGS {
+            let mut start = buffer.len() - MAX_BUFFERED_LOGS;
+            while !buffer.is_char_boundary(start) {
+                start += 1;
+            }
+            buffer.drain(..start);
+        }
+        let _ = inner.sender.send((package_id, BuildLogEvent::Content(content)));
+    }
+
+    /// Ends the log streams of the package once its build is over.
+    pub fn finish(&self, package_id: i32) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.buffers.remove(&package_id);
+        let _ = inner.sender.send((package_id, BuildLogEvent::End));
+    }
+
+    /// Returns the logs of the running build of the package received so far and a receiver for the following ones.
+    pub fn subscribe(&self, package_id: i32) -> (String, broadcast::Receiver<(i32, BuildLogEvent)>) {
+        let inner = self.inner.lock().unwrap();
+        (
+            inner.buffers.get(&package_id).cloned().unwrap_or_default(),
+            inner.sender.subscribe(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::worker::build_logs::{BuildLogEvent, BuildLogs};
+
+    #[test]
+    fn subscribe_build_logs() {
+        let logs = BuildLogs::new();
+        logs.start(1);
+        logs.push(1, "first ".to_string());
+
+        let (buffered, mut receiver) = logs.subscribe(1);
+        assert_eq!("first ", buffered);
+
+        logs.push(2, "other".to_string());
+        logs.push(1, "second".to_string());
+        logs.finish(1);
+
+        assert_eq!((2, BuildLogEvent::Content("other".to_string())), receiver.try_recv().unwrap());
+        assert_eq!((1, BuildLogEvent::Content("second".to_string())), receiver.try_recv().unwrap());
+        assert_eq!((1, BuildLogEvent::End), receiver.try_recv().unwrap());
+        assert_eq!("", logs.subscribe(1).0);
+
+        logs.start(1);
+        logs.push(1, "é".repeat(super::MAX_BUFFERED_LOGS));
+        assert!(logs.subscribe(1).0.len() <= super::MAX_BUFFERED_LOGS);
+    }
+}
@@ -1,2 +1,3 @@
+pub mod build_logs;
 pub mod worker;
 pub mod worker_manager;
\ No newline at end of file
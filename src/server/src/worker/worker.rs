@@ -2,19 +2,21 @@ use actix_ws::{AggregatedMessage, AggregatedMessageStream, ProtocolError, Sessio
 use anyhow::{anyhow, Result};
 use common::http::responses::WorkerResponse;
 use common::messages::WebsocketMessage;
-use common::models::{PackageJob, WorkerStatus};
+use common::models::{PackageJob, WorkerLabels, WorkerStatus};
 use futures_util::{StreamExt};
 use log::{debug, error, info};
 use std::sync::Arc;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
+use crate::worker::build_logs::BuildLogs;
 
 pub struct InnerWorker {
     id: usize,
     status: WorkerStatus,
     current_job: Option<PackageJob>,
     version: String,
+    labels: WorkerLabels,
 }
 
 pub struct Worker {
@@ -30,12 +32,14 @@ impl Worker {
         id: usize,
         session: Session,
         stream: AggregatedMessageStream,
+        build_logs: BuildLogs,
     ) -> Worker {
         let inner = Arc::new(Mutex::new(InnerWorker {
             id,
             status: WorkerStatus::STANDBY,
             current_job: None,
             version: "Unknown".to_string(),
+            labels: WorkerLabels::default(),
         }));
 
         let (tx_message, rx_message) = unbounded_channel();
@@ -44,6 +48,7 @@ impl Worker {
             stream,
             rx_message,
             inner.clone(),
+            build_logs,
         ));
 
         Worker {
@@ -72,6 +77,16 @@ impl Worker {
         Ok(())
     }
 
+    /// Asks the worker to stop its current job and clean its sandbox.
+    pub async fn cancel_job(&self) -> Result<()> {
+        if let Some(job) = self.get_current_job().await {
+            self.tx_message.send(WebsocketMessage::JobCancel {
+                package_id: job.definition.package_id,
+            })?;
+        }
+        Ok(())
+    }
+
     pub async fn get_current_job(&self) -> Option<PackageJob> {
         self.inner.lock().await.current_job.clone()
     }
@@ -80,6 +95,10 @@ impl Worker {
         self.inner.lock().await.status
     }
 
+    pub async fn get_labels(&self) -> WorkerLabels {
+        self.inner.lock().await.labels.clone()
+    }
+
     pub fn is_finished(&self) -> bool {
         self.websocket_task.is_finished()
     }
@@ -100,6 +119,7 @@ impl Worker {
                 .as_ref()
                 .map(|i| i.definition.name.clone()),
             version: inner_lock.version.clone(),
+            labels: inner_lock.labels.clone(),
         }
     }
 }
@@ -115,6 +135,7 @@ async fn websocket_loop(
     mut stream: AggregatedMessageStream,
     mut rx: UnboundedReceiver<WebsocketMessage>,
     state: Arc<Mutex<InnerWorker>>,
+    build_logs: BuildLogs,
 ) {
     loop {
         tokio::select! {
@@ -136,7 +157,7 @@ async fn websocket_loop(
                     }
                     Some(message) => {
                         if let Err(e) =
-                            handle_received_message(message, &mut session, &state).await {
+                            handle_received_message(message, &mut session, &state, &build_logs).await {
                             error!("Error handling received message: {}", e);
                             return;
                         }
@@ -151,6 +172,7 @@ async fn handle_received_message(
     received_message: Result<AggregatedMessage, ProtocolError>,
     session: &mut Session,
     state: &Arc<Mutex<InnerWorker>>,
+    build_logs: &BuildLogs,
 ) -> Result<()> {
     match received_message {
         Ok(message) => {
@@ -158,17 +180,21 @@ async fn handle_received_message(
                 AggregatedMessage::Text(message) => {
                     let parsed: WebsocketMessage = serde_json::from_str(&message)?;
                     match parsed {
-                        WebsocketMessage::WorkerHello {status, version} => {
+                        WebsocketMessage::WorkerHello {status, version, labels} => {
                             let mut state = state.lock().await;
                             state.version = version;
                             state.status = status;
-                            info!("Worker id {} connected successfully with version {}", state.id, state.version);
+                            state.labels = labels;
+                            info!("Worker id {} connected successfully with version {} and labels {:?}", state.id, state.version, state.labels);
                         },
                         WebsocketMessage::WorkerStatusUpdate {status, job} => {
                             let mut state = state.lock().await;
                             state.status = status;
                             state.current_job = job;
                         }
+                        WebsocketMessage::BuildLogs {package_id, content} => {
+                            build_logs.push(package_id, content);
+                        }
                         _ => {}
                     }
                 }
@@ -1,18 +1,21 @@
 use actix_ws::{AggregatedMessageStream, Session};
-use common::models::{PackageJob, WorkerStatus};
+use anyhow::Result;
+use common::models::{PackageJob, PackageRequirements, WorkerStatus};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::worker::build_logs::BuildLogs;
 use crate::worker::worker::Worker;
 
 pub enum WorkerDispatchResult {
     NoneAvailable,
-    Ok,
+    Ok(usize),
     Err(anyhow::Error)
 }
 
 pub struct WorkerManager {
     next_id: Arc<AtomicUsize>,
+    build_logs: BuildLogs,
 
     workers: Vec<Worker>
 }
@@ -22,11 +25,17 @@ impl WorkerManager {
     {
         WorkerManager {
             next_id: Arc::new(AtomicUsize::new(0)),
+            build_logs: BuildLogs::new(),
 
             workers: Default::default(),
         }
     }
 
+    pub fn get_build_logs(&self) -> &BuildLogs
+    {
+        &self.build_logs
+    }
+
     pub fn get_workers(&self) -> &Vec<Worker>
     {
         &self.workers
@@ -38,6 +47,7 @@ impl WorkerManager {
             id,
             session,
             stream,
+            self.build_logs.clone(),
         );
         self.workers.push(worker);
     }
@@ -68,21 +78,42 @@ impl WorkerManager {
         removed
     }
 
-    async fn get_next_free_worker(&mut self) -> Option<&mut Worker> {
-        for worker in self.workers.iter_mut() {
+    /// Cancels the job of the worker building the given package. Returns false if no worker is building it.
+    pub async fn cancel_job(&self, package_id: i32) -> Result<bool> {
+        for worker in self.workers.iter() {
+            if worker.get_current_job().await.is_some_and(|job| job.definition.package_id == package_id) {
+                worker.cancel_job().await?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub async fn has_free_worker(&self) -> bool {
+        for worker in self.workers.iter() {
             if worker.get_status().await == WorkerStatus::STANDBY {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn get_next_free_worker(&mut self, requirements: &PackageRequirements, default_arch: &str) -> Option<&mut Worker> {
+        for worker in self.workers.iter_mut() {
+            if worker.get_status().await == WorkerStatus::STANDBY
+                && worker.get_labels().await.satisfies(requirements, default_arch) {
                 return Some(worker);
             }
         }
         None
     }
 
-    pub async fn dispatch(&mut self, package_job: PackageJob) -> WorkerDispatchResult {
-        match self.get_next_free_worker().await {
+    pub async fn dispatch(&mut self, package_job: PackageJob, requirements: &PackageRequirements, default_arch: &str) -> WorkerDispatchResult {
+        match self.get_next_free_worker(requirements, default_arch).await {
             None => WorkerDispatchResult::NoneAvailable,
             Some(worker) => {
                 match worker.dispatch_package(package_job).await {
-                    Ok(_) => WorkerDispatchResult::Ok,
+                    Ok(_) => WorkerDispatchResult::Ok(worker.get_id()),
                     Err(e) => WorkerDispatchResult::Err(e)
                 }
             }
@@ -1,18 +1,64 @@
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use std::path::{PathBuf};
-use std::process::{Output};
+use std::process::{Output, Stdio};
+use clap::ValueEnum;
 use log::{debug, error, info, warn};
+use serde::Deserialize;
 use tokio::fs::{create_dir_all, remove_dir_all};
 use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
 use crate::builder::utils::run_command;
 use crate::logs::LogSection;
 use crate::models::config::Config;
-use crate::utils::{copy_dir, get_package_dir_entries};
+use crate::utils::{copy_dir, get_package_dir_entries, reflink_dir};
+
+/// Mount point of the source cache in the sandbox, used by makepkg as `SRCDEST`.
+const SOURCE_CACHE_MOUNT: &str = "/srcdest";
+
+/// How the throwaway environments are created from the base sandbox.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxBackend {
+    /// Full copy of the base sandbox.
+    #[default]
+    Copy,
+    /// Copy sharing the data blocks of the base sandbox on filesystems supporting it (btrfs, xfs), full copy otherwise.
+    Reflink,
+    /// Writable directory mounted over the read-only base sandbox by bubblewrap, requires bubblewrap 0.8 and Linux 5.11.
+    Overlay,
+}
+
+/// Extra mounts and environment variables of the commands run in the sandbox for a package.
+#[derive(Clone, Debug, Default)]
+pub struct SandboxOptions {
+    /// Host paths bind mounted at the given path in the sandbox.
+    pub binds: Vec<(PathBuf, String)>,
+    pub env: Vec<(String, String)>,
+}
 
+impl SandboxOptions {
+    pub fn extend(&mut self, other: SandboxOptions) {
+        self.binds.extend(other.binds);
+        self.env.extend(other.env);
+    }
+}
+
+#[derive(Clone)]
 pub struct Bubblewrap {
     sandbox_path: PathBuf,
     pacman_config_path: PathBuf,
     pacman_mirrorlist_path: PathBuf,
+    backend: SandboxBackend,
+    pacman_cache_path: Option<PathBuf>,
+    source_cache_path: Option<PathBuf>,
+    options: SandboxOptions,
+    /// `cgroup.procs` file of the cgroup the sandbox commands are moved into.
+    cgroup_procs_path: Option<PathBuf>,
+    /// Whether the sandbox commands run in their own network namespace, without network access.
+    unshare_net: bool,
+    /// Whether the environment is mounted read-only as the root of the sandbox.
+    read_only_root: bool,
+    log_sender: Option<UnboundedSender<String>>,
 }
 
 impl Bubblewrap {
@@ -22,6 +68,14 @@ impl Bubblewrap {
             sandbox_path: config.sandbox_path.clone(),
             pacman_config_path: config.pacman_config_path.clone(),
             pacman_mirrorlist_path: config.pacman_mirrorlist_path.clone(),
+            backend: config.sandbox_backend,
+            pacman_cache_path: Some(config.pacman_cache_path.clone()),
+            source_cache_path: Some(config.source_cache_path.clone()),
+            options: SandboxOptions::default(),
+            cgroup_procs_path: None,
+            unshare_net: false,
+            read_only_root: false,
+            log_sender: None,
         }
     }
 
@@ -32,28 +86,93 @@ impl Bubblewrap {
             sandbox_path,
             pacman_config_path,
             pacman_mirrorlist_path,
+            backend: SandboxBackend::Copy,
+            pacman_cache_path: None,
+            source_cache_path: None,
+            options: SandboxOptions::default(),
+            cgroup_procs_path: None,
+            unshare_net: false,
+            read_only_root: false,
+            log_sender: None,
         }
     }
 
+    #[allow(dead_code)] // Used in test cases
+    pub fn with_backend(mut self, backend: SandboxBackend) -> Bubblewrap
+    {
+        self.backend = backend;
+        self
+    }
+
+    /// Forwards the output of the sandbox commands written to a log file to the given sender.
+    pub fn with_log_sender(mut self, log_sender: UnboundedSender<String>) -> Bubblewrap
+    {
+        self.log_sender = Some(log_sender);
+        self
+    }
+
+    /// Runs the sandbox commands with the given extra mounts and environment.
+    pub fn with_options(mut self, options: SandboxOptions) -> Bubblewrap
+    {
+        self.options = options;
+        self
+    }
+
+    /// Runs the sandbox commands in the cgroup of the given `cgroup.procs` file.
+    pub fn with_cgroup(mut self, cgroup_procs_path: PathBuf) -> Bubblewrap
+    {
+        self.cgroup_procs_path = Some(cgroup_procs_path);
+        self
+    }
+
+    /// Runs the sandbox commands without network access.
+    pub fn with_unshared_network(mut self) -> Bubblewrap
+    {
+        self.unshare_net = true;
+        self
+    }
+
+    /// Mounts the environment read-only, for commands that must not alter it.
+    pub fn with_read_only_root(mut self) -> Bubblewrap
+    {
+        self.read_only_root = true;
+        self
+    }
+
     pub fn namespace_path(&self, name: &str) -> PathBuf
     {
         self.sandbox_path.join(name)
     }
 
-    pub async fn delete(&self, name: &str) -> Result<()> {
-        let path = self.sandbox_path.join(name);
+    /// Work directory of the overlay mounted for the given environment, it must be on the same filesystem as the environment.
+    fn overlay_work_path(&self, name: &str) -> PathBuf
+    {
+        self.sandbox_path.join(".overlay-work").join(name)
+    }
+
+    async fn remove_path(path: &PathBuf) -> Result<()> {
         if path.exists() {
-            info!("Deleting {}", name);
             let out = Command::new("unshare")
                 .args(vec!["--map-auto", "-r", "--", "rm", "-rf", path.canonicalize()?.to_str().ok_or(anyhow!("Failed to canonicalize path"))?])
                 .output().await?;
             if !out.status.success() {
-                bail!("Failed to delete sandbox {} with code {:?}", name, out.status.code());
+                bail!("Failed to delete {:?} with code {:?}", path, out.status.code());
             }
         }
         Ok(())
     }
 
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let path = self.sandbox_path.join(name);
+        if path.exists() {
+            info!("Deleting {}", name);
+            Self::remove_path(&path).await
+                .with_context(|| format!("Failed to delete sandbox {}", name))?;
+        }
+        Self::remove_path(&self.overlay_work_path(name)).await?;
+        Ok(())
+    }
+
     pub async fn create(&self, force: bool) -> Result<()>
     {
         if force {
@@ -91,12 +210,16 @@ impl Bubblewrap {
             "--config", &self.sandbox_path.join("base/etc/pacman.conf").canonicalize()?.to_str().unwrap(),
             "base", "fakeroot", "base-devel"
         ]);
+        if let Some(cache_path) = self.get_pacman_cache_path()? {
+            command.args(["--cachedir", cache_path.as_str()]);
+        }
 
         let res = run_command(
             command,
             None,
+            None,
             None
-        )?.wait_with_output().await?;
+        ).await?;
 
         debug!("pacman init command command output: {:?} ",res.status.code());
 
@@ -113,26 +236,88 @@ impl Bubblewrap {
         Ok(())
     }
 
+    /// Rewrites the pacman configuration of the base sandbox from the configured one, followed by the given sections.
+    pub async fn write_pacman_config(&self, extra_sections: &str) -> Result<()>
+    {
+        let mut config = tokio::fs::read_to_string(&self.pacman_config_path).await
+            .with_context(|| format!("Failed to read {:?}", self.pacman_config_path))?;
+        if !extra_sections.is_empty() {
+            config.push('\n');
+            config.push_str(extra_sections);
+        }
+        tokio::fs::write(self.sandbox_path.join("base/etc/pacman.conf"), config).await?;
+        Ok(())
+    }
+
     pub async fn create_from_base(&self, name: &str) -> Result<PathBuf>
     {
         self.delete(name).await?;
 
         let dest = self.sandbox_path.join(name);
-        copy_dir(self.sandbox_path.join("base"), dest.clone()).await?;
+        match self.backend {
+            SandboxBackend::Copy => copy_dir(self.sandbox_path.join("base"), dest.clone()).await?,
+            SandboxBackend::Reflink => reflink_dir(self.sandbox_path.join("base"), dest.clone()).await?,
+            SandboxBackend::Overlay => {
+                create_dir_all(&dest).await?;
+                create_dir_all(self.overlay_work_path(name)).await?;
+            }
+        }
 
         Ok(dest)
     }
 
-    pub async fn run_sandbox(
+    /// Canonical path of the host pacman cache shared by the sandboxes, if there is one.
+    fn get_pacman_cache_path(&self) -> Result<Option<String>>
+    {
+        Self::get_cache_path(&self.pacman_cache_path)
+    }
+
+    fn get_cache_path(path: &Option<PathBuf>) -> Result<Option<String>>
+    {
+        match path {
+            Some(path) if path.exists() => Ok(Some(path.canonicalize()?.to_string_lossy().to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Bubblewrap arguments mounting the given environment as the root of the sandbox.
+    fn get_root_args(&self, env: &str) -> Result<Vec<String>>
+    {
+        let env_path = self.sandbox_path.join(env).canonicalize()?.to_string_lossy().to_string();
+        if self.backend != SandboxBackend::Overlay || env == "base" {
+            let bind = if self.read_only_root { "--ro-bind" } else { "--bind" };
+            return Ok(vec![bind.to_string(), env_path, "/".to_string()]);
+        }
+
+        if self.read_only_root {
+            return Ok(vec![
+                "--overlay-src".to_string(),
+                self.sandbox_path.join("base").canonicalize()?.to_string_lossy().to_string(),
+                "--overlay-src".to_string(),
+                env_path,
+                "--ro-overlay".to_string(),
+                "/".to_string(),
+            ]);
+        }
+
+        Ok(vec![
+            "--overlay-src".to_string(),
+            self.sandbox_path.join("base").canonicalize()?.to_string_lossy().to_string(),
+            "--overlay".to_string(),
+            env_path,
+            self.overlay_work_path(env).canonicalize()?.to_string_lossy().to_string(),
+            "/".to_string(),
+        ])
+    }
+
+    fn get_sandbox_command(
         &self,
         as_root: bool,
         env: &str,
         chdir: &str,
         program: &str,
-        mut program_args: Vec<&str>,
-        log_path: Option<&PathBuf>,
-        log_section: Option<LogSection>
-    ) -> Result<Output>
+        program_args: Vec<&str>,
+    ) -> Result<Command>
     {
         let as_user = if as_root {
             "-r"
@@ -140,44 +325,101 @@ impl Bubblewrap {
             "-c"
         };
 
-        let env_path = self.sandbox_path.join(env).canonicalize()?;
+        let mut root_args = self.get_root_args(env)?;
+        if let Some(cache_path) = self.get_pacman_cache_path()? {
+            root_args.extend(["--bind".to_string(), cache_path, "/var/cache/pacman/pkg".to_string()]);
+        }
+        let source_cache_path = Self::get_cache_path(&self.source_cache_path)?;
+        if let Some(cache_path) = source_cache_path.clone() {
+            root_args.extend(["--bind".to_string(), cache_path, SOURCE_CACHE_MOUNT.to_string()]);
+        }
+        for (source, destination) in self.options.binds.iter() {
+            root_args.extend([
+                "--bind".to_string(),
+                source.canonicalize()?.to_string_lossy().to_string(),
+                destination.clone(),
+            ]);
+        }
 
         let mut args = vec![
             "--map-auto",
             as_user,
             "--",
             "bwrap",
-            "--bind", env_path.to_str().unwrap(), "/",
+        ];
+        args.extend(root_args.iter().map(|a| a.as_str()));
+        if self.unshare_net {
+            args.push("--unshare-net");
+        }
+        args.extend([
             "--ro-bind", "/etc/resolv.conf", "/etc/resolv.conf",
             "--perms", "1777",
             "--tmpfs", "/tmp",
             "--proc", "/proc",
             "--dev", "/dev",
             "--chdir", chdir,
-        ];
-        args.push(program);
-        args.append(&mut program_args);
+        ]);
+
+        debug!("sandbox command {:?} {} {:?}", args, program, program_args);
 
-        let mut command = Command::new("unshare");
+        let mut command = match &self.cgroup_procs_path {
+            // The shell joins the cgroup before anything else runs, so all the children are accounted
+            Some(cgroup_procs_path) => {
+                let mut command = Command::new("sh");
+                command.args(["-c", "echo $$ > \"$0\" && exec \"$@\""])
+                    .arg(cgroup_procs_path)
+                    .arg("unshare");
+                command
+            }
+            None => Command::new("unshare"),
+        };
         command
             .env_clear()
             .env("FAKEROOTDONTTRYCHOWN", "true")
             .env("HOME", "/home/app")
             .env("_JAVA_OPTIONS", "-Duser.home=/home/app")
             .env("USER", "app")
-            .env("PATH", "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin")
-            .args(&args);
+            .env("PATH", "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin");
+        if source_cache_path.is_some() {
+            command.env("SRCDEST", SOURCE_CACHE_MOUNT);
+        }
+        command.envs(self.options.env.iter().map(|(k, v)| (k, v)));
+        command
+            .args(&args)
+            .arg(program)
+            .args(&program_args);
+        Ok(command)
+    }
+
+    pub async fn run_sandbox(
+        &self,
+        as_root: bool,
+        env: &str,
+        chdir: &str,
+        program: &str,
+        program_args: Vec<&str>,
+        log_path: Option<&PathBuf>,
+        log_section: Option<LogSection>
+    ) -> Result<Output>
+    {
+        let command = self.get_sandbox_command(as_root, env, chdir, program, program_args)?;
 
         let res = run_command(command,
             log_path,
-            log_section
-        )?.wait_with_output().await?;
+            log_section,
+            self.log_sender.clone()
+        ).await?;
 
-        debug!(
-            "sandbox command {:?} code: {:?}",
-            args,
-            res.status.code(),
-        );
+        debug!("sandbox command {} code: {:?}", program, res.status.code());
+        Ok(res)
+    }
+
+    /// Runs the command in the sandbox and captures its output instead of logging it.
+    pub async fn query_sandbox(&self, as_root: bool, env: &str, chdir: &str, program: &str, program_args: Vec<&str>) -> Result<Output>
+    {
+        let mut command = self.get_sandbox_command(as_root, env, chdir, program, program_args)?;
+        let res = command.stdin(Stdio::null()).output().await?;
+        debug!("sandbox query {} code: {:?}", program, res.status.code());
         Ok(res)
     }
 
@@ -225,4 +467,81 @@ impl Bubblewrap {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use tokio::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+    use crate::builder::bubblewrap::{Bubblewrap, SandboxBackend};
+    use crate::commands::pacman::get_server_repo_config;
+
+    #[tokio::test]
+    async fn create_from_base_backends() {
+        let sandbox_path = std::env::temp_dir().join("aur-build-worker-sandbox-test");
+        let _ = remove_dir_all(&sandbox_path).await;
+        create_dir_all(sandbox_path.join("base/etc")).await.unwrap();
+        write(sandbox_path.join("base/etc/pacman.conf"), "[options]").await.unwrap();
+
+        let bubblewrap = Bubblewrap::new(sandbox_path.clone(), PathBuf::new(), PathBuf::new())
+            .with_backend(SandboxBackend::Reflink);
+        let path = bubblewrap.create_from_base("reflink").await.unwrap();
+        assert!(path.join("etc/pacman.conf").exists());
+        assert_eq!(vec!["--bind", path.canonicalize().unwrap().to_str().unwrap(), "/"], bubblewrap.get_root_args("reflink").unwrap());
+
+        let bubblewrap = bubblewrap.with_backend(SandboxBackend::Overlay);
+        let path = bubblewrap.create_from_base("overlay").await.unwrap();
+        assert!(path.exists());
+        assert!(!path.join("etc").exists());
+        let base = sandbox_path.join("base").canonicalize().unwrap();
+        let work = sandbox_path.join(".overlay-work/overlay").canonicalize().unwrap();
+        let path = path.canonicalize().unwrap();
+        assert_eq!(
+            vec![
+                "--overlay-src", base.to_str().unwrap(),
+                "--overlay", path.to_str().unwrap(), work.to_str().unwrap(), "/",
+            ],
+            bubblewrap.get_root_args("overlay").unwrap()
+        );
+        assert_eq!(vec!["--bind", base.to_str().unwrap(), "/"], bubblewrap.get_root_args("base").unwrap());
+
+        remove_dir_all(&sandbox_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn write_pacman_config() {
+        let sandbox_path = std::env::temp_dir().join("aur-build-worker-pacman-config-test");
+        create_dir_all(sandbox_path.join("base/etc")).await.unwrap();
+        write(sandbox_path.join("pacman.conf"), "[options]\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n").await.unwrap();
+
+        let bubblewrap = Bubblewrap::new(sandbox_path.clone(), sandbox_path.join("pacman.conf"), PathBuf::new());
+        bubblewrap.write_pacman_config(&get_server_repo_config("aurbuild", "http://server:8888/repo/aurbuild/os/x86_64", true)).await.unwrap();
+        assert_eq!(
+            "[options]\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n\n[aurbuild]\nSigLevel = Required\nServer = http://server:8888/repo/aurbuild/os/x86_64\n",
+            read_to_string(sandbox_path.join("base/etc/pacman.conf")).await.unwrap()
+        );
+
+        bubblewrap.write_pacman_config("").await.unwrap();
+        assert_eq!(
+            "[options]\n\n[core]\nInclude = /etc/pacman.d/mirrorlist\n",
+            read_to_string(sandbox_path.join("base/etc/pacman.conf")).await.unwrap()
+        );
+
+        remove_dir_all(&sandbox_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unshare_network() {
+        let sandbox_path = std::env::temp_dir().join("aur-build-worker-network-test");
+        create_dir_all(sandbox_path.join("current")).await.unwrap();
+
+        let bubblewrap = Bubblewrap::new(sandbox_path.clone(), PathBuf::new(), PathBuf::new());
+        let command = bubblewrap.get_sandbox_command(false, "current", "/package", "makepkg", vec![]).unwrap();
+        assert!(!command.as_std().get_args().any(|arg| arg == "--unshare-net"));
+
+        let bubblewrap = bubblewrap.with_unshared_network();
+        let command = bubblewrap.get_sandbox_command(false, "current", "/package", "makepkg", vec![]).unwrap();
+        assert!(command.as_std().get_args().any(|arg| arg == "--unshare-net"));
+
+        remove_dir_all(&sandbox_path).await.unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use std::path::Path;
+use tokio::fs::create_dir_all;
+use common::models::BuildEnvironment;
+use crate::builder::bubblewrap::SandboxOptions;
+
+/// Quotes the value for a bash script.
+fn shell_quote(value: &str) -> String
+{
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// makepkg configuration applying the settings of the build environment on top of the distribution ones.
+fn get_makepkg_config(build_env: &BuildEnvironment) -> String
+{
+    let mut config = String::new();
+    if let Some(makeflags) = build_env.makeflags.as_ref() {
+        config += &format!("MAKEFLAGS={}\n", shell_quote(makeflags));
+    }
+    if let Some(cflags) = build_env.cflags.as_ref() {
+        config += &format!("CFLAGS={}\n", shell_quote(cflags));
+    }
+    if let Some(pkgext) = build_env.pkgext.as_ref() {
+        config += &format!("PKGEXT={}\n", shell_quote(pkgext));
+    }
+    if !build_env.options.is_empty() {
+        // makepkg uses the last occurrence of an option
+        let options = build_env.options.iter().map(|o| shell_quote(o)).collect::<Vec<_>>();
+        config += &format!("OPTIONS+=({})\n", options.join(" "));
+    }
+    config
+}
+
+/// Writes the makepkg settings of the build environment in the configuration of the given build environment root.
+pub async fn write_makepkg_config(root: &Path, build_env: &BuildEnvironment) -> Result<()>
+{
+    let config = get_makepkg_config(build_env);
+    if config.is_empty() {
+        return Ok(());
+    }
+
+    let config_dir = root.join("etc/makepkg.conf.d");
+    create_dir_all(&config_dir).await?;
+    tokio::fs::write(config_dir.join("build-env.conf"), config).await?;
+    Ok(())
+}
+
+/// Sandbox options passing the extra environment variables of the build environment.
+pub fn get_sandbox_options(build_env: &BuildEnvironment) -> SandboxOptions
+{
+    SandboxOptions {
+        binds: vec![],
+        env: build_env.env.clone().into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::models::BuildEnvironment;
+    use crate::builder::build_env::get_makepkg_config;
+
+    #[test]
+    fn test_get_makepkg_config() {
+        assert_eq!("", get_makepkg_config(&BuildEnvironment::default()));
+
+        let build_env = BuildEnvironment {
+            makeflags: Some("-j8".to_string()),
+            cflags: Some("-O2 -pipe -DNAME='it'".to_string()),
+            pkgext: Some(".pkg.tar.xz".to_string()),
+            options: vec!["!debug".to_string(), "!lto".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            "MAKEFLAGS='-j8'\nCFLAGS='-O2 -pipe -DNAME='\\''it'\\'''\nPKGEXT='.pkg.tar.xz'\nOPTIONS+=('!debug' '!lto')\n",
+            get_makepkg_config(&build_env)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use log::{debug, info};
+use tokio::fs::create_dir_all;
+use common::models::CompilerCacheStats;
+use crate::builder::bubblewrap::{Bubblewrap, SandboxOptions};
+use crate::models::config::Config;
+
+/// Packages installed in the build environment when the compiler cache is enabled.
+pub const COMPILER_CACHE_PACKAGES: [&str; 2] = ["ccache", "sccache"];
+
+const CCACHE_MOUNT: &str = "/build-cache/ccache";
+const SCCACHE_MOUNT: &str = "/build-cache/sccache";
+
+/// Enables ccache in the BUILDENV of makepkg, keeping the other options of the distribution configuration.
+const MAKEPKG_CONFIG: &str = "BUILDENV=(\"${BUILDENV[@]/#!ccache/ccache}\")\n";
+
+/// Persistent ccache and sccache directories of each package base, shared across its builds.
+pub struct CompilerCache {
+    path: PathBuf,
+}
+
+impl CompilerCache {
+    pub fn from_config(config: &Config) -> CompilerCache
+    {
+        CompilerCache {
+            path: config.compiler_cache_path.clone(),
+        }
+    }
+
+    /// Creates the cache directories of the package base and returns the sandbox options using them.
+    pub async fn get_sandbox_options(&self, package_base: &str) -> Result<SandboxOptions>
+    {
+        let path = self.path.join(package_base);
+        create_dir_all(path.join("ccache")).await?;
+        create_dir_all(path.join("sccache")).await?;
+
+        Ok(SandboxOptions {
+            binds: vec![
+                (path.join("ccache"), CCACHE_MOUNT.to_string()),
+                (path.join("sccache"), SCCACHE_MOUNT.to_string()),
+            ],
+            env: vec![
+                ("CCACHE_DIR".to_string(), CCACHE_MOUNT.to_string()),
+                ("SCCACHE_DIR".to_string(), SCCACHE_MOUNT.to_string()),
+                ("RUSTC_WRAPPER".to_string(), "sccache".to_string()),
+            ],
+        })
+    }
+
+    /// Enables ccache in the makepkg configuration of the given build environment.
+    pub async fn write_makepkg_config(root: &Path) -> Result<()>
+    {
+        let config_dir = root.join("etc/makepkg.conf.d");
+        create_dir_all(&config_dir).await?;
+        tokio::fs::write(config_dir.join("compiler-cache.conf"), MAKEPKG_CONFIG).await?;
+        Ok(())
+    }
+
+    /// Resets the ccache statistics before a build.
+    pub async fn reset_stats(bubblewrap: &Bubblewrap) -> Result<()>
+    {
+        let output = bubblewrap.query_sandbox(true, "current", "/", "ccache", vec!["--zero-stats"]).await?;
+        if !output.status.success() {
+            bail!("Failed to reset ccache statistics with code {:?}", output.status.code());
+        }
+        Ok(())
+    }
+
+    /// Collects the ccache and sccache statistics of the build, stopping the sccache server started by cargo.
+    pub async fn get_stats(bubblewrap: &Bubblewrap) -> Result<CompilerCacheStats>
+    {
+        let ccache = bubblewrap.query_sandbox(true, "current", "/", "ccache", vec!["--print-stats"]).await?;
+        if !ccache.status.success() {
+            bail!("Failed to get ccache statistics with code {:?}", ccache.status.code());
+        }
+        let mut stats = parse_ccache_stats(&String::from_utf8_lossy(&ccache.stdout));
+
+        // Fails when no Rust code was compiled as the server was never started
+        let sccache = bubblewrap.query_sandbox(true, "current", "/", "sccache", vec!["--stop-server"]).await?;
+        if sccache.status.success() {
+            let sccache_stats = parse_sccache_stats(&String::from_utf8_lossy(&sccache.stdout));
+            stats.hits += sccache_stats.hits;
+            stats.misses += sccache_stats.misses;
+        } else {
+            debug!("No sccache server to stop, code {:?}", sccache.status.code());
+        }
+
+        info!("Compiler cache: {} hits, {} misses", stats.hits, stats.misses);
+        Ok(stats)
+    }
+}
+
+/// Parses the tab separated output of `ccache --print-stats`.
+fn parse_ccache_stats(output: &str) -> CompilerCacheStats
+{
+    let mut stats = CompilerCacheStats::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('\t') else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().unwrap_or(0);
+        match key {
+            "direct_cache_hit" | "preprocessed_cache_hit" => stats.hits += value,
+            "cache_miss" => stats.misses += value,
+            _ => {}
+        }
+    }
+    stats
+}
+
+/// Parses the `Cache hits` and `Cache misses` totals of the human readable sccache statistics.
+fn parse_sccache_stats(output: &str) -> CompilerCacheStats
+{
+    let mut stats = CompilerCacheStats::default();
+    for line in output.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let [first, second, value] = words.as_slice() else {
+            continue;
+        };
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+        match (*first, *second) {
+            ("Cache", "hits") => stats.hits += value,
+            ("Cache", "misses") => stats.misses += value,
+            _ => {}
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use common::models::CompilerCacheStats;
+    use crate::builder::compiler_cache::{parse_ccache_stats, parse_sccache_stats};
+
+    #[test]
+    fn test_parse_stats() {
+        let ccache = "stats_updated_timestamp\t1700000000\ndirect_cache_hit\t10\npreprocessed_cache_hit\t2\ncache_miss\t5\n";
+        assert_eq!(CompilerCacheStats { hits: 12, misses: 5 }, parse_ccache_stats(ccache));
+
+        let sccache = "Compile requests                     42\n\
+            Cache hits                           30\n\
+            Cache hits (Rust)                    30\n\
+            Cache misses                          8\n\
+            Cache misses (Rust)                   8\n\
+            Cache timeouts                        0\n";
+        assert_eq!(CompilerCacheStats { hits: 30, misses: 8 }, parse_sccache_stats(sccache));
+    }
+}
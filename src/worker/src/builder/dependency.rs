@@ -1,20 +1,28 @@
-use anyhow::{anyhow, bail, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::path::{PathBuf};
 use async_recursion::async_recursion;
 use futures_util::future;
-use log::{debug, warn};
+use log::{debug, info, warn};
 use petgraph::Graph;
 use petgraph::graph::{EdgeIndex, NodeIndex};
 use serde::Deserialize;
 use srcinfo::{Srcinfo};
 use crate::builder::bubblewrap::Bubblewrap;
+use crate::builder::sync_db::{SyncDatabases, SyncPackage};
 use crate::commands::git::clone_repo;
-use crate::commands::makepkg::get_src_info;
-use crate::commands::pacman::is_package_in_repo;
+use crate::commands::makepkg::{check_src_info, get_src_info};
+use crate::models::build_error::Classify;
+use common::models::BuildErrorClass;
+use common::version::vercmp;
 use crate::utils::sanitize_dependency;
 
 pub type DependencyGraph = Graph<AurPackage, ()>;
 
+/// Maximum amount of packages queried in a single info request to stay under URL length limits.
+const INFO_BATCH_SIZE: usize = 100;
+
 #[derive(Debug, Clone)]
 pub struct AurPackage {
     pub package_name: String,
@@ -28,11 +36,20 @@ impl PartialEq<Self> for AurPackage {
     }
 }
 
-pub async fn build_dependency_graph(bubblewrap: &Bubblewrap, data_path: &PathBuf, aur_package: AurPackage) -> Result<DependencyGraph> {
+pub async fn build_dependency_graph(
+    bubblewrap: &Bubblewrap,
+    data_path: &PathBuf,
+    aur_rpc_url: &str,
+    server_repository: Option<&str>,
+    aur_package: AurPackage,
+) -> Result<DependencyGraph> {
     let mut dep_graph = Graph::<AurPackage, ()>::new();
 
+    let sync_databases = SyncDatabases::load(&bubblewrap.namespace_path("base"), server_repository).await
+        .with_context(|| "Failed to load sync databases")?;
+
     let node = dep_graph.add_node(aur_package);
-    get_package_dependencies(&mut dep_graph, bubblewrap, data_path, node, 0).await?;
+    get_package_dependencies(&mut dep_graph, bubblewrap, &sync_databases, aur_rpc_url, data_path, node, 0).await?;
 
     debug!("Dependency graph: {:#?}", dep_graph);
 
@@ -43,6 +60,8 @@ pub async fn build_dependency_graph(bubblewrap: &Bubblewrap, data_path: &PathBuf
 async fn get_package_dependencies(
     dep_graph: &mut DependencyGraph,
     bubblewrap: &Bubblewrap,
+    sync_databases: &SyncDatabases,
+    aur_rpc_url: &str,
     data_path: &PathBuf,
     node_index: NodeIndex,
     depth: u8,
@@ -54,10 +73,11 @@ async fn get_package_dependencies(
     let node_weight = dep_graph.node_weight_mut(node_index).unwrap();
 
     if !data_path.join(&node_weight.package_base).exists() {
-        clone_repo(&data_path, &node_weight.package_base)?;
+        clone_repo(&data_path, &node_weight.package_base).classify(BuildErrorClass::Source)?;
     }
-    let src_info = get_src_info(data_path, &node_weight.package_base).await?;
-    let (aur_deps, repo_deps) = extract_dependencies(bubblewrap, &src_info).await?;
+    check_src_info(bubblewrap, data_path, &node_weight.package_base).await;
+    let src_info = get_src_info(bubblewrap, data_path, &node_weight.package_base).await?;
+    let (aur_deps, repo_deps) = extract_dependencies(sync_databases, aur_rpc_url, &src_info).await?;
     debug!("Got aur deps: {:#?}", aur_deps);
     debug!("Got repo deps: {:#?}", repo_deps);
     node_weight.repo_deps = repo_deps;
@@ -69,7 +89,7 @@ async fn get_package_dependencies(
         } else {
             let dep_node = dep_graph.add_node(aur_dep);
             if add_edge(dep_graph, node_index, dep_node).is_some() {
-                get_package_dependencies(dep_graph, bubblewrap, data_path, dep_node, depth + 1).await?
+                get_package_dependencies(dep_graph, bubblewrap, sync_databases, aur_rpc_url, data_path, dep_node, depth + 1).await?
             }
         };
     }
@@ -89,23 +109,57 @@ fn add_edge(dep_graph: &mut DependencyGraph, start: NodeIndex, end: NodeIndex) -
     }
 }
 
-async fn split_aur_dependencies(bubblewrap: &Bubblewrap, deps: Vec<String>) -> (Vec<String>, Vec<String>) {
+/// Splits the dependencies to build from the AUR from the ones installed from the repositories.
+/// Packages of the repository of the server older than their AUR version are built again.
+async fn split_aur_dependencies(sync_databases: &SyncDatabases, aur_rpc_url: &str, deps: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut server_packages: Vec<String> = deps.iter()
+        .filter_map(|dep| sync_databases.find_satisfier(dep))
+        .filter(|package| sync_databases.is_from_server(package))
+        .map(|package| package.name.clone())
+        .collect();
+    server_packages.sort();
+    server_packages.dedup();
+
+    let aur_versions = if server_packages.is_empty() {
+        HashMap::new()
+    } else {
+        aur_api_query_versions(aur_rpc_url, &server_packages).await
+            .inspect_err(|e| warn!("Could not get the AUR versions of the server repository packages {:?}: {:#}", server_packages, e))
+            .unwrap_or_default()
+    };
+
     let mut aur_dependency = Vec::new();
     let mut repo_dependency = Vec::new();
 
     for dep in deps.into_iter() {
-        if !is_package_in_repo(bubblewrap, &dep).await {
-            aur_dependency.push(dep);
-        } else {
-            repo_dependency.push(dep);
+        match sync_databases.find_satisfier(&dep) {
+            None => aur_dependency.push(dep),
+            Some(package) if sync_databases.is_from_server(package) && is_outdated(package, aur_versions.get(&package.name)) => {
+                aur_dependency.push(dep);
+            }
+            Some(_) => repo_dependency.push(dep),
         }
     }
 
     (aur_dependency, repo_dependency)
 }
 
+/// Whether the AUR has a newer version of the given package of the repository of the server.
+fn is_outdated(package: &SyncPackage, aur_version: Option<&String>) -> bool {
+    let Some(aur_version) = aur_version else {
+        warn!("No AUR version of {}, installing {} from the server repository", package.name, package.version);
+        return false;
+    };
+    if vercmp(aur_version, &package.version) != Ordering::Greater {
+        return false;
+    }
+    info!("Server repository has {} {} but the AUR has {}, building it", package.name, package.version, aur_version);
+    true
+}
+
 async fn extract_dependencies(
-    bubblewrap: &Bubblewrap,
+    sync_databases: &SyncDatabases,
+    aur_rpc_url: &str,
     srcinfo: &Srcinfo,
 ) -> Result<(Vec<AurPackage>, Vec<String>)>
 {
@@ -131,13 +185,13 @@ async fn extract_dependencies(
     dependencies.dedup();
     dependencies.retain(|d| !packages.contains(d));
 
-    let (aur_dependencies, repo_dependencies) = split_aur_dependencies(bubblewrap, dependencies).await;
+    let (aur_dependencies, repo_dependencies) = split_aur_dependencies(sync_databases, aur_rpc_url, dependencies).await;
 
     let mut aur_packages = future::join_all(
         aur_dependencies.iter().map(|i| async move {
-            aur_api_query_provides(i, false).await.ok_or(anyhow!("Failed to get aur dependency {} by provide", i))
+            aur_api_query_provides(aur_rpc_url, i, false).await.ok_or(anyhow!("Failed to get aur dependency {} by provide", i))
         })
-    ).await.into_iter().collect::<Result<Vec<AurPackage>, Error>>()?;
+    ).await.into_iter().collect::<Result<Vec<AurPackage>, Error>>().classify(BuildErrorClass::AurRpc)?;
     aur_packages.retain(|i| !packages.contains(&i.package_base));
 
     debug!("Found AUR dependencies for {} {:?}", srcinfo.base.pkgbase, aur_packages);
@@ -151,6 +205,8 @@ struct AurResult {
     pub name: String,
     #[serde(rename = "PackageBase")]
     pub package_base: String,
+    #[serde(rename = "Version")]
+    pub version: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -158,11 +214,37 @@ struct AurResults {
     pub results: Vec<AurResult>
 }
 
-pub async fn aur_api_query_provides(package_name: &String, strict: bool) -> Option<AurPackage>
+/// Current versions of the given packages on the AUR, batching the info requests.
+/// Packages not found on the AUR are absent from the result.
+async fn aur_api_query_versions(aur_rpc_url: &str, package_names: &[String]) -> Result<HashMap<String, String>>
+{
+    let client = reqwest::Client::new();
+    let mut versions = HashMap::new();
+
+    for batch in package_names.chunks(INFO_BATCH_SIZE) {
+        let query: Vec<(&str, &str)> = batch.iter().map(|name| ("arg[]", name.as_str())).collect();
+        let parsed: AurResults = client
+            .get(format!("{}/v5/info", aur_rpc_url.trim_end_matches('/')))
+            .query(&query)
+            .send().await
+            .with_context(|| "Failed to query AUR RPC")?
+            .error_for_status()?
+            .json().await
+            .with_context(|| "Failed to parse AUR RPC response")?;
+        debug!("AUR RPC returned {} results for {} packages", parsed.results.len(), batch.len());
+
+        versions.extend(parsed.results.into_iter().map(|result| (result.name, result.version)));
+    }
+
+    Ok(versions)
+}
+
+pub async fn aur_api_query_provides(aur_rpc_url: &str, package_name: &String, strict: bool) -> Option<AurPackage>
 {
     let sanitized_package_name = sanitize_dependency(package_name);
     let url = format!(
-        "https://aur.archlinux.org/rpc/v5/search/{}?by=provides",
+        "{}/v5/search/{}?by=provides",
+        aur_rpc_url.trim_end_matches('/'),
         sanitized_package_name
     );
 
@@ -225,6 +307,8 @@ mod tests {
         let graph = build_dependency_graph(
             &bubblewrap,
             &PathBuf::from("./test/sandbox"),
+            "https://aur.archlinux.org/rpc",
+            None,
             AurPackage {
                 package_name: "bottles".to_string(),
                 package_base: "bottles".to_string(),
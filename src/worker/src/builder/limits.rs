@@ -0,0 +1,163 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::{debug, info};
+use tokio::fs::{create_dir_all, read_to_string, remove_dir, write};
+use common::models::ResourceLimits;
+use crate::utils::get_path_size;
+
+/// Child cgroup the worker moves itself into when it runs in the delegated cgroup,
+/// as cgroup v2 only allows processes in leaf cgroups once controllers are enabled.
+const WORKER_CGROUP: &str = "worker";
+/// Child cgroup of the sandbox commands of the current build.
+const BUILD_CGROUP: &str = "build";
+const CPU_PERIOD: u64 = 100000;
+const DISK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// cgroup v2 of a build enforcing its CPU, memory and pids limits.
+pub struct BuildCgroup {
+    path: PathBuf,
+}
+
+impl BuildCgroup {
+    /// Creates the build cgroup under the given delegated cgroup, killing the processes left by a previous build.
+    pub async fn create(base: &Path, limits: &ResourceLimits) -> Result<BuildCgroup>
+    {
+        let base = base.canonicalize()
+            .with_context(|| format!("Failed to find cgroup {:?}", base))?;
+
+        if get_own_cgroup().await? == base {
+            info!("Moving worker into cgroup {:?}", base.join(WORKER_CGROUP));
+            create_dir_all(base.join(WORKER_CGROUP)).await?;
+            write(base.join(WORKER_CGROUP).join("cgroup.procs"), std::process::id().to_string()).await
+                .with_context(|| "Failed to move worker out of the delegated cgroup")?;
+        }
+
+        write(base.join("cgroup.subtree_control"), "+cpu +memory +pids").await
+            .with_context(|| format!("Failed to enable cgroup controllers in {:?}, is it delegated to the worker ?", base))?;
+
+        let path = base.join(BUILD_CGROUP);
+        if path.exists() {
+            debug!("Removing previous build cgroup {:?}", path);
+            let _ = write(path.join("cgroup.kill"), "1").await;
+            remove_dir(&path).await
+                .with_context(|| format!("Failed to remove previous build cgroup {:?}", path))?;
+        }
+        create_dir_all(&path).await?;
+
+        if let Some(memory) = limits.memory {
+            write(path.join("memory.max"), (memory * 1024 * 1024).to_string()).await?;
+            // Without swap the build is killed instead of slowing down the whole worker
+            if let Err(e) = write(path.join("memory.swap.max"), "0").await {
+                debug!("Failed to disable swap of build cgroup: {}", e);
+            }
+        }
+        if let Some(cpus) = limits.cpus {
+            write(path.join("cpu.max"), get_cpu_max(cpus)).await?;
+        }
+        if let Some(pids) = limits.pids {
+            write(path.join("pids.max"), pids.to_string()).await?;
+        }
+
+        Ok(BuildCgroup { path })
+    }
+
+    /// File the sandbox commands write their pid to in order to join the cgroup.
+    pub fn procs_path(&self) -> PathBuf
+    {
+        self.path.join("cgroup.procs")
+    }
+
+    /// Describes the limit the processes of the build ran into, if any.
+    pub async fn get_exceeded_limit(&self) -> Option<String>
+    {
+        let oom_kills = read_to_string(self.path.join("memory.events")).await.ok()
+            .and_then(|events| parse_event_count(&events, "oom_kill"))
+            .unwrap_or(0);
+        if oom_kills > 0 {
+            return Some(format!("Build exceeded its memory limit, {} process(es) killed", oom_kills));
+        }
+
+        let pids = read_to_string(self.path.join("pids.events")).await.ok()
+            .and_then(|events| parse_event_count(&events, "max"))
+            .unwrap_or(0);
+        if pids > 0 {
+            return Some(format!("Build exceeded its pids limit, {} fork(s) rejected", pids));
+        }
+
+        None
+    }
+}
+
+/// Path of the cgroup of the worker, read from `/proc/self/cgroup`.
+async fn get_own_cgroup() -> Result<PathBuf>
+{
+    let content = read_to_string("/proc/self/cgroup").await?;
+    match content.lines().find_map(|line| line.strip_prefix("0::")) {
+        Some(cgroup) => Ok(PathBuf::from("/sys/fs/cgroup").join(cgroup.trim_start_matches('/'))),
+        None => bail!("Worker is not running in a cgroup v2 hierarchy"),
+    }
+}
+
+/// Value of `cpu.max` allowing the given amount of CPUs.
+fn get_cpu_max(cpus: f64) -> String
+{
+    format!("{} {}", (cpus * CPU_PERIOD as f64).round() as u64, CPU_PERIOD)
+}
+
+/// Counter of the given key in a cgroup events file such as `memory.events`.
+fn parse_event_count(events: &str, key: &str) -> Option<u64>
+{
+    events.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(name, _)| *name == key)
+        .and_then(|(_, count)| count.trim().parse().ok())
+}
+
+/// Combined size in bytes of the given paths, the missing ones counting as empty.
+pub async fn get_disk_usage(paths: &[PathBuf]) -> Result<u64>
+{
+    let mut size = 0;
+    for path in paths.iter().filter(|path| path.exists()) {
+        size += get_path_size(path.clone()).await
+            .with_context(|| format!("Failed to get disk usage of {:?}", path))?;
+    }
+    Ok(size)
+}
+
+/// Resolves once the given paths grow by more than the disk limit in GiB from their initial size, never without a limit.
+pub async fn watch_disk_usage(paths: &[PathBuf], initial_size: u64, disk: Option<u64>) -> String
+{
+    let Some(disk) = disk else {
+        return std::future::pending().await;
+    };
+    let max_size = initial_size + disk * 1024 * 1024 * 1024;
+
+    loop {
+        tokio::time::sleep(DISK_POLL_INTERVAL).await;
+        match get_disk_usage(paths).await {
+            Ok(size) if size > max_size => {
+                return format!("Build exceeded its disk limit of {} GiB", disk);
+            }
+            Ok(_) => {}
+            // Files are created and removed while the build runs
+            Err(e) => debug!("{:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::limits::{get_cpu_max, parse_event_count};
+
+    #[test]
+    fn test_cgroup_values() {
+        assert_eq!("150000 100000", get_cpu_max(1.5));
+        assert_eq!("400000 100000", get_cpu_max(4.0));
+
+        let events = "low 0\nhigh 12\nmax 3\noom 1\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(Some(1), parse_event_count(events, "oom_kill"));
+        assert_eq!(Some(3), parse_event_count(events, "max"));
+        assert_eq!(None, parse_event_count(events, "missing"));
+    }
+}
@@ -1,32 +1,52 @@
 use anyhow::{anyhow, bail, Context, Result};
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use log::{error, info, warn};
 use petgraph::Direction;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Sender, UnboundedSender};
 
-use common::models::{PackageJob, WorkerStatus};
+use common::models::{BuildErrorClass, CompilerCacheStats, PackageJob, PackageSource, ResourceLimits, WorkerStatus};
 
 use crate::builder::bubblewrap::Bubblewrap;
+use crate::builder::build_env::{get_sandbox_options, write_makepkg_config};
+use crate::builder::compiler_cache::{CompilerCache, COMPILER_CACHE_PACKAGES};
+use crate::builder::limits::{get_disk_usage, watch_disk_usage, BuildCgroup};
 use crate::builder::dependency::{aur_api_query_provides, AurPackage, build_dependency_graph, DependencyGraph};
+use crate::builder::pacman_cache::PacmanCache;
+use crate::builder::source::fetch_package_source;
+use crate::builder::source_cache::SourceCache;
 use crate::builder::utils::post_build_clean;
 use crate::commands::git::{apply_patches, clone_repo};
 use crate::commands::gpg::attempt_recv_pgp_keys;
-use crate::commands::makepkg::{get_package_version, run_makepkg};
-use crate::commands::pacman::{pacman_update};
+use crate::commands::makepkg::{download_sources, get_package_dependencies, get_package_version, read_src_info, run_makepkg};
+use crate::commands::pacman::{get_server_repo_config, import_server_repo_key, pacman_update, record_cache_usage};
 use crate::logs::{init_builder_logs};
 use crate::logs::LogSection::RunBefore;
+use crate::models::build_error::Classify;
 use crate::models::config::Config;
 use crate::models::package_build_result::PackageBuildResult;
 use crate::orchestrator::http::HttpClient;
 use crate::utils::{copy_dir_all, get_package_dir_entries};
 
 pub mod bubblewrap;
+mod build_env;
+mod compiler_cache;
 mod dependency;
-mod utils;
+mod limits;
+pub mod pacman_cache;
+mod source;
+pub mod source_cache;
+mod sync_db;
+pub mod utils;
 
 pub struct Builder {
     bubblewrap: Bubblewrap,
+    pacman_cache: PacmanCache,
+    source_cache: SourceCache,
+    compiler_cache: CompilerCache,
+    /// Compiler cache statistics of the packages built for the job so far.
+    compiler_cache_stats: Mutex<Option<CompilerCacheStats>>,
     package_job: PackageJob,
 
     tx_status: Sender<WorkerStatus>,
@@ -36,9 +56,19 @@ pub struct Builder {
 }
 
 impl Builder {
-    pub fn new(tx_status: Sender<WorkerStatus>, http_client: HttpClient, package_job: PackageJob, config: &Config) -> Builder {
+    pub fn new(
+        tx_status: Sender<WorkerStatus>,
+        tx_logs: UnboundedSender<String>,
+        http_client: HttpClient,
+        package_job: PackageJob,
+        config: &Config
+    ) -> Builder {
         Builder {
-            bubblewrap: Bubblewrap::from_config(&config),
+            bubblewrap: Bubblewrap::from_config(&config).with_log_sender(tx_logs),
+            pacman_cache: PacmanCache::from_config(config),
+            source_cache: SourceCache::from_config(config),
+            compiler_cache: CompilerCache::from_config(config),
+            compiler_cache_stats: Mutex::new(None),
             package_job,
 
             tx_status,
@@ -50,10 +80,17 @@ impl Builder {
 
     async fn fetch_package(&self) -> Result<AurPackage>
     {
-        let parent_package = aur_api_query_provides(&self.package_job.definition.name, true).await
-            .ok_or(anyhow!("Failed to get {} package by provide", &self.package_job.definition.name))?;
-        let repository = clone_repo(&self.config.data_path, &self.package_job.definition.name)?;
-        apply_patches(&self.package_job, repository).await?;
+        if self.package_job.definition.source != PackageSource::Aur {
+            return fetch_package_source(&self.bubblewrap, &self.config.data_path, &self.package_job, &self.http_client).await
+                .classify(BuildErrorClass::Source);
+        }
+
+        let parent_package = aur_api_query_provides(&self.config.aur_rpc_url, &self.package_job.definition.name, true).await
+            .ok_or(anyhow!("Failed to get {} package by provide", &self.package_job.definition.name))
+            .classify(BuildErrorClass::AurRpc)?;
+        let repository = clone_repo(&self.config.data_path, &self.package_job.definition.name)
+            .classify(BuildErrorClass::Source)?;
+        apply_patches(&self.package_job, repository).await.classify(BuildErrorClass::Source)?;
         Ok(parent_package)
     }
 
@@ -106,15 +143,32 @@ impl Builder {
 
         let root = self.init_build_chroot().await.with_context(|| "Failed to init build chroot")?;
 
+        let build_env = self.config.build_env.merge(&self.package_job.definition.build_env);
+        write_makepkg_config(&root, &build_env).await
+            .with_context(|| "Failed to write makepkg configuration")?;
+        let mut options = get_sandbox_options(&build_env);
+
+        let compiler_cache = self.package_job.definition.compiler_cache;
+        if compiler_cache {
+            info!("Enabling compiler cache");
+            CompilerCache::write_makepkg_config(&root).await
+                .with_context(|| "Failed to write compiler cache makepkg configuration")?;
+            options.extend(
+                self.compiler_cache.get_sandbox_options(&package.package_base).await
+                    .with_context(|| "Failed to create compiler cache")?
+            );
+        }
+        let bubblewrap = self.bubblewrap.clone().with_options(options);
+
         info!("Copying package into chroot");
         copy_dir_all(self.config.data_path.join(&package.package_base), root.join("package")).await
             .with_context(|| "Failed to copy package into chroot")?;
 
-        attempt_recv_pgp_keys(&self.bubblewrap, &self.config.data_path, &package.package_base).await;
+        attempt_recv_pgp_keys(&bubblewrap, &self.config.data_path, &package.package_base).await;
 
         if let Some(run_before) = self.package_job.definition.run_before.as_ref() {
             info!("Running run_before command '{}'", run_before);
-            let output = self.bubblewrap.run_sandbox(
+            let output = bubblewrap.run_sandbox(
                 true,
                 "current",
                 "/",
@@ -129,24 +183,94 @@ impl Builder {
             }
         }
 
-        if !package.repo_deps.is_empty() {
+        let mut repo_deps = package.repo_deps.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        if compiler_cache {
+            repo_deps.extend(COMPILER_CACHE_PACKAGES);
+        }
+        if !repo_deps.is_empty() {
             let mut pacman_args = vec!["-S", "--noconfirm", "--ask", "20"];
-            pacman_args.append(&mut package.repo_deps.iter().map(|x| x.as_str()).collect());
+            pacman_args.append(&mut repo_deps);
 
             info!("Installing dependencies from repo {:?}", pacman_args);
+            record_cache_usage(&bubblewrap, &self.pacman_cache, "current", pacman_args.clone()).await;
+
+            bubblewrap.run_sandbox(true, "current", "/", "pacman", pacman_args, None, None).await
+                .classify(BuildErrorClass::Network)?;
+        }
 
-            self.bubblewrap.run_sandbox(true, "current", "/", "pacman", pacman_args, None, None).await?;
+        if compiler_cache {
+            if let Err(e) = CompilerCache::reset_stats(&bubblewrap).await {
+                warn!("Failed to reset compiler cache statistics: {}", e);
+            }
         }
 
-        let output = run_makepkg(
-            &self.bubblewrap,
-            &package.package_base,
-            &log_path
-        ).await
-            .with_context(|| format!("Error while running makepkg for {}", &package.package_base))?;
+        let limits = self.config.limits.merge(&self.package_job.definition.limits);
+        let cgroup = self.create_build_cgroup(&limits).await?;
+        let mut makepkg_bubblewrap = match &cgroup {
+            Some(cgroup) => bubblewrap.clone().with_cgroup(cgroup.procs_path()),
+            None => bubblewrap.clone(),
+        };
+
+        let isolate_network = self.config.isolate_network && !self.package_job.definition.allow_network;
+        // The whole sandbox and the shared sources cache count against the disk limit,
+        // by how much they grow during the build as they already hold the base system and other sources
+        let disk_paths = vec![root.clone(), self.config.source_cache_path.clone()];
+        let initial_disk_usage = match limits.disk {
+            Some(_) => get_disk_usage(&disk_paths).await?,
+            None => 0,
+        };
+
+        if isolate_network {
+            let output = tokio::select! {
+                output = download_sources(&makepkg_bubblewrap, &package.package_base, &log_path) => output
+                    .with_context(|| format!("Error while downloading sources for {}", &package.package_base))?,
+                exceeded = watch_disk_usage(&disk_paths, initial_disk_usage, limits.disk) => {
+                    return Err(anyhow!(exceeded)).classify(BuildErrorClass::ResourceLimit);
+                }
+            };
+            if !output.status.success() {
+                return Err(anyhow!("Failed to download sources for {}", package.package_base)).classify(BuildErrorClass::Network);
+            }
+            info!("Running build without network access");
+            makepkg_bubblewrap = makepkg_bubblewrap.with_unshared_network();
+        }
+
+        let output = tokio::select! {
+            output = run_makepkg(&makepkg_bubblewrap, &package.package_base, &log_path, isolate_network) => output
+                .with_context(|| format!("Error while running makepkg for {}", &package.package_base))?,
+            exceeded = watch_disk_usage(&disk_paths, initial_disk_usage, limits.disk) => {
+                return Err(anyhow!(exceeded)).classify(BuildErrorClass::ResourceLimit);
+            }
+        };
+
+        match read_src_info(&self.bubblewrap, &self.config.data_path.join(&package.package_base)).await {
+            Ok(src_info) => self.source_cache.touch_sources(&src_info),
+            Err(e) => warn!("Could not read sources of {}: {}", package.package_base, e),
+        }
+
+        if compiler_cache {
+            match CompilerCache::get_stats(&bubblewrap).await {
+                Ok(stats) => {
+                    let mut total = self.compiler_cache_stats.lock().unwrap();
+                    let total = total.get_or_insert_with(CompilerCacheStats::default);
+                    total.hits += stats.hits;
+                    total.misses += stats.misses;
+                }
+                Err(e) => warn!("Failed to get compiler cache statistics: {}", e),
+            }
+        }
 
         if !output.status.success() {
-            bail!("Failed to run makepkg for {}", package.package_base);
+            let exceeded = match &cgroup {
+                Some(cgroup) => cgroup.get_exceeded_limit().await,
+                None => None,
+            };
+            if let Some(exceeded) = exceeded {
+                return Err(anyhow!(exceeded))
+                    .with_context(|| format!("Failed to run makepkg for {}", package.package_base))
+                    .classify(BuildErrorClass::ResourceLimit);
+            }
+            return Err(anyhow!("Failed to run makepkg for {}", package.package_base)).classify(BuildErrorClass::Build);
         }
 
         self.bubblewrap.copy_built_packages(self.config.data_path.join("_built")).await
@@ -155,6 +279,24 @@ impl Builder {
         Ok(())
     }
 
+    /// Creates the cgroup enforcing the CPU, memory and pids limits of the build, if there are any.
+    /// Fails the build when they cannot be enforced rather than running it unconstrained.
+    async fn create_build_cgroup(&self, limits: &ResourceLimits) -> Result<Option<BuildCgroup>>
+    {
+        if limits.cpus.is_none() && limits.memory.is_none() && limits.pids.is_none() {
+            return Ok(None);
+        }
+        let Some(cgroup_path) = self.config.cgroup_path.as_ref() else {
+            return Err(anyhow!("No cgroup_path configured to enforce CPU, memory and pids limits {:?}", limits))
+                .classify(BuildErrorClass::ResourceLimit);
+        };
+
+        info!("Limiting build resources to {:?}", limits);
+        let cgroup = BuildCgroup::create(cgroup_path, limits).await
+            .with_context(|| "Failed to create build cgroup")?;
+        Ok(Some(cgroup))
+    }
+
     async fn stage_init(&self) -> Result<AurPackage> {
         post_build_clean(&self.config.data_path).await.with_context(|| "Failed initial clean")?;
 
@@ -185,7 +327,8 @@ impl Builder {
     async fn stage_build(&self, aur_package: AurPackage) -> Result<()>
     {
         info!("Building dependency graph");
-        let mut dep_graph = build_dependency_graph(&self.bubblewrap, &self.config.data_path, aur_package).await
+        let server_repository = self.package_job.repo_name.as_deref().filter(|_| self.config.use_server_repo);
+        let mut dep_graph = build_dependency_graph(&self.bubblewrap, &self.config.data_path, &self.config.aur_rpc_url, server_repository, aur_package).await
             .with_context(|| "Failed to build dependency graph")?;
 
         info!("Handling dependencies");
@@ -201,22 +344,54 @@ impl Builder {
         let aur_package = self.stage_init().await?;
 
         info!("Checking package version");
-        let version = get_package_version(&self.config.data_path, &aur_package.package_base).await?;
+        let version = get_package_version(&self.bubblewrap, &self.config.data_path, &aur_package.package_base).await?;
+        let dependencies = get_package_dependencies(&self.bubblewrap, &self.config.data_path, &aur_package.package_base).await?;
         if let Some(last_built_version) = &self.package_job.last_built_version {
             if last_built_version == &version {
                 info!("Found same version for package, skipping build ...");
-                return Ok(PackageBuildResult::new(false, version));
+                return Ok(PackageBuildResult::new(false, aur_package.package_base, version, dependencies));
             }
         }
 
         self.tx_status.send(WorkerStatus::UPDATING).await.unwrap();
+        self.configure_server_repo().await
+            .with_context(|| "Failed to configure server repository")?;
         info!("Updating base chroot");
-        pacman_update(&self.bubblewrap).await?;
+        pacman_update(&self.bubblewrap, &self.pacman_cache).await.classify(BuildErrorClass::Network)?;
 
         self.tx_status.send(WorkerStatus::WORKING).await.unwrap();
+        let package_base = aur_package.package_base.clone();
         self.stage_build(aur_package).await?;
 
-        Ok(PackageBuildResult::new(true, version))
+        Ok(PackageBuildResult::new(true, package_base, version, dependencies))
+    }
+
+    /// Trusts the key the server signs its repository with, returns whether it has one.
+    async fn import_server_repo_key(&self) -> Result<bool>
+    {
+        let Some(key) = self.http_client.get_repository_key().await? else {
+            return Ok(false);
+        };
+        import_server_repo_key(&self.bubblewrap, &key).await?;
+        Ok(true)
+    }
+
+    /// Adds the repository of the server to the base sandbox, so the dependencies it already serves are installed instead of built.
+    async fn configure_server_repo(&self) -> Result<()>
+    {
+        let mut sections = String::new();
+        if let Some(repo_name) = self.package_job.repo_name.as_ref().filter(|_| self.config.use_server_repo) {
+            if let Some(url) = self.http_client.get_repository_url(repo_name).await {
+                match self.import_server_repo_key().await {
+                    Ok(signed) => {
+                        info!("Using server repository {} at {}, signed: {}", repo_name, url, signed);
+                        sections = get_server_repo_config(repo_name, &url, signed);
+                    }
+                    Err(e) => warn!("Not using server repository {}, could not import its key: {:#}", repo_name, e),
+                }
+            }
+        }
+        self.bubblewrap.write_pacman_config(&sections).await
     }
 
     pub async fn process_package(&self) -> Result<()>
@@ -235,6 +410,12 @@ impl Builder {
 
         self.tx_status.send(WorkerStatus::CLEANING).await?;
         post_build_clean(&self.config.data_path).await?;
+        if let Err(e) = self.pacman_cache.prune().await {
+            warn!("Failed to prune pacman cache: {}", e);
+        }
+        if let Err(e) = self.source_cache.prune().await {
+            warn!("Failed to prune source cache: {}", e);
+        }
         Ok(())
     }
 
@@ -244,7 +425,8 @@ impl Builder {
     ) -> Result<()>
     {
         info!("Sending job result to orchestrator");
-        self.http_client.upload_packages(&self.package_job.definition.name, build_result).await?;
+        let compiler_cache = *self.compiler_cache_stats.lock().unwrap();
+        self.http_client.upload_packages(&self.package_job.definition.name, build_result, compiler_cache).await?;
         Ok(())
     }
 }
@@ -252,12 +434,10 @@ impl Builder {
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-
     use log::LevelFilter;
     use serial_test::serial;
     use simplelog::{ColorChoice, TerminalMode, TermLogger};
-    use tokio::sync::mpsc::channel;
+    use tokio::sync::mpsc::{channel, unbounded_channel};
     use anyhow::Result;
 
     use common::models::{PackageDefinition, PackageJob};
@@ -270,19 +450,7 @@ mod tests {
     use crate::orchestrator::http::HttpClient;
 
     async fn build_package(package_job: PackageJob) -> Result<PackageBuildResult> {
-        let config: Config = Config {
-            log_level: LevelFilter::Debug,
-            log_path: PathBuf::from("./test/worker.log"),
-            pacman_config_path: PathBuf::from("../../config/pacman.conf"),
-            pacman_mirrorlist_path: PathBuf::from("/etc/pacman.d/mirrorlist"),
-            force_base_sandbox_create: false,
-            data_path: PathBuf::from("./test/data"),
-            sandbox_path: PathBuf::from("./test/sandbox"),
-            build_logs_path: PathBuf::from("./test/build_logs"),
-            base_url: "".to_string(),
-            base_url_ws: "".to_string(),
-            api_key: "".to_string(),
-        };
+        let config = Config::for_tests();
 
         TermLogger::init(LevelFilter::Debug, simplelog::Config::default(), TerminalMode::Mixed, ColorChoice::Auto).unwrap();
 
@@ -291,8 +459,10 @@ mod tests {
         bubblewrap.create(false).await.unwrap();
 
         let (tx, _rx) = channel(1000);
+        let (tx_logs, _rx_logs) = unbounded_channel();
         let builder = Builder::new(
             tx,
+            tx_logs,
             HttpClient::from_config(&config),
             package_job,
             &config
@@ -310,10 +480,9 @@ mod tests {
             definition: PackageDefinition {
                 package_id: 1,
                 name: "coppwr".to_string(),
-                run_before: None,
-                patches: vec![],
+                ..Default::default()
             },
-            last_built_version: None,
+            ..Default::default()
         };
 
         let result = build_package(job).await.unwrap();
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use log::{debug, info};
+use tokio::fs::{create_dir_all, read_dir, remove_file};
+use crate::models::config::Config;
+
+/// Packages found in the cache since the worker started.
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+/// Packages downloaded since the worker started.
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Host directory holding the packages downloaded by pacman, shared by every sandbox across builds.
+pub struct PacmanCache {
+    path: PathBuf,
+    max_size: u64,
+}
+
+struct CacheFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl PacmanCache {
+    pub fn from_config(config: &Config) -> PacmanCache
+    {
+        PacmanCache::new(config.pacman_cache_path.clone(), config.pacman_cache_max_size * 1024 * 1024 * 1024)
+    }
+
+    /// Creates a cache at the given path, pruned down to `max_size` bytes. A `max_size` of 0 disables pruning.
+    pub fn new(path: PathBuf, max_size: u64) -> PacmanCache
+    {
+        PacmanCache {
+            path,
+            max_size,
+        }
+    }
+
+    pub async fn init(&self) -> Result<()>
+    {
+        create_dir_all(&self.path).await?;
+        Ok(())
+    }
+
+    async fn get_files(&self) -> Result<Vec<CacheFile>>
+    {
+        let mut files = Vec::new();
+        let mut dir = read_dir(&self.path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                files.push(CacheFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    /// Logs how many of the package files about to be installed by pacman are already in the cache.
+    /// Returns the number of hits and misses.
+    pub fn record_usage(&self, files: &[String]) -> (u64, u64)
+    {
+        let hits = files.iter().filter(|f| self.path.join(f).is_file()).count() as u64;
+        let misses = files.len() as u64 - hits;
+
+        let total_hits = CACHE_HITS.fetch_add(hits, Ordering::Relaxed) + hits;
+        let total_misses = CACHE_MISSES.fetch_add(misses, Ordering::Relaxed) + misses;
+        if !files.is_empty() {
+            info!(
+                "Pacman cache: {} hits, {} misses, {} hits and {} misses since startup ({:.1}% hit rate)",
+                hits,
+                misses,
+                total_hits,
+                total_misses,
+                total_hits as f64 * 100.0 / (total_hits + total_misses) as f64
+            );
+        }
+
+        (hits, misses)
+    }
+
+    /// Deletes the oldest downloaded files until the cache fits in its maximum size. Returns the size of the cache.
+    pub async fn prune(&self) -> Result<u64>
+    {
+        let mut files = self.get_files().await?;
+        let mut size: u64 = files.iter().map(|f| f.size).sum();
+        if self.max_size == 0 || size <= self.max_size {
+            debug!("Pacman cache size {} MiB", size / 1024 / 1024);
+            return Ok(size);
+        }
+
+        files.sort_by_key(|f| f.modified);
+        let mut removed = 0;
+        for file in files {
+            if size <= self.max_size {
+                break;
+            }
+            remove_file(&file.path).await?;
+            size -= file.size;
+            removed += 1;
+        }
+        info!("Pruned {} files from pacman cache, size {} MiB", removed, size / 1024 / 1024);
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+    use tokio::fs::{remove_dir_all, write, File};
+    use crate::builder::pacman_cache::PacmanCache;
+
+    #[tokio::test]
+    async fn prune_oldest_files() {
+        let path = std::env::temp_dir().join("aur-build-worker-pacman-cache-test");
+        let _ = remove_dir_all(&path).await;
+        let cache = PacmanCache::new(path.clone(), 250);
+        cache.init().await.unwrap();
+
+        let now = SystemTime::now();
+        for (i, name) in ["old.pkg.tar.zst", "mid.pkg.tar.zst", "new.pkg.tar.zst"].iter().enumerate() {
+            write(path.join(name), vec![0; 100]).await.unwrap();
+            File::options().write(true).open(path.join(name)).await.unwrap()
+                .into_std().await
+                .set_modified(now - Duration::from_secs(100 - i as u64 * 10)).unwrap();
+        }
+
+        assert_eq!((2, 1), cache.record_usage(&[
+            "mid.pkg.tar.zst".to_string(),
+            "new.pkg.tar.zst".to_string(),
+            "missing.pkg.tar.zst".to_string(),
+        ]));
+
+        assert_eq!(200, cache.prune().await.unwrap());
+        assert!(!path.join("old.pkg.tar.zst").exists());
+        assert!(path.join("mid.pkg.tar.zst").exists());
+        assert!(path.join("new.pkg.tar.zst").exists());
+
+        remove_dir_all(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Component, Path, PathBuf};
+use log::{info, warn};
+use tokio::fs::{create_dir_all, remove_dir_all};
+use common::models::{BuildErrorClass, PackageJob, PackageSource};
+use crate::builder::bubblewrap::Bubblewrap;
+use crate::builder::dependency::AurPackage;
+use crate::commands::git::{apply_patches, clone_git_source, init_repo};
+use crate::commands::makepkg::read_src_info;
+use crate::models::build_error::Classify;
+use crate::orchestrator::http::HttpClient;
+use crate::utils::copy_dir;
+
+/// Fetches a package from a source other than AUR.
+/// The source is fetched into `data_path/_source/{name}`, patched and then copied into `data_path/{pkgbase}`
+/// so the rest of the build can treat it like an AUR package.
+pub async fn fetch_package_source(bubblewrap: &Bubblewrap, data_path: &Path, package_job: &PackageJob, http_client: &HttpClient) -> Result<AurPackage>
+{
+    let definition = &package_job.definition;
+    let source_path = data_path.join("_source").join(&definition.name);
+    if source_path.exists() {
+        remove_dir_all(&source_path).await?;
+    }
+    create_dir_all(data_path.join("_source")).await?;
+
+    let package_path = match &definition.source {
+        PackageSource::Aur => bail!("{} is an AUR package", definition.name),
+        PackageSource::Git { url, reference, subdirectory } => {
+            info!("Cloning {} from {}", definition.name, url);
+            let repository = clone_git_source(&source_path, url, reference.as_ref())?;
+            apply_patches(package_job, repository).await?;
+
+            match subdirectory {
+                Some(subdirectory) => get_subdirectory_path(&source_path, subdirectory)
+                    .with_context(|| format!("Invalid subdirectory {} for {}", subdirectory, definition.name))?,
+                None => source_path.clone(),
+            }
+        }
+        PackageSource::Local { path } => {
+            info!("Copying {} from {}", definition.name, path);
+            copy_dir(PathBuf::from(path), source_path.clone()).await
+                .with_context(|| format!("Failed to copy local source {}", path))?;
+            let repository = init_repo(&source_path)?;
+            apply_patches(package_job, repository).await?;
+
+            source_path.clone()
+        }
+        PackageSource::Upload { file } => {
+            info!("Downloading uploaded source {} for {}", file, definition.name);
+            http_client.download_source(file, &source_path).await
+                .with_context(|| format!("Failed to download uploaded source {}", file))
+                .classify(BuildErrorClass::Network)?;
+            let repository = init_repo(&source_path)?;
+            apply_patches(package_job, repository).await?;
+
+            source_path.clone()
+        }
+    };
+
+    let src_info = read_src_info(bubblewrap, &package_path).await
+        .with_context(|| format!("Failed to read package metadata for {}", definition.name))?;
+    let package_base = src_info.base.pkgbase.clone();
+    if !src_info.pkgs.iter().any(|p| p.pkgname == definition.name) {
+        warn!("{} is not one of the packages declared by {}", definition.name, package_base);
+    }
+
+    let destination = data_path.join(&package_base);
+    if destination.exists() {
+        remove_dir_all(&destination).await?;
+    }
+    copy_dir(package_path, destination).await
+        .with_context(|| format!("Failed to copy {} into data directory", package_base))?;
+
+    Ok(AurPackage {
+        package_name: definition.name.clone(),
+        package_base,
+        repo_deps: Vec::new(),
+    })
+}
+
+/// Resolves a subdirectory of a cloned repository, making sure neither its path nor a symlink leads out of the repository.
+fn get_subdirectory_path(source_path: &Path, subdirectory: &str) -> Result<PathBuf>
+{
+    if Path::new(subdirectory).components().any(|c| !matches!(c, Component::Normal(_))) {
+        bail!("Only relative paths inside the repository are allowed");
+    }
+    let path = source_path.join(subdirectory).canonicalize()
+        .with_context(|| format!("Failed to resolve {:?}", source_path.join(subdirectory)))?;
+    if !path.starts_with(source_path.canonicalize()?) {
+        bail!("{:?} is outside of the repository", path);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use tokio::fs::{create_dir_all, remove_dir_all, write};
+    use common::models::{PackageDefinition, PackageJob, PackageSource};
+    use crate::builder::source::{fetch_package_source, get_subdirectory_path};
+    use crate::builder::bubblewrap::Bubblewrap;
+    use crate::models::config::Config;
+    use crate::orchestrator::http::HttpClient;
+
+    fn get_http_client() -> HttpClient {
+        HttpClient::from_config(&Config::for_tests())
+    }
+
+    #[tokio::test]
+    async fn fetch_local_source() {
+        let local_path = PathBuf::from("test/local-source");
+        let data_path = PathBuf::from("test/local-source-data");
+        create_dir_all(&local_path).await.unwrap();
+        write(local_path.join("PKGBUILD"), "pkgname=local-test\n").await.unwrap();
+        write(local_path.join(".SRCINFO"), "pkgbase = local-base\n\tpkgver = 1.0.0\n\tpkgrel = 1\n\tarch = any\n\npkgname = local-test\n").await.unwrap();
+
+        let job = PackageJob {
+            definition: PackageDefinition {
+                package_id: 1,
+                name: "local-test".to_string(),
+                source: PackageSource::Local { path: local_path.to_str().unwrap().to_string() },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let bubblewrap = Bubblewrap::new(PathBuf::from("./test/sandbox"), PathBuf::new(), PathBuf::new());
+        let package = fetch_package_source(&bubblewrap, &data_path, &job, &get_http_client()).await.unwrap();
+        assert_eq!("local-test", package.package_name);
+        assert_eq!("local-base", package.package_base);
+        assert!(data_path.join("local-base/PKGBUILD").exists());
+        assert!(data_path.join("local-base/.SRCINFO").exists());
+
+        remove_dir_all(local_path).await.unwrap();
+        remove_dir_all(data_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resolve_subdirectory() {
+        let source_path = PathBuf::from("test/subdirectory-source");
+        create_dir_all(source_path.join("pkg")).await.unwrap();
+        std::os::unix::fs::symlink("/etc", source_path.join("link")).unwrap();
+
+        let expected = source_path.join("pkg").canonicalize().unwrap();
+        assert_eq!(expected, get_subdirectory_path(&source_path, "pkg").unwrap());
+        for subdirectory in ["/etc", "../other", "pkg/../..", "link", "missing"] {
+            assert!(get_subdirectory_path(&source_path, subdirectory).is_err(), "{}", subdirectory);
+        }
+
+        remove_dir_all(source_path).await.unwrap();
+    }
+}
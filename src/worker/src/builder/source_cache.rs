@@ -0,0 +1,182 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use log::{debug, info, warn};
+use srcinfo::Srcinfo;
+use tokio::fs::{create_dir_all, read_dir, remove_dir_all, remove_file};
+use crate::models::config::Config;
+use crate::utils::get_path_size;
+
+/// Host directory used as `SRCDEST` by makepkg, keeping the downloaded sources across builds.
+/// VCS sources are kept by makepkg as bare mirrors and only fetched incrementally.
+pub struct SourceCache {
+    path: PathBuf,
+    max_size: u64,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    is_dir: bool,
+    used: SystemTime,
+}
+
+/// Name under which makepkg stores the given source in `SRCDEST`, None for local sources.
+pub fn get_source_file_name(source: &str) -> Option<String>
+{
+    let (name, url) = match source.split_once("::") {
+        Some((name, url)) => (Some(name), url),
+        None => (None, source),
+    };
+    let (protocol, _) = url.split_once("://")?;
+
+    if let Some(name) = name {
+        return Some(name.to_string());
+    }
+
+    let mut file_name = url.trim_end_matches('/').rsplit('/').next()?;
+    let vcs = protocol.split('+').next().unwrap_or(protocol);
+    if ["bzr", "fossil", "git", "hg", "svn"].contains(&vcs) {
+        file_name = file_name.split('#').next().unwrap_or(file_name);
+        file_name = file_name.split('?').next().unwrap_or(file_name);
+        if vcs == "git" {
+            file_name = file_name.split(".git").next().unwrap_or(file_name);
+        }
+    }
+
+    Some(file_name.to_string())
+}
+
+impl SourceCache {
+    pub fn from_config(config: &Config) -> SourceCache
+    {
+        SourceCache::new(config.source_cache_path.clone(), config.source_cache_max_size * 1024 * 1024 * 1024)
+    }
+
+    /// Creates a cache at the given path, evicting the least recently used sources above `max_size` bytes.
+    /// A `max_size` of 0 disables eviction.
+    pub fn new(path: PathBuf, max_size: u64) -> SourceCache
+    {
+        SourceCache {
+            path,
+            max_size,
+        }
+    }
+
+    pub async fn init(&self) -> Result<()>
+    {
+        create_dir_all(&self.path).await?;
+        Ok(())
+    }
+
+    /// Marks the cached sources of the package as used, makepkg does not touch the sources it finds in the cache.
+    pub fn touch_sources(&self, src_info: &Srcinfo)
+    {
+        let now = SystemTime::now();
+        for source in src_info.base.source.iter().flat_map(|s| s.vec.iter()) {
+            let Some(file_name) = get_source_file_name(source) else {
+                continue;
+            };
+            let path = self.path.join(file_name);
+            if let Err(e) = std::fs::File::open(&path).and_then(|f| f.set_modified(now)) {
+                debug!("Could not mark source {:?} as used: {}", path, e);
+            }
+        }
+    }
+
+    async fn get_entries(&self) -> Result<Vec<CacheEntry>>
+    {
+        let mut entries = Vec::new();
+        let mut dir = read_dir(&self.path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            entries.push(CacheEntry {
+                path: entry.path(),
+                size: get_path_size(entry.path()).await?,
+                is_dir: metadata.is_dir(),
+                used: metadata.modified()?,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Evicts the least recently used sources until the cache fits in its maximum size. Returns the size of the cache.
+    pub async fn prune(&self) -> Result<u64>
+    {
+        let mut entries = self.get_entries().await?;
+        let mut size: u64 = entries.iter().map(|e| e.size).sum();
+        if self.max_size == 0 || size <= self.max_size {
+            debug!("Source cache size {} MiB", size / 1024 / 1024);
+            return Ok(size);
+        }
+
+        entries.sort_by_key(|e| e.used);
+        let mut removed = 0;
+        for entry in entries {
+            if size <= self.max_size {
+                break;
+            }
+            let res = if entry.is_dir {
+                remove_dir_all(&entry.path).await
+            } else {
+                remove_file(&entry.path).await
+            };
+            if let Err(e) = res {
+                warn!("Failed to evict source {:?}: {}", entry.path, e);
+                continue;
+            }
+            size -= entry.size;
+            removed += 1;
+        }
+        info!("Evicted {} sources from source cache, size {} MiB", removed, size / 1024 / 1024);
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+    use srcinfo::Srcinfo;
+    use tokio::fs::{create_dir_all, remove_dir_all, write};
+    use crate::builder::source_cache::{get_source_file_name, SourceCache};
+
+    #[test]
+    fn test_get_source_file_name() {
+        assert_eq!(Some("foo-1.0.tar.gz".to_string()), get_source_file_name("https://example.com/foo-1.0.tar.gz"));
+        assert_eq!(Some("foo.tar.gz".to_string()), get_source_file_name("foo.tar.gz::https://example.com/v1.0.tar.gz"));
+        assert_eq!(Some("foo".to_string()), get_source_file_name("git+https://example.com/foo.git#tag=v1.0"));
+        assert_eq!(Some("bar".to_string()), get_source_file_name("bar::git+https://example.com/foo.git"));
+        assert_eq!(Some("foo".to_string()), get_source_file_name("hg+https://example.com/foo?signed#branch=main"));
+        assert_eq!(None, get_source_file_name("fix.patch"));
+    }
+
+    #[tokio::test]
+    async fn prune_least_recently_used() {
+        let path = std::env::temp_dir().join("aur-build-worker-source-cache-test");
+        let _ = remove_dir_all(&path).await;
+        let cache = SourceCache::new(path.clone(), 250);
+        cache.init().await.unwrap();
+
+        let past = SystemTime::now() - Duration::from_secs(100);
+        write(path.join("old.tar.gz"), vec![0; 100]).await.unwrap();
+        create_dir_all(path.join("repo/objects")).await.unwrap();
+        write(path.join("repo/objects/pack"), vec![0; 100]).await.unwrap();
+        write(path.join("new.tar.gz"), vec![0; 100]).await.unwrap();
+        for name in ["old.tar.gz", "repo", "new.tar.gz"] {
+            std::fs::File::open(path.join(name)).unwrap().set_modified(past).unwrap();
+        }
+
+        let src_info = Srcinfo::parse_buf(
+            "pkgbase = foo\n\tpkgver = 1\n\tpkgrel = 1\n\tsource = git+https://example.com/repo.git\n\tsource = https://example.com/new.tar.gz\n\npkgname = foo\n".as_bytes()
+        ).unwrap();
+        cache.touch_sources(&src_info);
+
+        assert!(cache.prune().await.unwrap() <= 250);
+        assert!(!path.join("old.tar.gz").exists());
+        assert!(path.join("repo/objects/pack").exists());
+        assert!(path.join("new.tar.gz").exists());
+
+        remove_dir_all(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use flate2::read::GzDecoder;
+use log::debug;
+use tar::Archive;
+use common::version::vercmp;
+
+/// Version comparison of a dependency, e.g. the `>=` of `glibc>=2.28`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionOperator {
+    Lower,
+    LowerOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl VersionOperator {
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            VersionOperator::Lower => ordering == Ordering::Less,
+            VersionOperator::LowerOrEqual => ordering != Ordering::Greater,
+            VersionOperator::Equal => ordering == Ordering::Equal,
+            VersionOperator::GreaterOrEqual => ordering != Ordering::Less,
+            VersionOperator::Greater => ordering == Ordering::Greater,
+        }
+    }
+}
+
+/// Dependency or provide with an optional version constraint, as written in a PKGBUILD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub constraint: Option<(VersionOperator, String)>,
+}
+
+impl Dependency {
+    pub fn parse(value: &str) -> Dependency {
+        const OPERATORS: [(&str, VersionOperator); 5] = [
+            (">=", VersionOperator::GreaterOrEqual),
+            ("<=", VersionOperator::LowerOrEqual),
+            ("=", VersionOperator::Equal),
+            (">", VersionOperator::Greater),
+            ("<", VersionOperator::Lower),
+        ];
+
+        let Some(index) = value.find(['<', '>', '=']) else {
+            return Dependency { name: value.to_string(), constraint: None };
+        };
+        let (name, rest) = value.split_at(index);
+        let (operator, version) = OPERATORS.iter()
+            .find_map(|(prefix, operator)| rest.strip_prefix(prefix).map(|version| (*operator, version)))
+            .unwrap();
+
+        Dependency {
+            name: name.to_string(),
+            constraint: Some((operator, version.to_string())),
+        }
+    }
+
+    /// Whether the given version satisfies the constraint of the dependency.
+    fn accepts(&self, version: Option<&str>) -> bool {
+        match (&self.constraint, version) {
+            (None, _) => true,
+            (Some((operator, required)), Some(version)) => operator.matches(vercmp(version, required)),
+            // A provide without version only satisfies unversioned dependencies
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Package of a sync database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncPackage {
+    pub name: String,
+    pub version: String,
+    pub provides: Vec<Dependency>,
+}
+
+/// Packages of the sync databases of a pacman installation, used to resolve dependencies without running pacman.
+#[derive(Debug, Default)]
+pub struct SyncDatabases {
+    packages: HashMap<String, SyncPackage>,
+    /// Names of the packages providing each name.
+    providers: HashMap<String, Vec<String>>,
+    /// Names of the packages served by the repository of the server.
+    server_packages: HashSet<String>,
+}
+
+impl SyncDatabases {
+    /// Loads the databases of the repositories configured in the `pacman.conf` of the given root.
+    /// The packages of the given repository of the server are tracked to check whether they are up to date.
+    pub async fn load(root: &Path, server_repository: Option<&str>) -> Result<SyncDatabases>
+    {
+        let config_path = root.join("etc/pacman.conf");
+        let config = tokio::fs::read_to_string(&config_path).await
+            .with_context(|| format!("Failed to read {:?}", config_path))?;
+
+        let mut databases = SyncDatabases::default();
+        // Packages of the first repositories take precedence, as for pacman
+        for repository in get_repositories(&config) {
+            let path = root.join("var/lib/pacman/sync").join(format!("{}.db", repository));
+            let content = tokio::fs::read(&path).await
+                .with_context(|| format!("Failed to read sync database {:?}", path))?;
+            let packages = tokio::task::spawn_blocking(move || parse_database(content.as_slice())).await?
+                .with_context(|| format!("Failed to parse sync database {:?}", path))?;
+            debug!("Loaded {} packages from {:?}", packages.len(), path);
+            if server_repository == Some(repository.as_str()) {
+                databases.server_packages.extend(packages.iter()
+                    .filter(|package| !databases.packages.contains_key(&package.name))
+                    .map(|package| package.name.clone()));
+            }
+            databases.extend(packages);
+        }
+
+        Ok(databases)
+    }
+
+    pub fn extend(&mut self, packages: Vec<SyncPackage>) {
+        for package in packages {
+            if self.packages.contains_key(&package.name) {
+                continue;
+            }
+            for provide in package.provides.iter() {
+                self.providers.entry(provide.name.clone()).or_default().push(package.name.clone());
+            }
+            self.packages.insert(package.name.clone(), package);
+        }
+    }
+
+    /// Whether the given package is installed from the repository of the server.
+    pub fn is_from_server(&self, package: &SyncPackage) -> bool
+    {
+        self.server_packages.contains(&package.name)
+    }
+
+    /// Finds a package satisfying the given dependency by its name or one of its provides.
+    pub fn find_satisfier(&self, dependency: &str) -> Option<&SyncPackage>
+    {
+        let dependency = Dependency::parse(dependency);
+
+        if let Some(package) = self.packages.get(&dependency.name) {
+            if dependency.accepts(Some(&package.version)) {
+                return Some(package);
+            }
+        }
+
+        self.providers.get(&dependency.name)?
+            .iter()
+            .filter_map(|name| self.packages.get(name))
+            .find(|package| package.provides.iter().any(|provide| {
+                provide.name == dependency.name && dependency.accepts(provide.constraint.as_ref().map(|(_, v)| v.as_str()))
+            }))
+    }
+}
+
+/// Names of the repositories of a pacman configuration, in order.
+fn get_repositories(config: &str) -> Vec<String>
+{
+    config.lines()
+        .map(|line| line.trim())
+        .filter_map(|line| line.strip_prefix('[')?.strip_suffix(']'))
+        .filter(|section| *section != "options")
+        .map(|section| section.to_string())
+        .collect()
+}
+
+/// Reads the packages of a sync database, a tarball compressed with gzip or not compressed.
+fn parse_database(content: &[u8]) -> Result<Vec<SyncPackage>>
+{
+    let reader: Box<dyn Read + '_> = if content.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(content))
+    } else {
+        Box::new(content)
+    };
+
+    let mut packages = Vec::new();
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.path()?.ends_with("desc") {
+            continue;
+        }
+        let mut desc = String::new();
+        entry.read_to_string(&mut desc)?;
+        if let Some(package) = parse_desc(&desc) {
+            packages.push(package);
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Parses the `desc` file of a package in a sync database.
+fn parse_desc(desc: &str) -> Option<SyncPackage>
+{
+    let mut fields: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = None;
+    for line in desc.lines() {
+        if line.starts_with('%') && line.ends_with('%') && line.len() > 1 {
+            current = Some(&line[1..line.len() - 1]);
+        } else if line.is_empty() {
+            current = None;
+        } else if let Some(field) = current {
+            fields.entry(field).or_default().push(line);
+        }
+    }
+
+    Some(SyncPackage {
+        name: fields.get("NAME")?.first()?.to_string(),
+        version: fields.get("VERSION")?.first()?.to_string(),
+        provides: fields.get("PROVIDES")
+            .map(|provides| provides.iter().map(|p| Dependency::parse(p)).collect())
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use common::version::vercmp;
+    use crate::builder::sync_db::{get_repositories, parse_database, Dependency, SyncDatabases, VersionOperator};
+
+    #[test]
+    fn test_vercmp() {
+        let cases = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "1.1", Ordering::Less),
+            ("1.0.1", "1.0", Ordering::Greater),
+            ("1.0a", "1.0", Ordering::Less),
+            ("1.0alpha", "1.0beta", Ordering::Less),
+            ("1.0", "1.0.a", Ordering::Less),
+            ("1.010", "1.9", Ordering::Greater),
+            ("1.0-1", "1.0-2", Ordering::Less),
+            ("1.0", "1.0-2", Ordering::Equal),
+            ("1:1.0", "2.0", Ordering::Greater),
+            ("0:1.0", "1.0", Ordering::Equal),
+            ("1.0_1", "1.0.1", Ordering::Equal),
+            ("1.0..1", "1.0.1", Ordering::Greater),
+            ("2.28-4", "2.28", Ordering::Equal),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(expected, vercmp(a, b), "{} {}", a, b);
+            assert_eq!(expected.reverse(), vercmp(b, a), "{} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_parse_dependency() {
+        assert_eq!(Dependency { name: "glibc".to_string(), constraint: None }, Dependency::parse("glibc"));
+        assert_eq!(
+            Dependency { name: "glibc".to_string(), constraint: Some((VersionOperator::GreaterOrEqual, "2.28-4".to_string())) },
+            Dependency::parse("glibc>=2.28-4")
+        );
+        assert_eq!(
+            Dependency { name: "java-runtime".to_string(), constraint: Some((VersionOperator::Equal, "17".to_string())) },
+            Dependency::parse("java-runtime=17")
+        );
+        assert_eq!(Some((VersionOperator::Lower, "2".to_string())), Dependency::parse("python<2").constraint);
+    }
+
+    #[test]
+    fn test_get_repositories() {
+        let config = "[options]\nArchitecture = auto\n\n#[testing]\n[core]\nInclude = /etc/pacman.d/mirrorlist\n\n[extra]\nInclude = /etc/pacman.d/mirrorlist\n";
+        assert_eq!(vec!["core", "extra"], get_repositories(config));
+    }
+
+    fn get_database() -> Vec<u8> {
+        let packages = [
+            ("glibc-2.39-1", "%NAME%\nglibc\n\n%VERSION%\n2.39-1\n\n%PROVIDES%\nlibc.so=6-64\n\n"),
+            ("jre17-openjdk-17.0.10-1", "%NAME%\njre17-openjdk\n\n%VERSION%\n17.0.10-1\n\n%PROVIDES%\njava-runtime=17\njre17\n\n"),
+            ("bash-5.2-1", "%FILENAME%\nbash-5.2-1-x86_64.pkg.tar.zst\n\n%NAME%\nbash\n\n%VERSION%\n5.2-1\n\n%PROVIDES%\nsh\n\n"),
+        ];
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (directory, desc) in packages {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(desc.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("{}/desc", directory), desc.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_find_satisfier() {
+        let mut databases = SyncDatabases::default();
+        databases.extend(parse_database(&get_database()).unwrap());
+
+        let satisfier = |dependency: &str| databases.find_satisfier(dependency).map(|p| p.name.as_str());
+        assert_eq!(Some("glibc"), satisfier("glibc"));
+        assert_eq!(Some("glibc"), satisfier("glibc>=2.28"));
+        assert_eq!(None, satisfier("glibc>=3"));
+        assert_eq!(Some("glibc"), satisfier("libc.so=6-64"));
+        assert_eq!(Some("jre17-openjdk"), satisfier("java-runtime"));
+        assert_eq!(Some("jre17-openjdk"), satisfier("java-runtime>=11"));
+        assert_eq!(None, satisfier("java-runtime>=21"));
+        assert_eq!(Some("bash"), satisfier("sh"));
+        // Unversioned provides only satisfy unversioned dependencies
+        assert_eq!(None, satisfier("sh>=1"));
+        assert_eq!(None, satisfier("yay"));
+
+        let glibc = databases.find_satisfier("glibc").unwrap().clone();
+        assert!(!databases.is_from_server(&glibc));
+        databases.server_packages.insert("glibc".to_string());
+        assert!(databases.is_from_server(&glibc));
+    }
+}
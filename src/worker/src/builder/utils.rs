@@ -1,9 +1,10 @@
 use anyhow::Result;
 use log::{error, info, trace};
 use std::path::PathBuf;
-use std::process::Stdio;
+use std::process::{Output, Stdio};
 use tokio::fs::{create_dir_all, remove_dir_all};
-use tokio::process::{Child, Command};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
 use crate::logs::{write_tail_logs, LogSection};
 
 pub async fn post_build_clean(data_path: &PathBuf) -> Result<()> {
@@ -16,12 +17,38 @@ pub async fn post_build_clean(data_path: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-pub fn run_command(mut command: Command, log_path: Option<&PathBuf>, log_section: Option<LogSection>) -> Result<Child> {
+/// Kills the process group of a command if it is dropped before the command exited, e.g. when the build is cancelled.
+struct ProcessGroupGuard {
+    pid: Option<u32>,
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            info!("Killing process group {}", pid);
+            let _ = std::process::Command::new("kill")
+                .args(["-KILL", "--", format!("-{}", pid).as_str()])
+                .status();
+        }
+    }
+}
+
+/// Runs the command in its own process group, writing its output to the given log file.
+/// The whole process tree is killed if the returned future is dropped.
+pub async fn run_command(
+    mut command: Command,
+    log_path: Option<&PathBuf>,
+    log_section: Option<LogSection>,
+    log_sender: Option<UnboundedSender<String>>,
+) -> Result<Output> {
     let (reader, writer) = os_pipe::pipe()?;
 
     let child = command.stdin(Stdio::null())
         .stdout(writer.try_clone()?)
-        .stderr(writer).spawn()?;
+        .stderr(writer)
+        .process_group(0)
+        .spawn()?;
+    let mut guard = ProcessGroupGuard { pid: child.id() };
 
     let log_path = match log_path {
         None => None,
@@ -31,11 +58,39 @@ pub fn run_command(mut command: Command, log_path: Option<&PathBuf>, log_section
     drop(command);
 
     tokio::task::spawn(async move {
-        match write_tail_logs(reader, log_path, log_section).await {
+        match write_tail_logs(reader, log_path, log_section, log_sender).await {
             Ok(_) => trace!("Successfully wrote command logs"),
             Err(e) => error!("Failed to write command logs: {}", e),
         }
     });
 
-    Ok(child)
+    let output = child.wait_with_output().await?;
+    guard.pid = None;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::process::Command;
+    use crate::builder::utils::run_command;
+
+    // The command output is read with blocking calls, the timer needs another thread
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn run_command_kill_on_drop() {
+        let pid_file = std::env::temp_dir().join("aur-build-worker-run-command.pid");
+        let mut command = Command::new("sh");
+        command.args(["-c", &format!("sleep 30 & echo $! > {}; wait", pid_file.display())]);
+
+        let res = tokio::time::timeout(Duration::from_millis(500), run_command(command, None, None, None)).await;
+        assert!(res.is_err());
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // Killed processes may stay around as zombies until reaped by their new parent
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "));
+        let _ = std::fs::remove_file(&pid_file);
+    }
 }
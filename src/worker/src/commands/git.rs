@@ -1,19 +1,23 @@
 use anyhow::{bail, Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use git2::{Diff, ObjectType, Repository};
+use git2::build::CheckoutBuilder;
 use log::{debug, info};
 use reqwest::Client;
 use sha2::{Digest, Sha512};
-use common::models::{PackageJob, PackagePatchDefinition};
+use common::models::{BuildErrorClass, PackageJob, PackagePatchDefinition};
+use crate::models::build_error::Classify;
 
 async fn fetch_patch(patch: &PackagePatchDefinition) -> Result<String>
 {
     let content = Client::new().get(&patch.url)
         .send()
-        .await.with_context(|| format!("Failed to retrieve patch {}", patch.url))?
+        .await.with_context(|| format!("Failed to retrieve patch {}", patch.url))
+        .classify(BuildErrorClass::Network)?
         .text()
-        .await.with_context(|| format!("Failed to parse patch request content {}", patch.url))?;
+        .await.with_context(|| format!("Failed to parse patch request content {}", patch.url))
+        .classify(BuildErrorClass::Network)?;
 
     if let Some(expected_hash) = patch.sha512.as_ref() {
         let mut hasher = Sha512::new();
@@ -48,7 +52,7 @@ pub async fn apply_patches(package: &PackageJob, repository: Repository) -> Resu
         info!("Applying patch {} on {} ...", patch.url, package.definition.name);
         let patch_content = fetch_patch(patch).await?;
         info!("Patch content : '{}'", patch_content);
-        apply_patch(&repository, &patch_content)?;
+        apply_patch(&repository, &patch_content).classify(BuildErrorClass::Source)?;
         info!("Patch is applied !");
     }
 
@@ -64,11 +68,44 @@ pub fn clone_repo(data_path: &PathBuf, repo_name: &String) -> Result<Repository>
     let path = data_path.join(repo_name);
     let url = format!("https://aur.archlinux.org/{}.git", repo_name);
 
+    clone_url(&url, &path)
+}
+
+/// Clones a git repository into the given path and checks out the given reference if any.
+/// The reference can be a branch, a tag or a commit.
+pub fn clone_git_source(path: &Path, url: &str, reference: Option<&String>) -> Result<Repository> {
+    let repository = clone_url(url, path)?;
+
+    if let Some(reference) = reference {
+        checkout_reference(&repository, reference)
+            .with_context(|| format!("Failed to checkout {} for url {}", reference, url))?;
+        debug!("Checked out {} for {}: {}", reference, url, get_current_commit_id(&repository)?);
+    }
+
+    Ok(repository)
+}
+
+/// Initializes a git repository in an existing directory so patches can be applied to it.
+pub fn init_repo(path: &Path) -> Result<Repository> {
+    Repository::init(path).with_context(|| format!("Failed to init repository in {:?}", path))
+}
+
+fn checkout_reference(repository: &Repository, reference: &str) -> Result<()> {
+    let object = repository.revparse_single(&format!("origin/{}", reference))
+        .or_else(|_| repository.revparse_single(reference))?;
+    let commit = object.peel_to_commit()?;
+
+    repository.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+    repository.set_head_detached(commit.id())?;
+    Ok(())
+}
+
+fn clone_url(url: &str, path: &Path) -> Result<Repository> {
     if path.exists() {
-        fs::remove_dir_all(&path).with_context(|| "Failed to clean repository. Check permissions")?;
+        fs::remove_dir_all(path).with_context(|| "Failed to clean repository. Check permissions")?;
     }
 
-    let repository = Repository::clone(url.as_str(), path)
+    let repository = Repository::clone(url, path)
         .with_context(|| format!("Failed to clone for url {}", url))?;
 
     let cloned_commit = get_current_commit_id(&repository)
@@ -93,15 +130,15 @@ mod tests {
             definition: PackageDefinition {
                 package_id: 1,
                 name: "google-chrome".to_string(),
-                run_before: None,
                 patches: vec![
                     PackagePatchDefinition {
                         url: "https://gist.githubusercontent.com/seifane/d1b04045a02452ada1fe894d18e2c2aa/raw/bc01f21fc579164d69dff0191685647d81d4b27e/gistfile1.txt".to_string(),
                         sha512: Some("cb8e7696fb1ff4fd6ed0d5200b2665c470aaf1ed2f67e0b73762b242327bdde34512afcf728151656d3442579e655465fc6d6fb89ff4412fad16357eb9c7632a".to_string()),
                     }
                 ],
+                ..Default::default()
             },
-            last_built_version: None,
+            ..Default::default()
         };
 
         apply_patches(&package, repo).await.unwrap();
@@ -1,12 +1,12 @@
-use std::path::PathBuf;
+use std::path::Path;
 use log::{info, warn};
 use crate::builder::bubblewrap::Bubblewrap;
 use crate::commands::makepkg::get_src_info;
 
-pub async fn attempt_recv_pgp_keys(bubblewrap: &Bubblewrap, data_path: &PathBuf, package_name: &String) {
+pub async fn attempt_recv_pgp_keys(bubblewrap: &Bubblewrap, data_path: &Path, package_name: &String) {
     info!("Attempting to fetch PGP keys");
 
-    match get_src_info(data_path, package_name).await {
+    match get_src_info(bubblewrap, data_path, package_name).await {
         Ok(src_info) => {
             for key in src_info.base.valid_pgp_keys.iter() {
                 info!("Trying to fetch {} public key", key);
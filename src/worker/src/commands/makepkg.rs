@@ -1,29 +1,117 @@
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
 use std::process::Output;
-use log::{info};
-use srcinfo::Srcinfo;
-use tokio::process::Command;
-use crate::builder::bubblewrap::Bubblewrap;
+use log::{info, warn};
+use srcinfo::{ArchVec, Srcinfo};
+use crate::builder::bubblewrap::{Bubblewrap, SandboxOptions};
 use crate::logs::LogSection;
+use crate::utils::sanitize_dependency;
 
-pub async fn get_src_info(data_path: &PathBuf, package_name: &String) -> Result<Srcinfo>
+/// Reads the metadata of the package in the given directory.
+/// Uses the `.SRCINFO` file shipped with the package if present, otherwise generates it from the PKGBUILD in the sandbox.
+pub async fn read_src_info(bubblewrap: &Bubblewrap, path: &Path) -> Result<Srcinfo>
 {
-    let output = Command::new("makepkg")
-        .arg("--printsrcinfo")
-        .current_dir(data_path.join(package_name))
-        .output()
-        .await
-        .with_context(|| format!("Failed to execute makepkg printsrcinfo for {}", package_name))?;
+    let srcinfo_path = path.join(".SRCINFO");
+    if srcinfo_path.exists() {
+        let content = tokio::fs::read(&srcinfo_path).await
+            .with_context(|| format!("Failed to read {:?}", srcinfo_path))?;
+        return Srcinfo::parse_buf(content.as_slice()).with_context(|| "Failed to parse SrcInfo");
+    }
 
-    Ok(
-        Srcinfo::parse_buf(output.stdout.as_slice()).with_context(|| "Failed to parse SrcInfo")?
-    )
+    print_src_info(bubblewrap, path).await
 }
 
-pub async fn run_makepkg(bubblewrap: &Bubblewrap, package_name: &String, log_path: &PathBuf) -> Result<Output>
+/// Runs `makepkg --printsrcinfo` in the base sandbox mounted read-only, as the PKGBUILD is sourced by it.
+async fn print_src_info(bubblewrap: &Bubblewrap, path: &Path) -> Result<Srcinfo>
 {
-    info!("Running makepkg for {}", package_name);
+    let output = bubblewrap.clone()
+        .with_options(SandboxOptions {
+            binds: vec![(path.to_path_buf(), "/package".to_string())],
+            ..Default::default()
+        })
+        .with_read_only_root()
+        .query_sandbox(false, "base", "/package", "makepkg", vec!["--printsrcinfo"]).await
+        .with_context(|| format!("Failed to execute makepkg printsrcinfo in {:?}", path))?;
+    if !output.status.success() {
+        bail!("makepkg printsrcinfo failed with code {:?}: {}", output.status.code(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Srcinfo::parse_buf(output.stdout.as_slice()).with_context(|| "Failed to parse SrcInfo")
+}
+
+pub async fn get_src_info(bubblewrap: &Bubblewrap, data_path: &Path, package_name: &String) -> Result<Srcinfo>
+{
+    read_src_info(bubblewrap, &data_path.join(package_name)).await
+        .with_context(|| format!("Failed to read package metadata for {}", package_name))
+}
+
+/// Warns when the `.SRCINFO` shipped with the package does not match its PKGBUILD, e.g. when the maintainer forgot to regenerate it.
+pub async fn check_src_info(bubblewrap: &Bubblewrap, data_path: &Path, package_name: &String)
+{
+    let path = data_path.join(package_name);
+    if !path.join(".SRCINFO").exists() {
+        return;
+    }
+
+    let shipped = match read_src_info(bubblewrap, &path).await {
+        Ok(src_info) => src_info,
+        Err(e) => return warn!("Could not read .SRCINFO of {}: {:#}", package_name, e),
+    };
+    let generated = match print_src_info(bubblewrap, &path).await {
+        Ok(src_info) => src_info,
+        Err(e) => return warn!("Could not check .SRCINFO of {}: {:#}", package_name, e),
+    };
+
+    let differences = get_src_info_differences(&shipped, &generated);
+    if !differences.is_empty() {
+        warn!("The .SRCINFO of {} does not match its PKGBUILD, using the .SRCINFO: {}", package_name, differences.join(", "));
+    }
+}
+
+fn format_arch_vecs(values: &[ArchVec]) -> String
+{
+    values.iter()
+        .flat_map(|v| v.vec.iter().map(move |value| match &v.arch {
+            Some(arch) => format!("{}:{}", arch, value),
+            None => value.clone(),
+        }))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Describes the fields of the `.SRCINFO` differing from the metadata generated from the PKGBUILD.
+fn get_src_info_differences(shipped: &Srcinfo, generated: &Srcinfo) -> Vec<String>
+{
+    let mut fields = vec![
+        ("pkgbase", shipped.base.pkgbase.clone(), generated.base.pkgbase.clone()),
+        ("pkgver", shipped.base.pkgver.clone(), generated.base.pkgver.clone()),
+        ("pkgrel", shipped.base.pkgrel.clone(), generated.base.pkgrel.clone()),
+        ("epoch", shipped.base.epoch.clone().unwrap_or_default(), generated.base.epoch.clone().unwrap_or_default()),
+        (
+            "pkgname",
+            shipped.pkgs.iter().map(|p| p.pkgname.as_str()).collect::<Vec<_>>().join(" "),
+            generated.pkgs.iter().map(|p| p.pkgname.as_str()).collect::<Vec<_>>().join(" "),
+        ),
+        ("source", format_arch_vecs(&shipped.base.source), format_arch_vecs(&generated.base.source)),
+        ("makedepends", format_arch_vecs(&shipped.base.makedepends), format_arch_vecs(&generated.base.makedepends)),
+        ("checkdepends", format_arch_vecs(&shipped.base.checkdepends), format_arch_vecs(&generated.base.checkdepends)),
+    ];
+    for package in generated.pkgs.iter() {
+        if let Some(shipped_package) = shipped.pkgs.iter().find(|p| p.pkgname == package.pkgname) {
+            fields.push(("depends", format_arch_vecs(&shipped_package.depends), format_arch_vecs(&package.depends)));
+        }
+    }
+
+    fields.into_iter()
+        .filter(|(_, shipped, generated)| shipped != generated)
+        .map(|(name, shipped, generated)| format!("{} '{}' instead of '{}'", name, shipped, generated))
+        .collect()
+}
+
+/// Downloads and verifies the sources of the package without building it.
+pub async fn download_sources(bubblewrap: &Bubblewrap, package_name: &String, log_path: &PathBuf) -> Result<Output>
+{
+    info!("Downloading sources for {}", package_name);
 
     let output = bubblewrap.run_sandbox(
         false,
@@ -31,21 +119,83 @@ pub async fn run_makepkg(bubblewrap: &Bubblewrap, package_name: &String, log_pat
         "/package",
         "makepkg",
         vec![
-            "--clean",
+            "--verifysource",
             "--noconfirm",
         ],
+        Some(log_path),
+        Some(LogSection::VerifySource(package_name.to_string()))
+    ).await?;
+    Ok(output)
+}
+
+/// Builds the package, `offline` keeps makepkg from updating the VCS sources already downloaded.
+pub async fn run_makepkg(bubblewrap: &Bubblewrap, package_name: &String, log_path: &PathBuf, offline: bool) -> Result<Output>
+{
+    info!("Running makepkg for {}", package_name);
+
+    let mut args = vec![
+        "--clean",
+        "--noconfirm",
+    ];
+    if offline {
+        args.push("--holdver");
+    }
+
+    let output = bubblewrap.run_sandbox(
+        false,
+        "current",
+        "/package",
+        "makepkg",
+        args,
         Some(&log_path),
         Some(LogSection::MakePkg(package_name.to_string()))
     ).await?;
     Ok(output)
 }
 
-pub async fn get_package_version(data_path: &PathBuf, package_base: &String) -> Result<String> {
-    let src_info = get_src_info(data_path, package_base).await?;
+pub async fn get_package_version(bubblewrap: &Bubblewrap, data_path: &Path, package_base: &String) -> Result<String> {
+    let src_info = get_src_info(bubblewrap, data_path, package_base).await?;
 
     let mut version = src_info.base.pkgver.clone();
     version += src_info.base.pkgrel.as_str();
     version += src_info.base.epoch.as_ref().unwrap_or(&"".to_string()).as_str();
 
     Ok(version)
-}
\ No newline at end of file
+}
+
+/// Returns the names of the depends and makedepends of the package, without their version constraints.
+pub async fn get_package_dependencies(bubblewrap: &Bubblewrap, data_path: &Path, package_base: &String) -> Result<Vec<String>> {
+    let src_info = get_src_info(bubblewrap, data_path, package_base).await?;
+
+    let mut dependencies: Vec<String> = src_info.base.makedepends.iter()
+        .chain(src_info.pkgs.iter().flat_map(|pkg| pkg.depends.iter()))
+        .flat_map(|v| v.vec.iter())
+        .map(|d| sanitize_dependency(d))
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use srcinfo::Srcinfo;
+    use crate::commands::makepkg::get_src_info_differences;
+
+    #[test]
+    fn test_get_src_info_differences() {
+        let shipped = Srcinfo::parse_buf("pkgbase = foo\n\tpkgver = 1.0.0\n\tpkgrel = 1\n\tarch = any\n\tmakedepends = cmake\n\tsource = foo-1.0.0.tar.gz::https://example.com/foo-1.0.0.tar.gz\n\npkgname = foo\n\tdepends = glibc\n".as_bytes()).unwrap();
+        assert!(get_src_info_differences(&shipped, &shipped).is_empty());
+
+        let generated = Srcinfo::parse_buf("pkgbase = foo\n\tpkgver = 1.1.0\n\tpkgrel = 1\n\tarch = any\n\tmakedepends = cmake\n\tsource = foo-1.1.0.tar.gz::https://example.com/foo-1.1.0.tar.gz\n\npkgname = foo\n\tdepends = glibc\n\tdepends = zlib\n".as_bytes()).unwrap();
+        assert_eq!(
+            vec![
+                "pkgver '1.0.0' instead of '1.1.0'",
+                "source 'foo-1.0.0.tar.gz::https://example.com/foo-1.0.0.tar.gz' instead of 'foo-1.1.0.tar.gz::https://example.com/foo-1.1.0.tar.gz'",
+                "depends 'glibc' instead of 'glibc zlib'",
+            ],
+            get_src_info_differences(&shipped, &generated)
+        );
+    }
+}
@@ -1,13 +1,20 @@
 use anyhow::{bail, Result};
 use log::warn;
 use crate::builder::bubblewrap::Bubblewrap;
-use crate::utils::sanitize_dependency;
+use crate::builder::pacman_cache::PacmanCache;
 
-pub async fn pacman_update(bubblewrap: &Bubblewrap) -> Result<()>
+pub async fn pacman_update(bubblewrap: &Bubblewrap, pacman_cache: &PacmanCache) -> Result<()>
 {
-    let output = bubblewrap.run_sandbox(true, "base", "/", "pacman", vec!["-Syyu", "--noconfirm"], None, None)
+    let output = bubblewrap.run_sandbox(true, "base", "/", "pacman", vec!["-Syy"], None, None)
         .await?;
+    if !output.status.success() {
+        bail!("Failed to refresh databases with code {:?}", output.status.code());
+    }
 
+    record_cache_usage(bubblewrap, pacman_cache, "base", vec!["-Su"]).await;
+
+    let output = bubblewrap.run_sandbox(true, "base", "/", "pacman", vec!["-Su", "--noconfirm"], None, None)
+        .await?;
     if !output.status.success() {
         bail!("Failed to update with code {:?}", output.status.code());
     }
@@ -15,16 +22,102 @@ pub async fn pacman_update(bubblewrap: &Bubblewrap) -> Result<()>
     Ok(())
 }
 
-// TODO: Replace with proper call to libalpm
-pub async fn is_package_in_repo(bubblewrap: &Bubblewrap, package_name: &String) -> bool {
-    let output = bubblewrap.run_sandbox(true,"base", "/", "pacman", vec![
-        "-Ss",
-        format!("^{}$", sanitize_dependency(package_name)).as_str(),
-    ], None, None).await;
+/// pacman configuration section of the repository served by the server.
+/// Its packages and database must be signed when the server has a `sign_key`, they are not signed otherwise.
+pub fn get_server_repo_config(repo_name: &str, url: &str, signed: bool) -> String
+{
+    let sig_level = if signed { "Required" } else { "Optional TrustAll" };
+    format!("[{}]\nSigLevel = {}\nServer = {}\n", repo_name, sig_level, url)
+}
+
+/// Imports the key the repository of the server is signed with into the keyring of the base sandbox and trusts it.
+pub async fn import_server_repo_key(bubblewrap: &Bubblewrap, key: &str) -> Result<()>
+{
+    let key_path = "/etc/pacman.d/aur-build-server.asc";
+    tokio::fs::write(bubblewrap.namespace_path("base").join(key_path.trim_start_matches('/')), key).await?;
+
+    let output = bubblewrap.query_sandbox(true, "base", "/", "gpg", vec!["--with-colons", "--show-keys", key_path])
+        .await?;
+    if !output.status.success() {
+        bail!("Failed to read the server repository key with code {:?}", output.status.code());
+    }
+    let fingerprints = parse_key_fingerprints(&String::from_utf8_lossy(&output.stdout));
+    if fingerprints.is_empty() {
+        bail!("No key found in the server repository key");
+    }
 
-    if output.is_err() {
-        warn!("Could not check pacman for {}, {}", package_name, output.unwrap_err());
-        return false;
+    let output = bubblewrap.run_sandbox(true, "base", "/", "pacman-key", vec!["--add", key_path], None, None)
+        .await?;
+    if !output.status.success() {
+        bail!("Failed to import the server repository key with code {:?}", output.status.code());
+    }
+    for fingerprint in fingerprints.iter() {
+        let output = bubblewrap.run_sandbox(true, "base", "/", "pacman-key", vec!["--lsign-key", fingerprint], None, None)
+            .await?;
+        if !output.status.success() {
+            bail!("Failed to sign the server repository key {} with code {:?}", fingerprint, output.status.code());
+        }
     }
-    output.unwrap().status.success()
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Fingerprints of the primary keys listed by `gpg --with-colons`.
+fn parse_key_fingerprints(colons: &str) -> Vec<String>
+{
+    let mut fingerprints = Vec::new();
+    let mut primary = false;
+    for fields in colons.lines().map(|line| line.split(':').collect::<Vec<_>>()) {
+        match fields[0] {
+            "pub" => primary = true,
+            "fpr" if primary => {
+                if let Some(fingerprint) = fields.get(9).filter(|f| !f.is_empty()) {
+                    fingerprints.push(fingerprint.to_string());
+                }
+                primary = false;
+            }
+            _ => {}
+        }
+    }
+    fingerprints
+}
+
+/// Logs the cache statistics of the given sync operation before it is run.
+pub async fn record_cache_usage(bubblewrap: &Bubblewrap, pacman_cache: &PacmanCache, env: &str, mut args: Vec<&str>)
+{
+    args.extend(["--print", "--print-format", "%f", "--noconfirm"]);
+    let output = match bubblewrap.query_sandbox(true, env, "/", "pacman", args).await {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            warn!("Could not list pacman targets, code {:?}", output.status.code());
+            return;
+        }
+        Err(e) => {
+            warn!("Could not list pacman targets, {}", e);
+            return;
+        }
+    };
+
+    let files = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+    pacman_cache.record_usage(&files);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::pacman::parse_key_fingerprints;
+
+    #[test]
+    fn parse_primary_key_fingerprints() {
+        let colons = "pub:-:4096:1:0123456789ABCDEF:1700000000:::-:::scESC::::::23::0:\n\
+            fpr:::::::::AAAABBBBCCCCDDDDEEEEFFFF0123456789ABCDEF:\n\
+            uid:-::::1700000000::HASH::Server <server@example.com>::::::::::0:\n\
+            sub:-:4096:1:FEDCBA9876543210:1700000000::::::e::::::23:\n\
+            fpr:::::::::1111222233334444555566667777FEDCBA9876543210:\n";
+
+        assert_eq!(vec!["AAAABBBBCCCCDDDDEEEEFFFF0123456789ABCDEF".to_string()], parse_key_fingerprints(colons));
+        assert!(parse_key_fingerprints("").is_empty());
+    }
+}
@@ -5,9 +5,11 @@ use log::{log_enabled, Level};
 use os_pipe::PipeReader;
 use tokio::fs::{create_dir, File, OpenOptions, remove_dir_all};
 use tokio::io::{AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
 
 pub enum LogSection {
     RunBefore,
+    VerifySource(String),
     MakePkg(String)
 }
 
@@ -16,6 +18,7 @@ impl LogSection {
     {
         let message = match self {
             LogSection::RunBefore => "run_before".to_string(),
+            LogSection::VerifySource(package) => format!("MakePkg --verifysource {}", package),
             LogSection::MakePkg(package) => format!("MakePkg {}", package),
         };
 
@@ -36,22 +39,44 @@ pub async fn get_log_file_handle(path: &PathBuf) -> Result<File> {
     Ok(file)
 }
 
+/// Takes the longest valid UTF-8 prefix out of the buffer, leaving an incomplete trailing character for the next read.
+fn take_utf8(buffer: &mut Vec<u8>) -> String {
+    let end = match std::str::from_utf8(buffer) {
+        Ok(_) => buffer.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => buffer.len(),
+    };
+    let rest = buffer.split_off(end);
+    let content = String::from_utf8_lossy(buffer).to_string();
+    *buffer = rest;
+    content
+}
+
+/// Writes the output of a command to the log file, also forwarding it to the given sender to stream it to the server.
+/// Output is only forwarded when written to a log file.
 pub async fn write_tail_logs(
     mut reader: PipeReader,
     path: Option<PathBuf>,
-    section: Option<LogSection>
+    section: Option<LogSection>,
+    sender: Option<UnboundedSender<String>>,
 ) -> Result<()> {
     let mut file = if let Some(path) = path {
         let mut file = get_log_file_handle(&path).await?;
         if let Some(section) = section {
-            file.write(section.to_header_string().as_bytes()).await?;
+            let header = section.to_header_string();
+            file.write(header.as_bytes()).await?;
+            if let Some(sender) = sender.as_ref() {
+                let _ = sender.send(header);
+            }
         }
         Some(file)
     } else {
         None
     };
+    let sender = sender.filter(|_| file.is_some());
 
     let mut buffer = [0u8; 128];
+    let mut pending = Vec::new();
     loop {
         match reader.read(&mut buffer) {
             Ok(0) => {
@@ -64,6 +89,13 @@ pub async fn write_tail_logs(
                 if let Some(file) = file.as_mut() {
                     file.write(&buffer[..n]).await?;
                 }
+                if let Some(sender) = sender.as_ref() {
+                    pending.extend_from_slice(&buffer[..n]);
+                    let content = take_utf8(&mut pending);
+                    if !content.is_empty() {
+                        let _ = sender.send(content);
+                    }
+                }
             }
             Err(e) => {
                 return Err(e.into());
@@ -80,3 +112,20 @@ pub async fn init_builder_logs(path: &PathBuf) -> Result<()>
     create_dir(&path).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::logs::take_utf8;
+
+    #[test]
+    fn test_take_utf8() {
+        let mut buffer = "build ✓".as_bytes().to_vec();
+        buffer.pop();
+        assert_eq!("build ", take_utf8(&mut buffer));
+        assert_eq!(2, buffer.len());
+
+        buffer.push(0x93);
+        assert_eq!("✓", take_utf8(&mut buffer));
+        assert!(buffer.is_empty());
+    }
+}
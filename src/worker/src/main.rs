@@ -16,6 +16,8 @@ use simplelog::{ColorChoice, CombinedLogger, Config as SimpleLogConfig, Terminal
 use tokio::sync::RwLock;
 use tokio::time::sleep;
 use crate::builder::bubblewrap::Bubblewrap;
+use crate::builder::pacman_cache::PacmanCache;
+use crate::builder::source_cache::SourceCache;
 use crate::models::config::Config;
 use crate::orchestrator::websocket::WebsocketClient;
 use crate::worker::State;
@@ -50,6 +52,8 @@ async fn main() {
 
     info!("Starting aur-build-worker with version {}", env!("CARGO_PKG_VERSION"));
 
+    PacmanCache::from_config(&config).init().await.unwrap();
+    SourceCache::from_config(&config).init().await.unwrap();
     let bubblewrap = Bubblewrap::from_config(&config);
     bubblewrap.create(config.force_base_sandbox_create).await.unwrap();
 
@@ -0,0 +1,62 @@
+use std::fmt;
+use anyhow::Result;
+use common::models::BuildErrorClass;
+
+/// Error of a build step tagged with its class, reported to the server to decide whether to retry the build.
+#[derive(Debug)]
+pub struct ClassifiedError {
+    pub class: BuildErrorClass,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for ClassifiedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for ClassifiedError {}
+
+pub trait Classify<T> {
+    fn classify(self, class: BuildErrorClass) -> Result<T>;
+}
+
+impl<T> Classify<T> for Result<T> {
+    fn classify(self, class: BuildErrorClass) -> Result<T> {
+        self.map_err(|error| {
+            if error.downcast_ref::<ClassifiedError>().is_some() {
+                return error;
+            }
+            anyhow::Error::new(ClassifiedError { class, error })
+        })
+    }
+}
+
+/// Returns the class of the first classified error in the chain, `Unknown` if there is none.
+pub fn get_error_class(error: &anyhow::Error) -> BuildErrorClass {
+    error.downcast_ref::<ClassifiedError>()
+        .map(|e| e.class)
+        .unwrap_or(BuildErrorClass::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context, Result};
+    use common::models::BuildErrorClass;
+    use crate::models::build_error::{get_error_class, Classify};
+
+    #[test]
+    fn classify_error() {
+        let res: Result<()> = Err(anyhow!("Connection refused"));
+        let error = res
+            .classify(BuildErrorClass::Network)
+            .classify(BuildErrorClass::Build)
+            .with_context(|| "Failed to update chroot")
+            .unwrap_err();
+
+        assert_eq!(BuildErrorClass::Network, get_error_class(&error));
+        assert_eq!("Failed to update chroot: Connection refused", format!("{:#}", error));
+
+        assert_eq!(BuildErrorClass::Unknown, get_error_class(&anyhow!("Other")));
+    }
+}
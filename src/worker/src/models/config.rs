@@ -1,10 +1,12 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::path::PathBuf;
 use clap::builder::TypedValueParser;
 
 use clap::Parser;
 use log::LevelFilter;
 use serde::Deserialize;
+use common::models::{BuildEnvironment, ResourceLimits, WorkerLabels};
+use crate::builder::bubblewrap::SandboxBackend;
 
 #[derive(Deserialize, Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -38,6 +40,24 @@ pub struct SharedConfig {
     /// Path to the directory where the sandbox will be stored. Default: './worker/sandbox'
     #[clap(short = 's', long, value_hint = clap::ValueHint::DirPath)]
     pub sandbox_path: Option<PathBuf>,
+    /// How build environments are created from the base sandbox. Default: 'copy'
+    #[clap(long)]
+    pub sandbox_backend: Option<SandboxBackend>,
+    /// Path to the directory caching the packages downloaded by pacman, shared by all builds. Default: './worker/pacman_cache'
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    pub pacman_cache_path: Option<PathBuf>,
+    /// Size in GiB above which the oldest packages are removed from the pacman cache, 0 to never prune. Default: '10'
+    #[clap(long)]
+    pub pacman_cache_max_size: Option<u64>,
+    /// Path to the directory caching the sources downloaded by makepkg (SRCDEST), shared by all builds. Default: './worker/source_cache'
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    pub source_cache_path: Option<PathBuf>,
+    /// Size in GiB above which the least recently used sources are removed from the source cache, 0 to never prune. Default: '20'
+    #[clap(long)]
+    pub source_cache_max_size: Option<u64>,
+    /// Path to the directory keeping the ccache and sccache caches of the packages enabling them. Default: './worker/compiler_cache'
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    pub compiler_cache_path: Option<PathBuf>,
     /// Path to the directory where build logs will be stored. Default: './worker/logs'
     #[clap(short = 'l', long, value_hint = clap::ValueHint::DirPath)]
     pub build_logs_path: Option<PathBuf>,
@@ -52,6 +72,47 @@ pub struct SharedConfig {
     #[clap(short = 'k', long)]
     pub api_key: Option<String>,
 
+    /// Architecture advertised to the server. Default: architecture of the worker
+    #[clap(long)]
+    pub arch: Option<String>,
+    /// Disk space in GiB available for builds, advertised to the server
+    #[clap(long)]
+    pub max_disk: Option<u64>,
+    /// Comma separated custom tags advertised to the server, packages can require them
+    #[clap(long, value_delimiter = ',')]
+    pub tags: Option<Vec<String>>,
+
+    /// Path of the cgroup v2 delegated to the worker, required to enforce the CPU, memory and pids limits. Example: '/sys/fs/cgroup/system.slice/aur-build-worker.service'
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    pub cgroup_path: Option<PathBuf>,
+    /// Amount of CPUs a build may use, packages can override it. Default: unlimited
+    #[clap(long)]
+    pub limit_cpus: Option<f64>,
+    /// Memory in MiB a build may use, packages can override it. Default: unlimited
+    #[clap(long)]
+    pub limit_memory: Option<u64>,
+    /// Amount of processes a build may run at once, packages can override it. Default: unlimited
+    #[clap(long)]
+    pub limit_pids: Option<u64>,
+    /// Disk space in GiB a build may use in its sandbox and the sources cache, packages can override it. Default: unlimited
+    #[clap(long)]
+    pub limit_disk: Option<u64>,
+
+    /// Download the sources with network access then run build() and package() without it, packages can opt out. Default: 'false'
+    #[clap(long)]
+    pub isolate_network: Option<bool>,
+
+    /// Install the AUR dependencies already built by the server from its repository instead of building them. Default: 'true'
+    #[clap(long)]
+    pub use_server_repo: Option<bool>,
+    /// Base URL of the AUR RPC used to find dependencies and their versions. Default: 'https://aur.archlinux.org/rpc'
+    #[clap(long)]
+    pub aur_rpc_url: Option<String>,
+
+    /// makepkg settings and environment variables of all builds, packages can override them. Only read from the configuration file
+    #[clap(skip)]
+    pub build_env: Option<BuildEnvironment>,
+
     /// Should the worker rebuild its sandbox from scratch at startup. Default 'false'
     #[clap(short = 'f', long)]
     pub force_base_sandbox_create: Option<bool>,
@@ -76,12 +137,28 @@ pub struct Config {
 
     pub data_path: PathBuf,
     pub sandbox_path: PathBuf,
+    pub sandbox_backend: SandboxBackend,
+    pub pacman_cache_path: PathBuf,
+    pub pacman_cache_max_size: u64,
+    pub source_cache_path: PathBuf,
+    pub source_cache_max_size: u64,
+    pub compiler_cache_path: PathBuf,
+    pub build_env: BuildEnvironment,
+    pub cgroup_path: Option<PathBuf>,
+    pub limits: ResourceLimits,
+    pub isolate_network: bool,
+    pub use_server_repo: bool,
+    pub aur_rpc_url: String,
     pub build_logs_path: PathBuf,
 
     pub base_url: String,
     pub base_url_ws: String,
     pub api_key: String,
 
+    pub arch: String,
+    pub max_disk: Option<u64>,
+    pub tags: Vec<String>,
+
     pub force_base_sandbox_create: bool,
 }
 
@@ -101,15 +178,86 @@ impl Config {
 
             data_path: cli_config.data_path.unwrap_or(file_config.data_path.unwrap_or(PathBuf::from("./worker/data"))),
             sandbox_path: cli_config.sandbox_path.unwrap_or(file_config.sandbox_path.unwrap_or(PathBuf::from("./worker/sandbox"))),
+            sandbox_backend: cli_config.sandbox_backend.unwrap_or(file_config.sandbox_backend.unwrap_or_default()),
+            pacman_cache_path: cli_config.pacman_cache_path.unwrap_or(file_config.pacman_cache_path.unwrap_or(PathBuf::from("./worker/pacman_cache"))),
+            pacman_cache_max_size: cli_config.pacman_cache_max_size.unwrap_or(file_config.pacman_cache_max_size.unwrap_or(10)),
+            source_cache_path: cli_config.source_cache_path.unwrap_or(file_config.source_cache_path.unwrap_or(PathBuf::from("./worker/source_cache"))),
+            source_cache_max_size: cli_config.source_cache_max_size.unwrap_or(file_config.source_cache_max_size.unwrap_or(20)),
+            compiler_cache_path: cli_config.compiler_cache_path.unwrap_or(file_config.compiler_cache_path.unwrap_or(PathBuf::from("./worker/compiler_cache"))),
+            build_env: file_config.build_env.unwrap_or_default(),
+            cgroup_path: cli_config.cgroup_path.or(file_config.cgroup_path),
+            limits: ResourceLimits {
+                cpus: cli_config.limit_cpus.or(file_config.limit_cpus),
+                memory: cli_config.limit_memory.or(file_config.limit_memory),
+                pids: cli_config.limit_pids.or(file_config.limit_pids),
+                disk: cli_config.limit_disk.or(file_config.limit_disk),
+            },
+            isolate_network: cli_config.isolate_network.unwrap_or(file_config.isolate_network.unwrap_or(false)),
+            use_server_repo: cli_config.use_server_repo.unwrap_or(file_config.use_server_repo.unwrap_or(true)),
+            aur_rpc_url: cli_config.aur_rpc_url.unwrap_or(file_config.aur_rpc_url.unwrap_or(String::from("https://aur.archlinux.org/rpc"))),
             build_logs_path: cli_config.build_logs_path.unwrap_or(file_config.build_logs_path.unwrap_or(PathBuf::from("./worker/logs"))),
 
             base_url: cli_config.base_url.unwrap_or(file_config.base_url.unwrap()),
             base_url_ws: cli_config.base_url_ws.unwrap_or(file_config.base_url_ws.unwrap()),
             api_key: cli_config.api_key.unwrap_or(file_config.api_key.unwrap()),
 
+            arch: cli_config.arch.unwrap_or(file_config.arch.unwrap_or(std::env::consts::ARCH.to_string())),
+            max_disk: cli_config.max_disk.or(file_config.max_disk),
+            tags: cli_config.tags.unwrap_or(file_config.tags.unwrap_or_default()),
+
             force_base_sandbox_create: cli_config.force_base_sandbox_create.unwrap_or(file_config.force_base_sandbox_create.unwrap_or(false)),
         };
 
+        let limits = &config.limits;
+        if config.cgroup_path.is_none() && (limits.cpus.is_some() || limits.memory.is_some() || limits.pids.is_some()) {
+            bail!("CPU, memory and pids limits require a cgroup_path to be enforced");
+        }
+
         Ok(config)
     }
-}
\ No newline at end of file
+
+    /// Labels advertised to the server to match the worker with packages.
+    pub fn get_labels(&self) -> WorkerLabels {
+        WorkerLabels {
+            arch: self.arch.clone(),
+            cpus: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            max_disk: self.max_disk,
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// Configuration of the tests, storing everything in the `./test` directory.
+    pub fn for_tests() -> Config {
+        Config {
+            log_level: LevelFilter::Off,
+            log_path: PathBuf::from("./test/worker.log"),
+            pacman_config_path: PathBuf::from("../../config/pacman.conf"),
+            pacman_mirrorlist_path: PathBuf::from("/etc/pacman.d/mirrorlist"),
+            arch: "x86_64".to_string(),
+            max_disk: None,
+            tags: vec![],
+            force_base_sandbox_create: false,
+            data_path: PathBuf::from("./test/data"),
+            sandbox_path: PathBuf::from("./test/sandbox"),
+            sandbox_backend: SandboxBackend::Copy,
+            pacman_cache_path: PathBuf::from("./test/pacman_cache"),
+            pacman_cache_max_size: 10,
+            source_cache_path: PathBuf::from("./test/source_cache"),
+            source_cache_max_size: 20,
+            compiler_cache_path: PathBuf::from("./test/compiler_cache"),
+            build_env: Default::default(),
+            cgroup_path: None,
+            limits: Default::default(),
+            isolate_network: false,
+            use_server_repo: false,
+            aur_rpc_url: "https://aur.archlinux.org/rpc".to_string(),
+            build_logs_path: PathBuf::from("./test/build_logs"),
+            base_url: "".to_string(),
+            base_url_ws: "".to_string(),
+            api_key: "".to_string(),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod build_error;
 pub mod package_build_result;
 pub mod config;
 
@@ -1,14 +1,18 @@
 #[derive(Clone, Debug)]
 pub struct PackageBuildResult {
     pub built: bool,
+    pub package_base: String,
     pub version: String,
+    pub dependencies: Vec<String>,
 }
 
 impl PackageBuildResult {
-    pub fn new(built: bool, version: String) -> PackageBuildResult {
+    pub fn new(built: bool, package_base: String, version: String, dependencies: Vec<String>) -> PackageBuildResult {
         PackageBuildResult {
             built,
+            package_base,
             version,
+            dependencies,
         }
     }
 }
@@ -1,7 +1,13 @@
 use anyhow::{Context, Result};
-use log::{error, info};
+use log::{error, info, warn};
 use reqwest::multipart::Form;
+use std::fs::create_dir_all;
+use std::path::Path;
+use flate2::read::GzDecoder;
+use tar::Archive;
 use tokio::fs::{read_dir};
+use common::models::CompilerCacheStats;
+use crate::models::build_error::get_error_class;
 use crate::models::config::Config;
 use crate::models::package_build_result::PackageBuildResult;
 use crate::utils::get_package_dir_entries;
@@ -44,19 +50,34 @@ impl HttpClient {
         Ok(form)
     }
 
-    async fn build_form(&self, package_name: &String, build_result: Result<PackageBuildResult>) -> Result<Form> {
+    async fn build_form(
+        &self,
+        package_name: &String,
+        build_result: Result<PackageBuildResult>,
+        compiler_cache: Option<CompilerCacheStats>,
+    ) -> Result<Form> {
         let mut form = Form::new()
             .text("package_name", package_name.clone());
 
+        if let Some(stats) = compiler_cache {
+            form = form.text("compiler_cache_hits", stats.hits.to_string())
+                .text("compiler_cache_misses", stats.misses.to_string());
+        }
+
         form = match build_result {
             Ok(result) => {
                 if result.built {
                     form = self.add_package_files_to_form_data(form).await.with_context(|| "Failed to add packages files to form")?;
                 }
-                form.text("version", result.version)
+                for dependency in result.dependencies {
+                    form = form.text("dependencies", dependency);
+                }
+                form.text("package_base", result.package_base)
+                    .text("version", result.version)
             }
             Err(e) => {
-                form.text("error", format!("{:#}", e))
+                form.text("error_class", get_error_class(&e).as_str())
+                    .text("error", format!("{:#}", e))
             }
         };
 
@@ -66,13 +87,63 @@ impl HttpClient {
     }
 
 
+    /// Url of the given repository served by the server for the architecture of the worker, None if it has no database yet.
+    pub async fn get_repository_url(&self, repo_name: &str) -> Option<String>
+    {
+        let url = format!("{}/repo/{}/os/{}", self.config.base_url, repo_name, self.config.arch);
+        match reqwest::Client::new().head(format!("{}/{}.db", url, repo_name)).send().await {
+            Ok(response) if response.status().is_success() => Some(url),
+            Ok(response) => {
+                info!("Repository {} of the server is not available, status {}", repo_name, response.status());
+                None
+            }
+            Err(e) => {
+                warn!("Could not reach repository {} of the server: {}", repo_name, e);
+                None
+            }
+        }
+    }
+
+    /// Public key the repository of the server is signed with, `None` when the server does not sign it.
+    pub async fn get_repository_key(&self) -> Result<Option<String>>
+    {
+        let response = reqwest::Client::new()
+            .get(format!("{}/api_workers/repository/key", self.config.base_url))
+            .header("Authorization", &self.config.api_key)
+            .send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.text().await?))
+    }
+
+    /// Downloads an uploaded source tarball from the server and extracts it into the given directory.
+    pub async fn download_source(&self, file: &str, destination: &Path) -> Result<()>
+    {
+        let content = reqwest::Client::new()
+            .get(format!("{}/api_workers/sources/{}", self.config.base_url, file))
+            .header("Authorization", &self.config.api_key)
+            .send().await?
+            .error_for_status()?
+            .bytes().await?;
+
+        let destination = destination.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            create_dir_all(&destination)?;
+            Archive::new(GzDecoder::new(content.as_ref())).unpack(&destination)
+        }).await??;
+
+        Ok(())
+    }
+
     pub async fn upload_packages(
         &self,
         package_name: &String,
-        build_result: Result<PackageBuildResult>
+        build_result: Result<PackageBuildResult>,
+        compiler_cache: Option<CompilerCacheStats>,
     ) -> Result<()>
     {
-        let form = self.build_form(package_name, build_result).await?;
+        let form = self.build_form(package_name, build_result, compiler_cache).await?;
 
         let res = reqwest::Client::new()
             .post(format!("{}/api_workers/upload", self.config.base_url))
@@ -13,8 +13,10 @@ use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use common::messages::WebsocketMessage;
-use common::models::{PackageJob, WorkerStatus};
+use common::models::{PackageJob, WorkerLabels, WorkerStatus};
+use crate::builder::bubblewrap::Bubblewrap;
 use crate::builder::Builder;
+use crate::builder::utils::post_build_clean;
 use crate::models::config::Config;
 use crate::worker::State;
 
@@ -69,9 +71,11 @@ async fn handle_job_submit(package_job: &PackageJob, state: &Arc<RwLock<State>>)
 
     let monitor_handle = tokio::task::spawn(async move {
         let (tx, mut rx) = mpsc::channel(1);
+        let (tx_logs, mut rx_logs) = mpsc::unbounded_channel();
 
         let builder = Builder::new(
             tx,
+            tx_logs,
             http_client,
             job,
             &config
@@ -79,27 +83,44 @@ async fn handle_job_submit(package_job: &PackageJob, state: &Arc<RwLock<State>>)
         let handle = tokio::task::spawn(async move {
             builder.process_package().await
         });
+        cloned_state.write().await.builder_handle = Some(handle.abort_handle());
 
-        while let Some(msg) = rx.recv().await {
-            {
-                let _ = cloned_state.write().await.set_status(msg); // TODO: handle ?
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => {
+                        let _ = cloned_state.write().await.set_status(msg); // TODO: handle ?
+                    }
+                    None => break,
+                },
+                Some(content) = rx_logs.recv() => {
+                    let _ = cloned_state.read().await.push_logs(content);
+                }
             }
         }
         info!("State receiver was closed");
+        while let Ok(content) = rx_logs.try_recv() {
+            let _ = cloned_state.read().await.push_logs(content);
+        }
 
         if !handle.is_finished() {
             handle.abort();
             info!("Builder task aborted");
-        } else {
-            let res = handle.await;
-            match res {
-                Ok(res) => {
-                    info!("Builder task finished with {:?}", res);
-                }
-                Err(e) => {
-                    error!("Failed to join on builder thread: {}", e);
+        }
+        match handle.await {
+            Ok(res) => {
+                info!("Builder task finished with {:?}", res);
+            }
+            Err(e) if e.is_cancelled() => {
+                info!("Builder task was cancelled, cleaning sandbox");
+                let _ = cloned_state.write().await.set_status(WorkerStatus::CLEANING);
+                if let Err(e) = clean_cancelled_job(&config).await {
+                    error!("Failed to clean after cancelled job: {}", e);
                 }
             }
+            Err(e) => {
+                error!("Failed to join on builder thread: {}", e);
+            }
         }
 
         let _ = cloned_state.write().await.clear_job(); // TODO: handle ?
@@ -110,11 +131,33 @@ async fn handle_job_submit(package_job: &PackageJob, state: &Arc<RwLock<State>>)
     Ok(())
 }
 
+async fn clean_cancelled_job(config: &Config) -> Result<()> {
+    Bubblewrap::from_config(config).delete("current").await?;
+    post_build_clean(&config.data_path).await
+}
+
+async fn handle_job_cancel(package_id: i32, state: &Arc<RwLock<State>>) -> Result<()> {
+    let state = state.read().await;
+    if state.current_job.as_ref().is_none_or(|job| job.definition.package_id != package_id) {
+        warn!("Received cancel for package {} which is not the current job", package_id);
+        return Ok(());
+    }
+
+    if let Some(handle) = state.builder_handle.as_ref() {
+        info!("Cancelling job for package {}", package_id);
+        handle.abort();
+    }
+    Ok(())
+}
+
 async fn handle_message(message: &WebsocketMessage, state: &Arc<RwLock<State>>) -> Result<()> {
     match message {
         WebsocketMessage::JobSubmit { package} => {
             handle_job_submit(package, state).await?;
         }
+        WebsocketMessage::JobCancel { package_id } => {
+            handle_job_cancel(*package_id, state).await?;
+        }
         WebsocketMessage::WorkerStatusRequest { .. } => {
             state.write().await.push_state()?;
         }
@@ -128,6 +171,7 @@ async fn handle_message(message: &WebsocketMessage, state: &Arc<RwLock<State>>)
 pub struct WebsocketClient {
     url: String,
     api_key: String,
+    labels: WorkerLabels,
 
     pub state: Arc<RwLock<State>>,
 }
@@ -138,6 +182,7 @@ impl WebsocketClient {
         WebsocketClient {
             url: format!("{}/api_workers/ws", config.base_url_ws),
             api_key: config.api_key.clone(),
+            labels: config.get_labels(),
 
             state,
         }
@@ -160,6 +205,7 @@ impl WebsocketClient {
         tx.send(WebsocketMessage::WorkerHello {
             version: env!("CARGO_PKG_VERSION").to_string(),
             status: WorkerStatus::STANDBY,
+            labels: self.labels.clone(),
         }).with_context(|| "Failed to send authenticate message")?;
 
         self.state.write().await.sender = Some(tx);
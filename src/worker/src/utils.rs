@@ -34,7 +34,8 @@ pub async fn get_package_dir_entries(path: impl AsRef<Path>) -> Result<Vec<DirEn
     let mut dir = read_dir(path).await?;
 
     while let Some(entry) = dir.next_entry().await? {
-        if entry.file_name().to_str().unwrap().ends_with(".pkg.tar.zst") {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.contains(".pkg.tar") && !name.ends_with(".sig") {
             packages.push(entry);
         }
     }
@@ -55,11 +56,38 @@ pub async fn copy_dir_all(src: PathBuf, dst: PathBuf) -> Result<()> {
     Ok(())
 }
 
+/// Total size in bytes of the files under the given path, symbolic links are not followed.
+#[async_recursion]
+pub async fn get_path_size(path: PathBuf) -> Result<u64> {
+    let metadata = tokio::fs::symlink_metadata(&path).await?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    let mut dir = read_dir(path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        size += get_path_size(entry.path()).await?;
+    }
+    Ok(size)
+}
+
 // Temporary workaround while fixing copy_dir_all for some types of files
 pub async fn copy_dir(src: PathBuf, dst: PathBuf) -> Result<()>
+{
+    run_cp(vec!["-r"], src, dst).await
+}
+
+/// Copies the directory sharing its data blocks when the filesystem supports it.
+pub async fn reflink_dir(src: PathBuf, dst: PathBuf) -> Result<()>
+{
+    run_cp(vec!["-r", "--reflink=auto"], src, dst).await
+}
+
+async fn run_cp(args: Vec<&str>, src: PathBuf, dst: PathBuf) -> Result<()>
 {
     let mut command = Command::new("cp");
-    command.arg("-r");
+    command.args(args);
     command.arg(src);
     command.arg(dst);
     let out = command.output().await?;
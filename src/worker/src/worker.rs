@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use tokio::sync::mpsc::{UnboundedSender};
-use tokio::task::JoinHandle;
+use tokio::task::{AbortHandle, JoinHandle};
 use common::messages::{WebsocketMessage};
 use common::models::{PackageJob, WorkerStatus};
 use crate::models::config::Config;
@@ -11,6 +11,7 @@ pub struct State {
 
     pub current_job: Option<PackageJob>,
     pub monitor_handle: Option<JoinHandle<()>>,
+    pub builder_handle: Option<AbortHandle>,
     pub status: WorkerStatus,
 
     pub sender: Option<UnboundedSender<WebsocketMessage>>,
@@ -22,6 +23,7 @@ impl State {
             config: config.clone(),
             current_job: None,
             monitor_handle: None,
+            builder_handle: None,
             status: WorkerStatus::STANDBY,
 
             sender: None
@@ -32,6 +34,7 @@ impl State {
     {
         self.status = WorkerStatus::STANDBY;
         self.current_job = None;
+        self.builder_handle = None;
         self.push_state()
     }
 
@@ -52,6 +55,18 @@ impl State {
         Ok(())
     }
 
+    /// Streams build logs of the current job to the server.
+    pub fn push_logs(&self, content: String) -> Result<()>
+    {
+        if let (Some(sender), Some(job)) = (self.sender.as_ref(), self.current_job.as_ref()) {
+            sender.send(WebsocketMessage::BuildLogs {
+                package_id: job.definition.package_id,
+                content,
+            }).with_context(|| "Failed to send message via sender".to_string())?;
+        }
+        Ok(())
+    }
+
     pub fn get_http_client(&self) -> HttpClient
     {
         HttpClient::from_config(&self.config)